@@ -0,0 +1,28 @@
+use serde_json::Value;
+
+const SIMPLE_STATUS: &[u8] = include_bytes!("../../tests/fixtures/simple_fdb.json");
+const PROCESSES_COUNT: usize = 500;
+
+/// Build a status similar to a large cluster by duplicating processes of the
+/// sample status until reaching [PROCESSES_COUNT]. Each copy gets its own
+/// process id, address and machine, so per-process series don't collapse
+/// into the few of the sample status.
+pub fn large_status() -> Vec<u8> {
+    let mut status: Value = serde_json::from_slice(SIMPLE_STATUS).unwrap();
+    let processes = status["cluster"]["processes"].as_object_mut().unwrap();
+    let templates: Vec<Value> = processes.values().cloned().collect();
+    processes.clear();
+    for i in 0..PROCESSES_COUNT {
+        let process_id = format!("{:032x}", i);
+        let machine_id = format!("machine-{:03}", i);
+        let mut process = templates[i % templates.len()].clone();
+        process["address"] = Value::from(format!("10.0.{}.{}:4500", i / 256, i % 256));
+        process["machine_id"] = Value::from(machine_id.as_str());
+        process["fault_domain"] = Value::from(machine_id.as_str());
+        process["locality"]["machineid"] = Value::from(machine_id.as_str());
+        process["locality"]["zoneid"] = Value::from(machine_id.as_str());
+        process["locality"]["processid"] = Value::from(process_id.as_str());
+        processes.insert(process_id, process);
+    }
+    serde_json::to_vec(&status).unwrap()
+}
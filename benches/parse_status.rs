@@ -0,0 +1,23 @@
+mod common;
+
+use common::large_status;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use fdbexporter::{parse_cluster_status, Status};
+
+fn parse_status(c: &mut Criterion) {
+    let raw = large_status();
+
+    c.bench_function("serde_json (500 processes)", |b| {
+        b.iter(|| {
+            let de = &mut serde_json::Deserializer::from_slice(black_box(&raw));
+            let status: Status = serde_path_to_error::deserialize(de).unwrap();
+            status
+        })
+    });
+    c.bench_function("parse_cluster_status (500 processes)", |b| {
+        b.iter(|| parse_cluster_status(black_box(&raw)).unwrap())
+    });
+}
+
+criterion_group!(benches, parse_status);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+mod common;
+
+use common::large_status;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use fdbexporter::{parse_cluster_status, Exporter, MetricsOptions};
+
+fn bench_process_metrics(c: &mut Criterion) {
+    let raw = large_status();
+    let exporter = Exporter::new();
+    let options = MetricsOptions {
+        cluster_name: String::from("bench"),
+        ..Default::default()
+    };
+
+    c.bench_function("process_metrics (500 processes)", |b| {
+        b.iter_batched(
+            || parse_cluster_status(&raw).unwrap(),
+            |status| exporter.process_metrics(&status, &options),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_process_metrics);
+criterion_main!(benches);
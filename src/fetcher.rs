@@ -1,9 +1,28 @@
-use std::{path::Path, time::Duration};
+use std::{
+    future::Future,
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
 
-use foundationdb::{options::TransactionOption, Database, FdbBindingError, FdbError};
-use tracing::error;
+use foundationdb::{
+    options::{StreamingMode, TransactionOption},
+    Database, FdbBindingError, FdbError, RangeOption,
+};
+use tracing::{debug, error, warn};
 
-use crate::status_models::Status;
+use crate::status_models::{cluster_data::ShardStats, lenient, Status};
+
+/// Special key at which the status JSON is stored
+pub const STATUS_KEY: &[u8] = b"\xff\xff/status/json";
+
+/// Prefix of the special keys holding the size of each shard, read by
+/// [fetch_shard_sizes]
+pub const SHARD_STATS_PREFIX: &[u8] = b"\xff\xff/metrics/data_distribution_stats/";
+
+/// FoundationDB error code of `transaction_timed_out`
+const TRANSACTION_TIMED_OUT: i32 = 1031;
 
 /// Errors that can occur when fetching cluster status
 #[derive(Debug)]
@@ -14,10 +33,19 @@ pub enum FetchError {
     Fdb(FdbError),
     /// Error from FoundationDB binding operations
     FdbBinding(FdbBindingError),
+    /// Error when the status read exceeds the transaction timeout
+    TransactionTimedOut(FdbError),
     /// Error when the status key is not found
     StatusNotFound,
+    /// Error when the status key exists but its value is empty
+    EmptyStatus,
     /// Error when the requested timeout is too large
     TimeoutTooLarge(u128),
+    /// Error when `fdbcli` can't be run or exits with a failure
+    Fdbcli(std::io::Error),
+    /// Error when the whole fetch, database opening included, exceeds the
+    /// given duration
+    Timeout(Duration),
 }
 
 impl std::fmt::Display for FetchError {
@@ -26,7 +54,11 @@ impl std::fmt::Display for FetchError {
             FetchError::Parsing(e) => write!(f, "Failed to parse status JSON: {}", e),
             FetchError::Fdb(e) => write!(f, "FoundationDB error: {}", e),
             FetchError::FdbBinding(e) => write!(f, "FoundationDB binding error: {}", e),
+            FetchError::TransactionTimedOut(e) => {
+                write!(f, "FoundationDB status read timed out: {}", e)
+            }
             FetchError::StatusNotFound => write!(f, "Status key not found in FoundationDB"),
+            FetchError::EmptyStatus => write!(f, "Status key has an empty value"),
             FetchError::TimeoutTooLarge(ms) => {
                 write!(
                     f,
@@ -35,6 +67,10 @@ impl std::fmt::Display for FetchError {
                     i32::MAX
                 )
             }
+            FetchError::Fdbcli(e) => write!(f, "fdbcli error: {}", e),
+            FetchError::Timeout(duration) => {
+                write!(f, "Status fetch didn't complete within {:?}", duration)
+            }
         }
     }
 }
@@ -45,21 +81,34 @@ impl std::error::Error for FetchError {
             FetchError::Parsing(e) => Some(e),
             FetchError::Fdb(e) => Some(e),
             FetchError::FdbBinding(e) => Some(e),
+            FetchError::TransactionTimedOut(e) => Some(e),
             FetchError::StatusNotFound => None,
+            FetchError::EmptyStatus => None,
             FetchError::TimeoutTooLarge(_) => None,
+            FetchError::Fdbcli(e) => Some(e),
+            FetchError::Timeout(_) => None,
         }
     }
 }
 
 impl From<FdbError> for FetchError {
     fn from(e: FdbError) -> Self {
+        if e.code() == TRANSACTION_TIMED_OUT {
+            return FetchError::TransactionTimedOut(e);
+        }
         FetchError::Fdb(e)
     }
 }
 
+/// Errors carrying an FDB code, e.g. the last error once the retry limit is
+/// reached, are cluster errors, [FetchError::FdbBinding] is kept for misuses
+/// of the bindings
 impl From<FdbBindingError> for FetchError {
     fn from(e: FdbBindingError) -> Self {
-        FetchError::FdbBinding(e)
+        match e.get_fdb_error() {
+            Some(fdb_error) => FetchError::from(fdb_error),
+            None => FetchError::FdbBinding(e),
+        }
     }
 }
 
@@ -97,6 +146,79 @@ pub async fn fetch_cluster_status(
     cluster_file: Option<&Path>,
     timeout_duration: Duration,
 ) -> Result<Status, FetchError> {
+    fetch_cluster_status_timed(cluster_file, timeout_duration)
+        .await
+        .0
+}
+
+/// Same as [fetch_cluster_status], also returning the time spent reading and
+/// parsing the status, see [crate::Exporter::set_fetch_timings].
+pub async fn fetch_cluster_status_timed(
+    cluster_file: Option<&Path>,
+    timeout_duration: Duration,
+) -> (Result<Status, FetchError>, FetchTimings) {
+    let mut timings = FetchTimings::default();
+    let status = async {
+        let db = open_database(cluster_file)?;
+        let json_bytes = timings
+            .time_io(fetch_raw_status(&db, timeout_duration))
+            .await?;
+        timings.time_parse(&json_bytes, false)
+    }
+    .await;
+    (status, timings)
+}
+
+/// Time spent in each step of a status fetch, a step which didn't run is None.
+///
+/// Steps are timed whether they succeed or not, a read ending in a timeout is
+/// the one worth knowing about.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FetchTimings {
+    /// Reading the raw status, retries included, without opening the database
+    pub io: Option<Duration>,
+    /// Parsing the raw status
+    pub parse: Option<Duration>,
+}
+
+impl FetchTimings {
+    /// Await `read` (e.g. [fetch_raw_status] or [fetch_raw_status_fdbcli] on
+    /// a blocking thread) and record the time it took as [FetchTimings::io]
+    pub async fn time_io<T>(&mut self, read: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let output = read.await;
+        self.io = Some(started.elapsed());
+        output
+    }
+
+    /// Parse the raw status like [parse_cluster_status], or
+    /// [parse_cluster_status_lenient] when `lenient`, and record the time it
+    /// took as [FetchTimings::parse]
+    pub fn time_parse(&mut self, json_bytes: &[u8], lenient: bool) -> Result<Status, FetchError> {
+        let started = Instant::now();
+        let status = parse_status(json_bytes, lenient);
+        self.parse = Some(started.elapsed());
+        status
+    }
+}
+
+/// Fetches the raw JSON bytes stored in the system key `\xff\xff/status/json`,
+/// without parsing them.
+///
+/// Useful when the raw document must be kept around (e.g. for debugging), use
+/// [parse_cluster_status] to turn it into a [Status] afterwards.
+pub async fn fetch_raw_cluster_status(
+    cluster_file: Option<&Path>,
+    timeout_duration: Duration,
+) -> Result<Vec<u8>, FetchError> {
+    let db = open_database(cluster_file)?;
+    fetch_raw_status(&db, timeout_duration).await
+}
+
+/// Opens a [Database] from the given cluster file, or from the default one if
+/// None. The handle can be kept to fetch the status repeatedly with
+/// [fetch_raw_status].
+pub fn open_database(cluster_file: Option<&Path>) -> Result<Database, FetchError> {
     let db = if let Some(path) = cluster_file {
         let path_str = path.to_str().ok_or_else(|| {
             // Create a custom error for invalid path
@@ -109,36 +231,332 @@ pub async fn fetch_cluster_status(
     } else {
         Database::default()?
     };
+    Ok(db)
+}
 
+/// Time the first read version of a freshly opened [Database] takes, which
+/// includes reaching the coordinators to find the cluster controller and
+/// proxies. Calling it before the first [fetch_raw_status] tells connection
+/// latency apart from the time the cluster takes to generate its status.
+pub async fn connect_database(
+    db: &Database,
+    timeout_duration: Duration,
+) -> Result<Duration, FetchError> {
     let timeout_millis = timeout_duration
         .as_millis()
         .try_into()
         .map_err(|_| FetchError::TimeoutTooLarge(timeout_duration.as_millis()))?;
+    let start = Instant::now();
+    db.run(|trx, _maybe_committed| async move {
+        trx.set_option(TransactionOption::Timeout(timeout_millis))?;
+        trx.get_read_version().await?;
+        Ok(())
+    })
+    .await?;
+    Ok(start.elapsed())
+}
+
+/// Same as [fetch_raw_cluster_status] using an already opened [Database].
+pub async fn fetch_raw_status(
+    db: &Database,
+    timeout_duration: Duration,
+) -> Result<Vec<u8>, FetchError> {
+    fetch_raw_status_with_retry_limit(db, timeout_duration, None)
+        .await
+        .0
+}
+
+/// Same as [fetch_raw_status], giving up after `retry_limit` retries of the
+/// transaction instead of retrying until the timeout.
+///
+/// Returns the status along with the number of transaction attempts needed,
+/// see [crate::Exporter::observe_fetch_attempts].
+pub async fn fetch_raw_status_with_retry_limit(
+    db: &Database,
+    timeout_duration: Duration,
+    retry_limit: Option<i32>,
+) -> (Result<Vec<u8>, FetchError>, u32) {
+    fetch_raw_status_at_key(db, STATUS_KEY, timeout_duration, retry_limit).await
+}
+
+/// Same as [fetch_raw_status_with_retry_limit], reading the status from
+/// `status_key` instead of [STATUS_KEY] (e.g. to test another special key).
+pub async fn fetch_raw_status_at_key(
+    db: &Database,
+    status_key: &[u8],
+    timeout_duration: Duration,
+    retry_limit: Option<i32>,
+) -> (Result<Vec<u8>, FetchError>, u32) {
+    let timeout_millis = match timeout_duration.as_millis().try_into() {
+        Ok(timeout_millis) => timeout_millis,
+        Err(_) => {
+            return (
+                Err(FetchError::TimeoutTooLarge(timeout_duration.as_millis())),
+                0,
+            )
+        }
+    };
 
     // Read the status JSON from the system key
+    let attempts = AtomicU32::new(0);
     let status_json = db
-        .run(|trx, _maybe_committed| async move {
-            // Set the option to read system keys
-            trx.set_option(TransactionOption::ReadSystemKeys)?;
-            trx.set_option(TransactionOption::Timeout(timeout_millis))?;
+        .run(|trx, _maybe_committed| {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            async move {
+                debug!("Reading status, attempt {}", attempt);
+
+                // Set the option to read system keys
+                trx.set_option(TransactionOption::ReadSystemKeys)?;
+                trx.set_option(TransactionOption::Timeout(timeout_millis))?;
+                if let Some(retry_limit) = retry_limit {
+                    trx.set_option(TransactionOption::RetryLimit(retry_limit))?;
+                }
+
+                // Read the key
+                let value = trx.get(status_key, false).await?;
+                if value.is_none() {
+                    // Tells a key missing at a given version, e.g. during a
+                    // recovery, from a key never written
+                    let version = trx.get_read_version().await?;
+                    warn!("Status key not found at read version {}", version);
+                }
+
+                Ok(value)
+            }
+        })
+        .await;
+    let status_json = status_json
+        .map_err(FetchError::from)
+        // Check if the key exists
+        .and_then(|value| value.ok_or(FetchError::StatusNotFound))
+        .map(|json_bytes| json_bytes.to_vec());
+
+    (status_json, attempts.into_inner())
+}
+
+/// Same as [fetch_raw_cluster_status], but the status is read by running
+/// `fdbcli --exec "status json"`, `fdbcli` must be in the `PATH`.
+///
+/// Useful where reading system keys from the client is not allowed while
+/// `fdbcli` works. The command blocks until `fdbcli` exits, which is bounded
+/// by `timeout_duration`.
+pub fn fetch_raw_status_fdbcli(
+    cluster_file: Option<&Path>,
+    timeout_duration: Duration,
+) -> Result<Vec<u8>, FetchError> {
+    let mut command = Command::new("fdbcli");
+    if let Some(path) = cluster_file {
+        command.arg("--cluster-file").arg(path);
+    }
+    // fdbcli only accepts whole seconds, and would not time out with 0
+    let timeout_secs = timeout_duration.as_secs().max(1);
+    let output = command
+        .arg("--timeout")
+        .arg(timeout_secs.to_string())
+        .arg("--exec")
+        .arg("status json")
+        .output()
+        .map_err(FetchError::Fdbcli)?;
 
-            // The status JSON is stored at the special key \xff\xff/status/json
-            let status_key = b"\xff\xff/status/json";
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("fdbcli failed with {}: {}", output.status, stderr.trim());
+        return Err(FetchError::Fdbcli(std::io::Error::other(format!(
+            "fdbcli exited with {}",
+            output.status
+        ))));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Reads the size in bytes of every shard from the data distribution special
+/// keys (`\xff\xff/metrics/data_distribution_stats/`), the status only
+/// describes shards through their count and average size.
+///
+/// Every shard is read, which takes several round trips on large clusters.
+pub async fn fetch_shard_sizes(
+    db: &Database,
+    timeout_duration: Duration,
+) -> Result<Vec<u64>, FetchError> {
+    let timeout_millis = timeout_duration
+        .as_millis()
+        .try_into()
+        .map_err(|_| FetchError::TimeoutTooLarge(timeout_duration.as_millis()))?;
+    let end = [SHARD_STATS_PREFIX, b"\xff"].concat();
 
-            // Read the key
-            let value = trx.get(status_key, false).await?;
+    let values = db
+        .run(|trx, _maybe_committed| {
+            let end = end.clone();
+            async move {
+                trx.set_option(TransactionOption::ReadSystemKeys)?;
+                trx.set_option(TransactionOption::Timeout(timeout_millis))?;
 
-            Ok(value)
+                let mut values = Vec::new();
+                let mut range = Some(RangeOption {
+                    mode: StreamingMode::WantAll,
+                    ..RangeOption::from((SHARD_STATS_PREFIX.to_vec(), end))
+                });
+                let mut iteration = 1;
+                while let Some(current) = range {
+                    let shards = trx.get_range(&current, iteration, false).await?;
+                    values.extend(shards.iter().map(|shard| shard.value().to_vec()));
+                    range = current.next_range(&shards);
+                    iteration += 1;
+                }
+                Ok(values)
+            }
         })
         .await?;
 
-    // Check if the key exists
-    let json_bytes = status_json.ok_or(FetchError::StatusNotFound)?;
+    values.iter().map(|value| parse_shard_size(value)).collect()
+}
+
+/// Parses the value of a data distribution special key, e.g.
+/// `{"shard_bytes": 1048576}`
+fn parse_shard_size(value: &[u8]) -> Result<u64, FetchError> {
+    let json_stats = &mut serde_json::Deserializer::from_slice(value);
+    serde_path_to_error::deserialize::<_, ShardStats>(json_stats)
+        .map(|stats| stats.shard_bytes)
+        .map_err(FetchError::Parsing)
+}
 
-    // Parse the JSON
-    let json_status = &mut serde_json::Deserializer::from_slice(&json_bytes);
-    serde_path_to_error::deserialize(json_status).map_err(|e| {
-        error!("Couldn't parse json: {}", e);
-        FetchError::Parsing(e)
-    })
+/// Parses a raw status JSON document into a [Status].
+///
+/// With the `simd-json` feature, the document is first parsed with `simd-json`.
+/// On failure it is parsed again with `serde_json` so errors still report the
+/// precise location of the invalid field.
+///
+/// An empty (or whitespace only) document is reported as
+/// [FetchError::EmptyStatus] rather than as a parsing error.
+pub fn parse_cluster_status(json_bytes: &[u8]) -> Result<Status, FetchError> {
+    parse_status(json_bytes, false)
+}
+
+/// Same as [parse_cluster_status], except that malformed or unexpected
+/// sub-objects (e.g. `cluster.qos` or a single process) are logged and
+/// skipped instead of failing the whole parse. Their number is reported in
+/// [Status::skipped_objects].
+pub fn parse_cluster_status_lenient(json_bytes: &[u8]) -> Result<Status, FetchError> {
+    parse_status(json_bytes, true)
+}
+
+fn parse_status(json_bytes: &[u8], lenient: bool) -> Result<Status, FetchError> {
+    if json_bytes.iter().all(u8::is_ascii_whitespace) {
+        error!("Status value is empty");
+        return Err(FetchError::EmptyStatus);
+    }
+
+    #[cfg(feature = "simd-json")]
+    {
+        // simd-json parses in place and needs its own mutable copy
+        let mut buffer = json_bytes.to_vec();
+        let (status, skipped_objects) = lenient::with_mode(lenient, || {
+            simd_json::serde::from_slice::<Status>(&mut buffer)
+        });
+        if let Ok(status) = status {
+            return Ok(Status {
+                skipped_objects,
+                ..status
+            });
+        }
+    }
+
+    let json_status = &mut serde_json::Deserializer::from_slice(json_bytes);
+    let (status, skipped_objects) = lenient::with_mode(lenient, || {
+        serde_path_to_error::deserialize::<_, Status>(json_status)
+    });
+    status
+        .map(|status| Status {
+            skipped_objects,
+            ..status
+        })
+        .map_err(|e| {
+            error!("Couldn't parse json: {}", e);
+            FetchError::Parsing(e)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_status_is_not_a_parsing_error() {
+        assert!(matches!(
+            parse_cluster_status(&[]),
+            Err(FetchError::EmptyStatus)
+        ));
+        assert!(matches!(
+            parse_cluster_status(b" \n"),
+            Err(FetchError::EmptyStatus)
+        ));
+        assert!(matches!(
+            parse_cluster_status(b"not json"),
+            Err(FetchError::Parsing(_))
+        ));
+    }
+
+    #[test]
+    fn shard_size() {
+        assert_eq!(
+            parse_shard_size(br#"{"shard_bytes": 1048576}"#).unwrap(),
+            1048576
+        );
+        assert!(matches!(
+            parse_shard_size(br#"{"shard_bytes": -1}"#),
+            Err(FetchError::Parsing(_))
+        ));
+    }
+
+    #[test]
+    fn failed_parse_is_timed() {
+        let mut timings = FetchTimings::default();
+        assert!(timings.time_parse(b"not json", false).is_err());
+        assert!(timings.parse.is_some());
+        assert_eq!(timings.io, None);
+    }
+
+    #[test]
+    fn lenient_parsing_skips_malformed_objects() {
+        let mut status: serde_json::Value =
+            serde_json::from_slice(include_bytes!("../tests/fixtures/simple_fdb.json")).unwrap();
+        status["cluster"]["qos"] = serde_json::json!({"unexpected": true});
+        let raw = serde_json::to_vec(&status).unwrap();
+
+        assert!(matches!(
+            parse_cluster_status(&raw),
+            Err(FetchError::Parsing(_))
+        ));
+        let status = parse_cluster_status_lenient(&raw).unwrap();
+        assert_eq!(status.skipped_objects, 1);
+        assert!(status.cluster.unwrap().qos.is_none());
+    }
+
+    #[test]
+    fn transaction_timed_out_is_routed() {
+        let timed_out = FdbError::from_code(TRANSACTION_TIMED_OUT);
+        let error = FetchError::from(FdbBindingError::NonRetryableFdbError(timed_out));
+        assert!(matches!(error, FetchError::TransactionTimedOut(_)));
+        assert!(std::error::Error::source(&error).is_some());
+        assert!(matches!(
+            FetchError::from(timed_out),
+            FetchError::TransactionTimedOut(_)
+        ));
+
+        // Returned once the retry limit is reached
+        let not_committed = FdbError::from_code(1020);
+        assert!(matches!(
+            FetchError::from(FdbBindingError::NonRetryableFdbError(not_committed)),
+            FetchError::Fdb(_)
+        ));
+        assert!(matches!(
+            FetchError::from(FdbBindingError::ReferenceToTransactionKept),
+            FetchError::FdbBinding(_)
+        ));
+        assert!(matches!(
+            FetchError::from(not_committed),
+            FetchError::Fdb(_)
+        ));
+    }
 }
@@ -8,7 +8,8 @@
 //! This library provides:
 //! - Direct access to FoundationDB cluster status via the system key `\xff\xff/status/json`
 //! - Parsing JSON status output into strongly-typed Rust structures
-//! - Converting status data into Prometheus metrics
+//! - Converting status data into Prometheus metrics, registered on the
+//!   [prometheus::Registry] of an [Exporter]
 //!
 //! ## Important Notes
 //!
@@ -18,7 +19,7 @@
 //! ## Example
 //!
 //! ```no_run
-//! use fdbexporter::{fetch_cluster_status, process_metrics};
+//! use fdbexporter::{fetch_cluster_status, Exporter, MetricsOptions};
 //! use std::{path::Path, time::Duration};
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
@@ -26,19 +27,23 @@
 //! let _guard = unsafe { foundationdb::boot() };
 //!
 //! let timeout = Duration::new(15, 0);
+//! let exporter = Exporter::new();
 //!
 //! // Fetch status using default cluster file
 //! match fetch_cluster_status(None, timeout).await {
-//!     Ok(status) => process_metrics(status),
+//!     Ok(status) => exporter.process_metrics(&status, &MetricsOptions::default()),
 //!     Err(e) => eprintln!("Failed to fetch status: {:?}", e),
 //! }
 //!
 //! // Or use a custom cluster file
 //! match fetch_cluster_status(Some(Path::new("/etc/foundationdb/fdb.cluster")), timeout).await {
-//!     Ok(status) => process_metrics(status),
+//!     Ok(status) => exporter.process_metrics(&status, &MetricsOptions::default()),
 //!     Err(e) => eprintln!("Failed to fetch status: {:?}", e),
 //! }
 //!
+//! // Metrics to encode, e.g. with prometheus::TextEncoder
+//! let metric_families = exporter.gather();
+//!
 //! // Clean shutdown
 //! drop(_guard);
 //! # Ok(())
@@ -51,6 +56,13 @@ pub mod metrics;
 pub mod status_models;
 
 // Re-export commonly used types and functions
-pub use fetcher::{fetch_cluster_status, FetchError};
-pub use metrics::{process_metrics, MetricsConvertible};
+pub use fetcher::{
+    connect_database, fetch_cluster_status, fetch_cluster_status_timed, fetch_raw_cluster_status,
+    fetch_raw_status, fetch_raw_status_at_key, fetch_raw_status_fdbcli,
+    fetch_raw_status_with_retry_limit, fetch_shard_sizes, open_database, parse_cluster_status,
+    parse_cluster_status_lenient, FetchError, FetchTimings, SHARD_STATS_PREFIX, STATUS_KEY,
+};
+pub use metrics::{
+    Exporter, HealthWeights, LastFetch, MetricGroup, MetricsConvertible, MetricsOptions,
+};
 pub use status_models::Status;
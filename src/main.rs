@@ -1,47 +1,439 @@
+use anyhow::Context;
 use bytes::Bytes;
-use clap::Parser;
-use fdbexporter::{fetch_cluster_status, process_metrics, FetchError, MetricsConvertible};
-use http_body_util::Full;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use fdbexporter::{
+    connect_database, fetch_raw_status_at_key, fetch_raw_status_fdbcli, fetch_shard_sizes,
+    open_database, parse_cluster_status, parse_cluster_status_lenient,
+    status_models::address::FdbProcessAddress, status_models::unparsed::unparsed_paths, Exporter,
+    FetchError, FetchTimings, HealthWeights, MetricGroup, MetricsOptions, Status, STATUS_KEY,
+};
+use foundationdb::{api::FdbApiBuilder, options::NetworkOption, Database};
+use http_body_util::{channel::Channel, channel::Sender, combinators::BoxBody, BodyExt, Full};
 use hyper::header::CONTENT_TYPE;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{Request, Response};
+use hyper::{Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
-use prometheus::{Encoder, TextEncoder};
+use prometheus::{Encoder, TextEncoder, TEXT_FORMAT};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
 
+use std::collections::HashMap;
 use std::convert::Infallible;
+use std::ffi::OsString;
+use std::future::Future;
+use std::io::{self, Write};
 use std::net::{IpAddr, SocketAddr};
-use std::num::ParseIntError;
-use std::path::PathBuf;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
 
 use tokio::{
-    net::TcpListener,
-    time::{sleep, Duration},
+    net::{TcpListener, TcpSocket},
+    runtime::Handle,
+    signal::unix::{signal, SignalKind},
+    task::JoinSet,
+    time::{sleep, timeout, Duration, Instant},
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
+use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+
+/// Below this number of series, metrics are encoded in a single buffer
+/// instead of being streamed
+const STREAMING_MIN_SERIES: usize = 1000;
+/// Size of chunks sent when streaming metrics
+const STREAMING_CHUNK_SIZE: usize = 64 * 1024;
+/// Number of chunks buffered before encoding waits for the client to read
+const STREAMING_CHANNEL_CAPACITY: usize = 4;
+
+/// Header of `/metrics` responses telling whether the last status fetch
+/// succeeded (`ok`) or not (`degraded`)
+const STATUS_HEADER: &str = "x-fdb-exporter-status";
+/// Header of `/metrics` responses with the number of seconds since the last
+/// successful status fetch, absent before the first one
+const DATA_AGE_HEADER: &str = "x-fdb-exporter-data-age";
+
+/// Maximum time given to in-flight connections to finish on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Highest `--tcp-backlog`, which the kernel caps anyway (e.g. with
+/// `net.core.somaxconn` on Linux)
+const MAX_TCP_BACKLOG: i64 = 65535;
+
+type ResponseBody = BoxBody<Bytes, Infallible>;
+
+/// Raw status JSON of the last fetch, shared between the fetcher and the HTTP server
+type StatusCache = Arc<RwLock<Option<Bytes>>>;
+
+#[derive(Debug, PartialEq)]
+enum StatusPathError {
+    /// Path is empty or contains an empty segment
+    InvalidPath,
+    /// Path does not match any subtree of the status
+    NotFound,
+    /// Raw status is not a valid JSON document
+    Parsing,
+}
+
+/// Settings of the routes served on every connection
+#[derive(Clone, Copy)]
+struct Routes {
+    debug_endpoints: bool,
+    lenient: bool,
+    max_response_size: Option<usize>,
+    /// End of the warmup, `/ready` fails until then
+    ready_at: Instant,
+}
+
+async fn serve(
+    req: Request<impl hyper::body::Body>,
+    cache: StatusCache,
+    exporter: Arc<Exporter>,
+    routes: Routes,
+    proxy: Option<StatusProxy>,
+) -> Result<Response<ResponseBody>, Infallible> {
+    match req.uri().path() {
+        "/status" => match proxy {
+            Some(proxy) => proxied_status(req, proxy, exporter).await,
+            None => status(req, cache).await,
+        },
+        "/ready" => Ok(ready(&exporter, routes.ready_at)),
+        "/status/parsed" if routes.debug_endpoints => parsed_status(cache, routes.lenient),
+        "/debug/status-schema" if routes.debug_endpoints => status_schema(cache),
+        _ => metrics(req, exporter, routes.max_response_size).await,
+    }
+}
+
+/// Serves `200` once the warmup is over and the last fetch succeeded, `503`
+/// otherwise
+fn ready(exporter: &Exporter, ready_at: Instant) -> Response<ResponseBody> {
+    if Instant::now() < ready_at {
+        text_response(StatusCode::SERVICE_UNAVAILABLE, "Warming up")
+    } else if !exporter.last_fetch().succeeded {
+        text_response(StatusCode::SERVICE_UNAVAILABLE, "Last fetch failed")
+    } else {
+        text_response(StatusCode::OK, "Ready")
+    }
+}
+
+/// Serves the paths of the last raw status which are not captured by the
+/// status model, as a JSON array
+fn status_schema(cache: StatusCache) -> Result<Response<ResponseBody>, Infallible> {
+    let raw = cache.read().expect("status cache lock poisoned").clone();
+    let Some(raw) = raw else {
+        return Ok(text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Status has not been fetched yet",
+        ));
+    };
+
+    let paths = match unparsed_paths(&raw) {
+        Ok(paths) => paths,
+        Err(e) => {
+            let message = format!("Cached status doesn't match the status model: {}", e);
+            let response = Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Full::new(Bytes::from(message)).boxed())
+                .expect("response is valid");
+            return Ok(response);
+        }
+    };
+    let body = serde_json::to_vec(&paths).expect("paths are serializable");
+    let response = Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(body.into()).boxed())
+        .expect("static header value is valid");
+    Ok(response)
+}
+
+/// Serves the last status as parsed by the exporter, in pretty JSON, to
+/// compare what the exporter understood with the raw status
+fn parsed_status(cache: StatusCache, lenient: bool) -> Result<Response<ResponseBody>, Infallible> {
+    let raw = cache.read().expect("status cache lock poisoned").clone();
+    let Some(raw) = raw else {
+        return Ok(text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Status has not been fetched yet",
+        ));
+    };
 
-async fn metrics(_: Request<impl hyper::body::Body>) -> Result<Response<Full<Bytes>>, Infallible> {
-    let encoder = TextEncoder::new();
-    let metric_families = prometheus::gather();
-    let mut buffer = vec![];
-    encoder.encode(&metric_families, &mut buffer).unwrap();
+    let status = if lenient {
+        parse_cluster_status_lenient(&raw)
+    } else {
+        parse_cluster_status(&raw)
+    };
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            let message = format!("Cached status couldn't be parsed: {}", e);
+            let response = Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Full::new(Bytes::from(message)).boxed())
+                .expect("response is valid");
+            return Ok(response);
+        }
+    };
+    let body = serde_json::to_vec_pretty(&status).expect("status is serializable");
     let response = Response::builder()
-        .header(CONTENT_TYPE, encoder.format_type())
-        .body(Full::new(buffer.into()))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(body.into()).boxed())
         .expect("static header value is valid");
     Ok(response)
 }
 
-async fn run_http_server(config: &CommandArgs) -> Result<(), anyhow::Error> {
+/// Serves the raw status JSON, or only the subtree given by the `path`
+/// query parameter (e.g. `/status?path=cluster.qos`)
+async fn status(
+    req: Request<impl hyper::body::Body>,
+    cache: StatusCache,
+) -> Result<Response<ResponseBody>, Infallible> {
+    let raw = cache.read().expect("status cache lock poisoned").clone();
+    let Some(raw) = raw else {
+        return Ok(text_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Status has not been fetched yet",
+        ));
+    };
+
+    let path = req.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "path")
+            .map(|(_, value)| value.into_owned())
+    });
+
+    let body = match path {
+        None => raw,
+        Some(path) => match status_subtree(&raw, &path) {
+            Ok(subtree) => subtree.into(),
+            Err(StatusPathError::InvalidPath) => {
+                return Ok(text_response(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid status path",
+                ));
+            }
+            Err(StatusPathError::NotFound) => {
+                return Ok(text_response(
+                    StatusCode::NOT_FOUND,
+                    "No subtree found at status path",
+                ));
+            }
+            Err(StatusPathError::Parsing) => {
+                return Ok(text_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Cached status is not valid JSON",
+                ));
+            }
+        },
+    };
+
+    let response = Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(body).boxed())
+        .expect("static header value is valid");
+    Ok(response)
+}
+
+/// Serves the raw status like [status], fetched for this request in
+/// [Mode::Proxy]
+async fn proxied_status(
+    req: Request<impl hyper::body::Body>,
+    proxy: StatusProxy,
+    exporter: Arc<Exporter>,
+) -> Result<Response<ResponseBody>, Infallible> {
+    let raw = proxy.fetch().await;
+    exporter.count_fetch(raw.is_ok());
+    match raw {
+        Ok(raw) => status(req, Arc::new(RwLock::new(Some(raw.into())))).await,
+        Err(e) => {
+            exporter.count_fetch_error(&e);
+            let message = format!("Couldn't fetch the status: {}", error_chain(&e));
+            let response = Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Full::new(Bytes::from(message)).boxed())
+                .expect("response is valid");
+            Ok(response)
+        }
+    }
+}
+
+/// Extract the subtree of a raw status JSON at a dot separated path,
+/// array elements are accessed by their index (e.g. `client.messages.0`)
+fn status_subtree(raw: &[u8], path: &str) -> Result<Vec<u8>, StatusPathError> {
+    let segments: Vec<&str> = path.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(StatusPathError::InvalidPath);
+    }
+
+    let root: Value = serde_json::from_slice(raw).map_err(|_| StatusPathError::Parsing)?;
+    let mut current = &root;
+    for segment in segments {
+        current = match current {
+            Value::Object(map) => map.get(segment),
+            Value::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        }
+        .ok_or(StatusPathError::NotFound)?;
+    }
+
+    serde_json::to_vec(current).map_err(|_| StatusPathError::Parsing)
+}
+
+fn text_response(status: StatusCode, message: &'static str) -> Response<ResponseBody> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(Bytes::from_static(message.as_bytes())).boxed())
+        .expect("static response is valid")
+}
+
+/// [Write] adapter sending what is written as chunks of a streaming body
+struct ChunkWriter {
+    sender: Sender<Bytes>,
+    runtime: Handle,
+    buffer: Vec<u8>,
+    /// Number of bytes written so far
+    written: usize,
+}
+
+impl ChunkWriter {
+    /// Must be used outside of the async runtime (e.g. in `spawn_blocking`)
+    /// as sending chunks blocks until the client reads them
+    fn new(sender: Sender<Bytes>, runtime: Handle) -> Self {
+        Self {
+            sender,
+            runtime,
+            buffer: Vec::with_capacity(STREAMING_CHUNK_SIZE),
+            written: 0,
+        }
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.written += buf.len();
+        if self.buffer.len() >= STREAMING_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(STREAMING_CHUNK_SIZE),
+        ));
+        self.runtime
+            .block_on(self.sender.send_data(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+/// Warn when an encoded metrics response is larger than `max_response_size`,
+/// it is still served whole as a truncated response would lose series
+fn check_response_size(exporter: &Exporter, size: usize, max_response_size: Option<usize>) {
+    if let Some(max_response_size) = max_response_size.filter(|max| size > *max) {
+        warn!(
+            "Metrics response of {} bytes exceeds the maximum of {} bytes, check the cardinality of the series",
+            size, max_response_size
+        );
+        exporter.count_response_truncated();
+    }
+}
+
+async fn metrics(
+    _: Request<impl hyper::body::Body>,
+    exporter: Arc<Exporter>,
+    max_response_size: Option<usize>,
+) -> Result<Response<ResponseBody>, Infallible> {
+    let metric_families = exporter.gather();
+    let series_count: usize = metric_families.iter().map(|f| f.get_metric().len()).sum();
+
+    // Small outputs are not worth the overhead of streaming
+    let body = if series_count < STREAMING_MIN_SERIES {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&metric_families, &mut buffer)
+            .unwrap();
+        check_response_size(&exporter, buffer.len(), max_response_size);
+        Full::new(buffer.into()).boxed()
+    } else {
+        let (sender, body) = Channel::new(STREAMING_CHANNEL_CAPACITY);
+        let mut writer = ChunkWriter::new(sender, Handle::current());
+        let exporter = exporter.clone();
+        tokio::task::spawn_blocking(move || {
+            let encoded = TextEncoder::new()
+                .encode(&metric_families, &mut writer)
+                .map_err(io::Error::other)
+                .and_then(|_| writer.flush());
+            match encoded {
+                Ok(()) => check_response_size(&exporter, writer.written, max_response_size),
+                Err(err) => error!("Error streaming metrics: {:?}", err),
+            }
+        });
+        body.boxed()
+    };
+
+    let last_fetch = exporter.last_fetch();
+    let mut response = Response::builder()
+        .header(CONTENT_TYPE, TEXT_FORMAT)
+        .header(
+            STATUS_HEADER,
+            if last_fetch.succeeded {
+                "ok"
+            } else {
+                "degraded"
+            },
+        );
+    if let Some(age) = last_fetch.age() {
+        response = response.header(DATA_AGE_HEADER, age.as_secs());
+    }
+    let response = response.body(body).expect("header values are valid");
+    Ok(response)
+}
+
+/// Accept connections until an error occurs or the future is dropped, spawned
+/// connection tasks are tracked in `connections` so they can be drained on shutdown
+async fn run_http_server(
+    config: &CommandArgs,
+    cache: StatusCache,
+    exporter: Arc<Exporter>,
+    connections: &mut JoinSet<()>,
+    proxy: Option<StatusProxy>,
+) -> Result<(), anyhow::Error> {
     let addr: SocketAddr = (config.addr, config.port).into();
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_listener(addr, config.tcp_backlog)?;
     info!("Listening on http://{}", addr);
+    let routes = Routes {
+        debug_endpoints: config.debug_endpoints,
+        lenient: config.lenient,
+        max_response_size: config.max_response_size,
+        ready_at: Instant::now() + config.warmup,
+    };
     loop {
         let (tcp, _) = listener.accept().await?;
+        if config.tcp_nodelay {
+            tcp.set_nodelay(true)?;
+        }
+        // Reap finished connections so the set doesn't grow forever
+        while connections.try_join_next().is_some() {}
+
         let io = TokioIo::new(tcp);
-        tokio::task::spawn(async move {
+        let cache = cache.clone();
+        let exporter = exporter.clone();
+        let proxy = proxy.clone();
+        connections.spawn(async move {
             if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(metrics))
+                .serve_connection(
+                    io,
+                    service_fn(|req| {
+                        serve(req, cache.clone(), exporter.clone(), routes, proxy.clone())
+                    }),
+                )
                 .await
             {
                 error!("Error serving connection: {:?}", err);
@@ -50,29 +442,500 @@ async fn run_http_server(config: &CommandArgs) -> Result<(), anyhow::Error> {
     }
 }
 
+/// Wait for in-flight connections to finish, connections still running after
+/// [SHUTDOWN_TIMEOUT] are aborted
+async fn drain_connections(mut connections: JoinSet<()>) {
+    if connections.is_empty() {
+        return;
+    }
+    info!("Waiting for {} connection(s) to finish", connections.len());
+    let drained = timeout(SHUTDOWN_TIMEOUT, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        error!(
+            "Aborting {} connection(s) still running after {:?}",
+            connections.len(),
+            SHUTDOWN_TIMEOUT
+        );
+        connections.shutdown().await;
+    }
+}
+
+/// Fetch and parse the status, opening `database` first if needed. The raw
+/// status is stored in `cache` even when it can't be parsed.
+async fn fetch_status(
+    config: &CommandArgs,
+    exporter: &Exporter,
+    database: &mut Option<Database>,
+    cache: &StatusCache,
+) -> Result<Arc<Status>, FetchError> {
+    let mut timings = FetchTimings::default();
+    let raw = match config.source {
+        StatusSource::Fdb => {
+            let db = match database.take() {
+                Some(db) => db,
+                None => {
+                    let db = open_database(config.cluster.as_deref())?;
+                    let connect = connect_database(&db, config.fdb_timeout).await?;
+                    exporter.set_coordinator_connect(connect);
+                    db
+                }
+            };
+            let (raw, attempts) = timings
+                .time_io(fetch_raw_status_at_key(
+                    &db,
+                    &config.status_key.0,
+                    config.fdb_timeout,
+                    config.fetch_retries,
+                ))
+                .await;
+            exporter.observe_fetch_attempts(attempts);
+            *database = Some(db);
+            raw
+        }
+        StatusSource::Fdbcli => {
+            timings
+                .time_io(fdbcli_status(config.cluster.clone(), config.fdb_timeout))
+                .await
+        }
+    };
+
+    let status = match raw {
+        Ok(raw) => {
+            exporter.set_status_bytes(raw.len());
+            // Shared with every consumer of the status instead of being cloned
+            let status = timings.time_parse(&raw, config.lenient).map(Arc::new);
+            *cache.write().expect("status cache lock poisoned") = Some(raw.into());
+            status
+        }
+        Err(e) => Err(e),
+    };
+    exporter.set_fetch_timings(&timings);
+    status
+}
+
+/// Run `fdbcli` on a blocking thread to fetch the raw status
+async fn fdbcli_status(
+    cluster: Option<PathBuf>,
+    fdb_timeout: Duration,
+) -> Result<Vec<u8>, FetchError> {
+    tokio::task::spawn_blocking(move || fetch_raw_status_fdbcli(cluster.as_deref(), fdb_timeout))
+        .await
+        .expect("fdbcli task panicked")
+}
+
+/// Fetches the raw status on demand in [Mode::Proxy], the database is opened
+/// by the first request and shared with the following ones
+#[derive(Clone)]
+struct StatusProxy {
+    source: StatusSource,
+    cluster: Option<PathBuf>,
+    status_key: Arc<[u8]>,
+    fdb_timeout: Duration,
+    fetch_retries: Option<i32>,
+    overall_timeout: Duration,
+    database: Arc<Mutex<Option<Arc<Database>>>>,
+}
+
+impl StatusProxy {
+    fn new(config: &CommandArgs) -> Self {
+        StatusProxy {
+            source: config.source,
+            cluster: config.cluster.clone(),
+            status_key: config.status_key.0.as_slice().into(),
+            fdb_timeout: config.fdb_timeout,
+            fetch_retries: config.fetch_retries,
+            overall_timeout: config.overall_timeout(),
+            database: Arc::default(),
+        }
+    }
+
+    async fn fetch(&self) -> Result<Vec<u8>, FetchError> {
+        with_overall_timeout(self.overall_timeout, async {
+            match self.source {
+                StatusSource::Fdb => {
+                    let db = self.database()?;
+                    fetch_raw_status_at_key(
+                        &db,
+                        &self.status_key,
+                        self.fdb_timeout,
+                        self.fetch_retries,
+                    )
+                    .await
+                    .0
+                }
+                StatusSource::Fdbcli => fdbcli_status(self.cluster.clone(), self.fdb_timeout).await,
+            }
+        })
+        .await
+    }
+
+    fn database(&self) -> Result<Arc<Database>, FetchError> {
+        let mut database = self.database.lock().expect("database lock poisoned");
+        if let Some(db) = database.as_ref() {
+            return Ok(db.clone());
+        }
+        let db = Arc::new(open_database(self.cluster.as_deref())?);
+        *database = Some(db.clone());
+        Ok(db)
+    }
+}
+
+/// Bound the duration of a whole fetch, the transaction timeout doesn't cover
+/// opening the database nor retries of the transaction
+async fn with_overall_timeout<T>(
+    duration: Duration,
+    fetch: impl Future<Output = Result<T, FetchError>>,
+) -> Result<T, FetchError> {
+    timeout(duration, fetch)
+        .await
+        .unwrap_or(Err(FetchError::Timeout(duration)))
+}
+
+/// Fetch the status a single time and write the resulting metrics on stdout
+async fn run_once(config: &CommandArgs) -> Result<(), anyhow::Error> {
+    let exporter = config.exporter()?;
+    let status = with_overall_timeout(
+        config.overall_timeout(),
+        fetch_status(config, &exporter, &mut None, &StatusCache::default()),
+    )
+    .await?;
+    exporter.process_metrics(&status, &config.metrics_options());
+
+    TextEncoder::new().encode(&exporter.gather(), &mut io::stdout().lock())?;
+    Ok(())
+}
+
+/// Fetch the status a single time and parse it strictly, without converting
+/// it to metrics
+async fn run_validate(config: &CommandArgs) -> Result<Status, FetchError> {
+    with_overall_timeout(config.overall_timeout(), async {
+        let raw = match config.source {
+            StatusSource::Fdb => {
+                let db = open_database(config.cluster.as_deref())?;
+                fetch_raw_status_at_key(
+                    &db,
+                    &config.status_key.0,
+                    config.fdb_timeout,
+                    config.fetch_retries,
+                )
+                .await
+                .0?
+            }
+            StatusSource::Fdbcli => {
+                fdbcli_status(config.cluster.clone(), config.fdb_timeout).await?
+            }
+        };
+        parse_cluster_status(&raw)
+    })
+    .await
+}
+
+/// Message of `error` followed by the messages of its sources, those already
+/// included in the previous message (e.g. the parse error of
+/// [FetchError::Parsing]) are skipped
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut previous = chain.clone();
+    let mut source = error.source();
+    while let Some(error) = source {
+        let message = error.to_string();
+        if !previous.contains(&message) {
+            chain.push_str(": ");
+            chain.push_str(&message);
+        }
+        previous = message;
+        source = error.source();
+    }
+    chain
+}
+
 /// Run a loop which will fetch regularly FDB status from the system key, to fetch current state
 /// of the cluster.
-async fn run_status_fetcher(config: &CommandArgs) -> Result<(), anyhow::Error> {
-    let cluster_path = config.cluster.as_deref();
+///
+/// The database handle is kept between two fetches, it is reopened after a
+/// failure to open it and on SIGHUP so a rotated cluster file is taken into
+/// account without restarting.
+async fn run_status_fetcher(
+    config: &CommandArgs,
+    exporter: &Exporter,
+    cache: StatusCache,
+) -> Result<(), anyhow::Error> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut database = None;
+    let options = config.metrics_options();
+    let cluster_options = without_process_groups(&options);
+    let mut iteration: u32 = 0;
+    let overall_timeout = config.overall_timeout();
+    if overall_timeout > config.delay_sec {
+        warn!(
+            "Overall timeout of {:?} is longer than the delay, fetches are cut after {:?}",
+            overall_timeout, config.delay_sec
+        );
+    }
+    let overall_timeout = overall_timeout.min(config.delay_sec);
+    #[cfg(feature = "remote-write")]
+    let mut remote_writer = config.remote_write.clone().map(RemoteWriter::new);
 
     loop {
-        let status = fetch_cluster_status(cluster_path, config.fdb_timeout).await;
-
+        exporter.count_loop_iteration();
+        // Per-process metrics keep their last values between two refreshes,
+        // only statuses converted successfully count towards the next one
+        let options = if iteration == 0 {
+            &options
+        } else {
+            &cluster_options
+        };
+        let started = Instant::now();
+        let status = with_overall_timeout(
+            overall_timeout,
+            fetch_status(config, exporter, &mut database, &cache),
+        )
+        .await;
+        exporter.count_fetch(status.is_ok());
         match status {
-            Ok(status) => process_metrics(status),
-            Err(FetchError::FdbBinding(e)) => {
-                return Err(e.into());
+            Ok(status) => {
+                // A latent bug on an unusual status must not stop the exporter
+                let conversion = panic::catch_unwind(AssertUnwindSafe(|| {
+                    exporter.process_metrics(&status, options)
+                }));
+                match conversion {
+                    Ok(()) => iteration = (iteration + 1) % config.process_refresh_multiplier,
+                    Err(_) => {
+                        error!("Conversion of the status to metrics panicked");
+                        exporter.count_conversion_panic();
+                    }
+                }
+                // The database is only opened with the fdb source
+                if let (true, Some(db)) = (config.shard_stats, &database) {
+                    let remaining = overall_timeout.saturating_sub(started.elapsed());
+                    let sizes =
+                        with_overall_timeout(remaining, fetch_shard_sizes(db, config.fdb_timeout));
+                    match sizes.await {
+                        Ok(sizes) => exporter.process_shard_sizes(&sizes, options),
+                        Err(e) => warn!("Couldn't read the shard sizes: {}", e),
+                    }
+                }
             }
-            Err(e) => e.to_metrics(&[]),
+            Err(e) => count_fetch_error(exporter, e)?,
         };
-        sleep(config.delay_sec).await;
+        if let Some(path) = &config.dump_file {
+            if let Err(e) = dump_metrics(exporter, path) {
+                error!("Couldn't write metrics to {}: {}", path.display(), e);
+                exporter.count_dump_error();
+            }
+        }
+        #[cfg(feature = "remote-write")]
+        if let Some(writer) = &mut remote_writer {
+            writer
+                .push(exporter, config.delay_sec, overall_timeout)
+                .await;
+        }
+        exporter.set_loop_lag(started.elapsed());
+        if let Some(bytes) = resident_memory_bytes() {
+            exporter.set_resident_memory(bytes);
+        }
+
+        tokio::select! {
+            _ = sleep(jittered(config.delay_sec, config.scrape_jitter)) => {},
+            _ = hangup.recv() => {
+                info!("Received SIGHUP, reloading cluster file");
+                database = None;
+                exporter.count_config_reload();
+            },
+        }
+    }
+}
+
+/// Count a failed fetch of [run_status_fetcher], only a misuse of the bindings
+/// stops the exporter
+fn count_fetch_error(exporter: &Exporter, error: FetchError) -> Result<(), anyhow::Error> {
+    match error {
+        FetchError::FdbBinding(e) => Err(e.into()),
+        e => {
+            exporter.count_fetch_error(&e);
+            Ok(())
+        }
+    }
+}
+
+/// Write the metrics to `path` in the text format, through a temporary file
+/// renamed over it so readers never see a partial file
+fn dump_metrics(exporter: &Exporter, path: &Path) -> Result<(), anyhow::Error> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = std::fs::File::create(&temporary)?;
+    TextEncoder::new().encode(&exporter.gather(), &mut file)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Longest delay between two pushes to a failing remote-write endpoint
+#[cfg(feature = "remote-write")]
+const REMOTE_WRITE_MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Parse the URL of a remote-write endpoint, only plain http is supported
+#[cfg(feature = "remote-write")]
+fn parse_remote_write_url(url: &str) -> Result<hyper::Uri, String> {
+    let uri: hyper::Uri = url
+        .parse()
+        .map_err(|e| format!("Invalid remote-write URL '{}': {}", url, e))?;
+    if uri.scheme_str() != Some("http") || uri.host().is_none() {
+        return Err(format!(
+            "Invalid remote-write URL '{}', expected http://host[:port]/path",
+            url
+        ));
+    }
+    Ok(uri)
+}
+
+/// Pushes the metrics to a Prometheus remote-write endpoint. After a failed
+/// push the following ones are skipped for a delay doubling with each
+/// consecutive failure.
+#[cfg(feature = "remote-write")]
+struct RemoteWriter {
+    client: hyper_util::client::legacy::Client<
+        hyper_util::client::legacy::connect::HttpConnector,
+        Full<Bytes>,
+    >,
+    url: hyper::Uri,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[cfg(feature = "remote-write")]
+impl RemoteWriter {
+    fn new(url: hyper::Uri) -> Self {
+        RemoteWriter {
+            client: hyper_util::client::legacy::Client::builder(
+                hyper_util::rt::TokioExecutor::new(),
+            )
+            .build_http(),
+            url,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    /// Push the current metrics unless backing off, failures are logged and
+    /// counted
+    async fn push(&mut self, exporter: &Exporter, delay: Duration, deadline: Duration) {
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return;
+        }
+        let pushed = match timeout(deadline, self.send(exporter)).await {
+            Ok(pushed) => pushed,
+            Err(_) => Err(anyhow::anyhow!("timed out after {:?}", deadline)),
+        };
+        match pushed {
+            Ok(()) => {
+                self.failures = 0;
+                self.retry_at = None;
+            }
+            Err(e) => {
+                self.failures = self.failures.saturating_add(1);
+                let backoff = remote_write_backoff(delay, self.failures);
+                error!(
+                    "Couldn't push metrics to {}: {}, next push in {:?}",
+                    self.url, e, backoff
+                );
+                exporter.count_remote_write_error();
+                self.retry_at = Some(Instant::now() + backoff);
+            }
+        }
+    }
+
+    async fn send(&self, exporter: &Exporter) -> Result<(), anyhow::Error> {
+        use fdbexporter::metrics::remote_write;
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis() as i64;
+        let body = remote_write::encode(&exporter.gather(), timestamp);
+        let request = Request::post(self.url.clone())
+            .header(CONTENT_TYPE, remote_write::CONTENT_TYPE)
+            .header(
+                hyper::header::CONTENT_ENCODING,
+                remote_write::CONTENT_ENCODING,
+            )
+            .header("X-Prometheus-Remote-Write-Version", remote_write::VERSION)
+            .body(Full::new(Bytes::from(body)))?;
+        let response = self.client.request(request).await?;
+        let status = response.status();
+        // Read the body so the connection can be reused
+        let body = response.into_body().collect().await?.to_bytes();
+        if !status.is_success() {
+            anyhow::bail!(
+                "endpoint answered {}: {}",
+                status,
+                String::from_utf8_lossy(&body).trim()
+            );
+        }
+        Ok(())
     }
 }
 
+/// Delay before pushing again after `failures` consecutive failures
+#[cfg(feature = "remote-write")]
+fn remote_write_backoff(delay: Duration, failures: u32) -> Duration {
+    delay
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(REMOTE_WRITE_MAX_BACKOFF)
+}
+
+/// Listen on `addr` with a queue of at most `backlog` pending connections,
+/// with the same socket options as [TcpListener::bind]
+fn bind_listener(addr: SocketAddr, backlog: u32) -> io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// Resident memory of the exporter read from `/proc/self/status`, `None` on
+/// systems without procfs
+fn resident_memory_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_rss(&status)
+}
+
+/// Value of the `VmRSS` line of a `/proc/<pid>/status` file, given in kB
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kilobytes: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Same as `options` with per-process and role metrics disabled, used for
+/// fetches which only refresh cluster-level metrics
+fn without_process_groups(options: &MetricsOptions) -> MetricsOptions {
+    let mut options = options.clone();
+    options
+        .disabled_groups
+        .extend([MetricGroup::Process, MetricGroup::Role]);
+    options
+}
+
 /// FoundationDB exporter for metrics parsed from status
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct CommandArgs {
+    /// TOML configuration file, command line flags and environment variables
+    /// override its values
+    #[arg(long, env = "FDB_EXPORTER_CONFIG")]
+    config: Option<PathBuf>,
+
     /// Listening port of the web server
     #[arg(short, long, default_value_t = 9090, env = "FDB_EXPORTER_PORT")]
     port: u16,
@@ -81,22 +944,675 @@ struct CommandArgs {
     #[arg(short, long, default_value = "0.0.0.0", env = "FDB_EXPORTER_ADDR")]
     addr: IpAddr,
 
+    /// Maximum number of connections waiting to be accepted by the web
+    /// server, bursts of scrapes beyond it are refused
+    #[arg(long, env = "FDB_EXPORTER_TCP_BACKLOG", value_parser = clap::value_parser!(u32).range(1..=MAX_TCP_BACKLOG), default_value_t = 1024)]
+    tcp_backlog: u32,
+
+    /// Disable Nagle's algorithm on connections of the web server, which
+    /// lowers the latency of small responses
+    #[arg(long, env = "FDB_EXPORTER_TCP_NODELAY")]
+    tcp_nodelay: bool,
+
     /// Location of fdb.cluster file
     #[arg(short, long, env = "FDB_CLUSTER_FILE")]
     cluster: Option<PathBuf>,
 
-    /// Delay in seconds between two update of the status & metrics
+    /// Certificate presented to a cluster configured with TLS, in PEM format
+    #[arg(long, env = "FDB_EXPORTER_FDB_TLS_CERT", requires = "fdb_tls_key")]
+    fdb_tls_cert: Option<PathBuf>,
+
+    /// Private key of the certificate given with `--fdb-tls-cert`
+    #[arg(long, env = "FDB_EXPORTER_FDB_TLS_KEY", requires = "fdb_tls_cert")]
+    fdb_tls_key: Option<PathBuf>,
+
+    /// Certificate authorities the certificates of the cluster are checked
+    /// against
+    #[arg(long, env = "FDB_EXPORTER_FDB_TLS_CA")]
+    fdb_tls_ca: Option<PathBuf>,
+
+    /// Delay between two update of the status & metrics, in seconds unless
+    /// suffixed with ms, s, m or h
     #[arg(short, long, env = "FDB_EXPORTER_DELAY", value_parser = parse_duration, default_value = "15")]
     delay_sec: Duration,
 
+    /// Randomly shorten or lengthen the delay between two updates by up to
+    /// this fraction of it (e.g. 0.2 for 20%), so exporters started together
+    /// don't read the status at the same time
+    #[arg(long, env = "FDB_EXPORTER_SCRAPE_JITTER", value_parser = parse_scrape_jitter, default_value = "0")]
+    scrape_jitter: f64,
+
     /// Timeout in seconds for FoundationDB status fetch operations
     #[arg(short = 't', long, env = "FDB_TIMEOUT", value_parser = parse_fdb_timeout, default_value = "60")]
     fdb_timeout: Duration,
+
+    /// Maximum number of retries of the status read transaction, by default
+    /// it is retried until the timeout
+    #[arg(long, env = "FDB_EXPORTER_FETCH_RETRIES", value_parser = clap::value_parser!(i32).range(0..))]
+    fetch_retries: Option<i32>,
+
+    /// Timeout of a whole status fetch, including opening the database and
+    /// retries, in seconds unless suffixed with ms, s, m or h. Defaults to
+    /// the delay, a larger value is cut to the delay by the fetch loop so a
+    /// fetch never runs past one interval. `--fdb-timeout` only bounds each
+    /// read transaction within it
+    #[arg(long, env = "FDB_EXPORTER_OVERALL_TIMEOUT", value_parser = parse_duration)]
+    overall_timeout: Option<Duration>,
+
+    /// Refresh per-process and role metrics only every N fetches, other
+    /// fetches only refresh cluster-level metrics
+    #[arg(long, env = "FDB_EXPORTER_PROCESS_REFRESH_MULTIPLIER", value_parser = clap::value_parser!(u32).range(1..), default_value_t = 1)]
+    process_refresh_multiplier: u32,
+
+    /// Value of the `cluster` label set on every cluster metric
+    #[arg(long, env = "FDB_CLUSTER_NAME", default_value = "default")]
+    cluster_name: String,
+
+    /// Label added to every metric as name=value (e.g. `environment=prod`),
+    /// can be repeated
+    #[arg(long, env = "FDB_EXPORTER_CONST_LABEL", value_delimiter = ',')]
+    const_label: Vec<ConstLabel>,
+
+    /// Remove cluster data gauges instead of keeping their last values when
+    /// the status has no data section
+    #[arg(long, env = "FDB_EXPORTER_RESET_MISSING_DATA")]
+    reset_missing_data: bool,
+
+    /// Group of metrics not to export (process, machine, role or qos), can be
+    /// repeated
+    #[arg(long, env = "FDB_EXPORTER_DISABLE", value_delimiter = ',')]
+    disable: Vec<MetricGroup>,
+
+    /// Only export metrics of processes whose id or address matches this
+    /// regular expression
+    #[arg(long, env = "FDB_EXPORTER_PROCESS_INCLUDE")]
+    process_include: Option<Regex>,
+
+    /// Don't export metrics of processes whose id or address matches this
+    /// regular expression
+    #[arg(long, env = "FDB_EXPORTER_PROCESS_EXCLUDE")]
+    process_exclude: Option<Regex>,
+
+    /// Also export noisy gauges with a `_smoothed` suffix, averaged with this
+    /// decay factor between 0 (no smoothing) and 1 (excluded)
+    #[arg(long, env = "FDB_EXPORTER_SMOOTHING_DECAY", value_parser = parse_smoothing_decay)]
+    smoothing_decay: Option<f64>,
+
+    /// Window over which recoveries are counted in
+    /// `fdb_cluster_recoveries_in_window`, in seconds unless suffixed with
+    /// ms, s, m or h
+    #[arg(long, env = "FDB_EXPORTER_RECOVERY_WINDOW", value_parser = parse_duration, default_value = "5m")]
+    recovery_window: Duration,
+
+    /// Time after startup during which `/ready` fails even if a fetch
+    /// succeeded, in seconds unless suffixed with ms, s, m or h
+    #[arg(long, env = "FDB_EXPORTER_WARMUP", value_parser = parse_duration, default_value = "0")]
+    warmup: Duration,
+
+    /// Weights of the components of `fdb_cluster_health_score` as a comma
+    /// separated list of name=weight, missing components keep their default
+    #[arg(long, env = "FDB_EXPORTER_HEALTH_WEIGHTS", default_value_t = HealthWeights::default())]
+    health_weights: HealthWeights,
+
+    /// Percentage of available kvstore space under which a storage server is
+    /// counted in `fdb_cluster_storage_servers_low_space`
+    #[arg(long, env = "FDB_EXPORTER_LOW_SPACE_THRESHOLD", value_parser = parse_low_space_threshold, default_value = "10")]
+    low_space_threshold: f64,
+
+    /// Coordinators the cluster should have, as a comma separated list of
+    /// host:port (IP or DNS host), those missing from the status or not
+    /// expected are counted in `fdb_cluster_coordinator_drift`
+    #[arg(long, env = "FDB_EXPORTER_EXPECTED_COORDINATORS", value_delimiter = ',', value_parser = parse_coordinator)]
+    expected_coordinators: Vec<FdbProcessAddress>,
+
+    /// Skip malformed parts of the status instead of failing the whole parse
+    #[arg(long, env = "FDB_EXPORTER_LENIENT")]
+    lenient: bool,
+
+    /// Format of the logs written on stdout
+    #[arg(long, env = "FDB_EXPORTER_LOG_FORMAT", value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Verbosity of the logs (off, error, warn, info, debug or trace),
+    /// `RUST_LOG` takes precedence when set
+    #[arg(long, env = "FDB_EXPORTER_LOG_LEVEL", value_parser = parse_log_level, default_value = "info")]
+    log_level: LevelFilter,
+
+    /// Where the status is read from, `fdbcli` runs `fdbcli --exec "status json"`
+    /// for environments where reading system keys is not allowed
+    #[arg(long, env = "FDB_EXPORTER_SOURCE", value_enum, default_value_t = StatusSource::Fdb)]
+    source: StatusSource,
+
+    /// Key the status is read from with the `fdb` source, non printable bytes
+    /// are written as `\xNN`
+    #[arg(long, env = "FDB_EXPORTER_STATUS_KEY", default_value_t = StatusKey(STATUS_KEY.to_vec()))]
+    status_key: StatusKey,
+
+    /// Also read the size of every shard with the `fdb` source, exported as
+    /// `fdb_cluster_largest_shard_bytes` and `fdb_cluster_shards_by_size`
+    #[arg(long, env = "FDB_EXPORTER_SHARD_STATS")]
+    shard_stats: bool,
+
+    /// Serve debugging routes, e.g. `/status/parsed` which shows the status as
+    /// parsed by the exporter or `/debug/status-schema` which lists the
+    /// keys of the status not captured by the exporter
+    #[arg(long, env = "FDB_EXPORTER_DEBUG_ENDPOINTS")]
+    debug_endpoints: bool,
+
+    /// Log a warning and count responses of `/metrics` larger than this
+    /// number of bytes, they are still served whole
+    #[arg(long, env = "FDB_EXPORTER_MAX_RESPONSE_SIZE")]
+    max_response_size: Option<usize>,
+
+    /// Also write the metrics to this file after each fetch, e.g. for the
+    /// textfile collector of node_exporter
+    #[arg(long, env = "FDB_EXPORTER_DUMP_FILE")]
+    dump_file: Option<PathBuf>,
+
+    /// Also push the metrics after each fetch to this Prometheus
+    /// remote-write endpoint (http only)
+    #[cfg(feature = "remote-write")]
+    #[arg(long, env = "FDB_EXPORTER_REMOTE_WRITE", value_parser = parse_remote_write_url)]
+    remote_write: Option<hyper::Uri>,
+
+    /// `proxy` only serves `/status`, fetched on demand for each request,
+    /// without fetching the status in the background nor exporting metrics
+    /// of the cluster
+    #[arg(long, env = "FDB_EXPORTER_MODE", value_enum, default_value_t = Mode::Metrics)]
+    mode: Mode,
+
+    /// Fetch the status once, print the metrics on stdout and exit instead
+    /// of serving them
+    #[arg(long, env = "FDB_EXPORTER_ONCE")]
+    once: bool,
+
+    /// Fetch and strictly parse the status once, then exit with a non-zero
+    /// code if it couldn't be fetched or parsed, without exporting metrics
+    #[arg(long, env = "FDB_EXPORTER_VALIDATE", conflicts_with = "once")]
+    validate: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    /// Human readable logs
+    Text,
+    /// One JSON object per line, for log aggregation pipelines
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Mode {
+    /// Fetch the status in the background and export it as metrics
+    Metrics,
+    /// Only serve the raw status, fetched on demand
+    Proxy,
 }
 
-fn parse_duration(arg: &str) -> Result<Duration, ParseIntError> {
-    let seconds = arg.parse()?;
-    Ok(Duration::from_secs(seconds))
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum StatusSource {
+    /// Read the status system key with the FoundationDB client
+    Fdb,
+    /// Run `fdbcli`, which must be in the `PATH`
+    Fdbcli,
+}
+
+/// Key of a special key space entry, written with `\xNN` escapes for non
+/// printable bytes (e.g. `\xff\xff/status/json`)
+#[derive(Clone, Debug, PartialEq)]
+struct StatusKey(Vec<u8>);
+
+impl FromStr for StatusKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = Vec::with_capacity(s.len());
+        let mut bytes = s.bytes();
+        while let Some(byte) = bytes.next() {
+            if byte != b'\\' {
+                key.push(byte);
+                continue;
+            }
+            match bytes.next() {
+                Some(b'\\') => key.push(b'\\'),
+                Some(b'x') => {
+                    let hex = [bytes.next(), bytes.next()];
+                    let escaped = match hex {
+                        [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                            .ok()
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                        _ => None,
+                    };
+                    key.push(escaped.ok_or(
+                        "Invalid status key, \\x must be followed by 2 hexadecimal digits",
+                    )?);
+                }
+                _ => {
+                    return Err(
+                        "Invalid status key, only \\xNN and \\\\ escapes are supported".to_string(),
+                    )
+                }
+            }
+        }
+        if key.is_empty() {
+            return Err("Status key must not be empty".to_string());
+        }
+        Ok(StatusKey(key))
+    }
+}
+
+impl std::fmt::Display for StatusKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in &self.0 {
+            match byte {
+                b'\\' => write!(f, "\\\\")?,
+                b' '..=b'~' => write!(f, "{}", *byte as char)?,
+                _ => write!(f, "\\x{:02x}", byte)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Label given as name=value, the name is validated when the exporter is
+/// created
+#[derive(Clone, Debug, PartialEq)]
+struct ConstLabel {
+    name: String,
+    value: String,
+}
+
+impl FromStr for ConstLabel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, value)) if !name.is_empty() && !value.is_empty() => Ok(ConstLabel {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!(
+                "Invalid constant label '{}', expected name=value",
+                s
+            )),
+        }
+    }
+}
+
+/// Content of the `--config` file, each key matches the long name of a flag
+/// with dashes replaced by underscores
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    port: Option<u16>,
+    addr: Option<IpAddr>,
+    tcp_backlog: Option<u32>,
+    tcp_nodelay: Option<bool>,
+    cluster: Option<PathBuf>,
+    fdb_tls_cert: Option<PathBuf>,
+    fdb_tls_key: Option<PathBuf>,
+    fdb_tls_ca: Option<PathBuf>,
+    delay_sec: Option<FileDuration>,
+    scrape_jitter: Option<f64>,
+    fdb_timeout: Option<u64>,
+    fetch_retries: Option<u32>,
+    overall_timeout: Option<FileDuration>,
+    process_refresh_multiplier: Option<u32>,
+    cluster_name: Option<String>,
+    const_label: Option<Vec<String>>,
+    reset_missing_data: Option<bool>,
+    disable: Option<Vec<String>>,
+    process_include: Option<String>,
+    process_exclude: Option<String>,
+    smoothing_decay: Option<f64>,
+    recovery_window: Option<FileDuration>,
+    warmup: Option<FileDuration>,
+    health_weights: Option<String>,
+    low_space_threshold: Option<f64>,
+    expected_coordinators: Option<Vec<String>>,
+    lenient: Option<bool>,
+    log_format: Option<String>,
+    log_level: Option<String>,
+    source: Option<String>,
+    status_key: Option<String>,
+    shard_stats: Option<bool>,
+    debug_endpoints: Option<bool>,
+    max_response_size: Option<usize>,
+    dump_file: Option<PathBuf>,
+    #[cfg(feature = "remote-write")]
+    remote_write: Option<String>,
+    mode: Option<String>,
+}
+
+/// Duration given either as a number of seconds or with a unit (e.g. "2m")
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FileDuration {
+    Seconds(u64),
+    Human(String),
+}
+
+impl std::fmt::Display for FileDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileDuration::Seconds(seconds) => write!(f, "{}", seconds),
+            FileDuration::Human(duration) => write!(f, "{}", duration),
+        }
+    }
+}
+
+impl FileConfig {
+    fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Couldn't parse config file {}", path.display()))
+    }
+
+    /// Parse `argv` again with the values of the file appended as flags, for
+    /// every option `matches` got neither from a flag nor from an environment
+    /// variable, so values of the file go through the parsers of the flags
+    fn merge(
+        self,
+        mut argv: Vec<OsString>,
+        matches: &ArgMatches,
+    ) -> Result<ArgMatches, anyhow::Error> {
+        let from_file = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                None | Some(ValueSource::DefaultValue)
+            )
+        };
+        macro_rules! file_args {
+            ($($(#[$meta:meta])* $field:ident),* $(,)?) => {$(
+                $(#[$meta])*
+                if let Some(value) = self.$field.filter(|_| from_file(stringify!($field))) {
+                    argv.extend(value.into_args(&stringify!($field).replace('_', "-")));
+                }
+            )*};
+        }
+        file_args!(
+            port,
+            addr,
+            tcp_backlog,
+            tcp_nodelay,
+            cluster,
+            fdb_tls_cert,
+            fdb_tls_key,
+            fdb_tls_ca,
+            delay_sec,
+            scrape_jitter,
+            fdb_timeout,
+            fetch_retries,
+            overall_timeout,
+            process_refresh_multiplier,
+            cluster_name,
+            const_label,
+            reset_missing_data,
+            disable,
+            process_include,
+            process_exclude,
+            smoothing_decay,
+            recovery_window,
+            warmup,
+            health_weights,
+            low_space_threshold,
+            expected_coordinators,
+            lenient,
+            log_format,
+            log_level,
+            source,
+            status_key,
+            shard_stats,
+            debug_endpoints,
+            max_response_size,
+            dump_file,
+            #[cfg(feature = "remote-write")]
+            remote_write,
+            mode,
+        );
+        CommandArgs::command()
+            .try_get_matches_from(argv)
+            .context("Invalid value in the config file")
+    }
+}
+
+/// Value of a [FileConfig] key, turned into the arguments of its flag
+trait FileValue {
+    fn into_args(self, flag: &str) -> Vec<OsString>;
+}
+
+macro_rules! display_file_value {
+    ($($value:ty),*) => {$(
+        impl FileValue for $value {
+            fn into_args(self, flag: &str) -> Vec<OsString> {
+                vec![format!("--{}={}", flag, self).into()]
+            }
+        }
+    )*};
+}
+
+display_file_value!(u16, u32, u64, usize, f64, String, IpAddr, FileDuration);
+
+impl FileValue for PathBuf {
+    fn into_args(self, flag: &str) -> Vec<OsString> {
+        let mut arg = OsString::from(format!("--{}=", flag));
+        arg.push(self);
+        vec![arg]
+    }
+}
+
+impl FileValue for bool {
+    /// Boolean flags take no value and default to false
+    fn into_args(self, flag: &str) -> Vec<OsString> {
+        if self {
+            vec![format!("--{}", flag).into()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl FileValue for Vec<String> {
+    fn into_args(self, flag: &str) -> Vec<OsString> {
+        self.into_iter()
+            .flat_map(|value| value.into_args(flag))
+            .collect()
+    }
+}
+
+impl CommandArgs {
+    fn metrics_options(&self) -> MetricsOptions {
+        MetricsOptions {
+            cluster_name: self.cluster_name.clone(),
+            reset_missing_data: self.reset_missing_data,
+            disabled_groups: self.disable.iter().copied().collect(),
+            process_include: self.process_include.clone(),
+            process_exclude: self.process_exclude.clone(),
+            smoothing_decay: self.smoothing_decay,
+            cluster_file: self.cluster.clone(),
+            recovery_window: self.recovery_window,
+            health_weights: self.health_weights.clone(),
+            low_space_threshold: self.low_space_threshold,
+            expected_coordinators: self.expected_coordinators.clone(),
+        }
+    }
+
+    /// Constant labels by name, fails if a name is given twice
+    fn const_labels(&self) -> Result<HashMap<String, String>, anyhow::Error> {
+        let mut labels = HashMap::with_capacity(self.const_label.len());
+        for label in &self.const_label {
+            if labels
+                .insert(label.name.clone(), label.value.clone())
+                .is_some()
+            {
+                anyhow::bail!("Constant label '{}' is given more than once", label.name);
+            }
+        }
+        Ok(labels)
+    }
+
+    /// Bound of a whole status fetch, the delay unless `--overall-timeout`
+    /// is given
+    fn overall_timeout(&self) -> Duration {
+        self.overall_timeout.unwrap_or(self.delay_sec)
+    }
+
+    /// Exporter whose metrics carry the constant labels
+    fn exporter(&self) -> Result<Exporter, anyhow::Error> {
+        Ok(Exporter::with_const_labels(self.const_labels()?)?)
+    }
+
+    /// Parse the command line, then fill every option which was neither given
+    /// as a flag nor as an environment variable from the `--config` file
+    fn load() -> Result<Self, anyhow::Error> {
+        let argv: Vec<OsString> = std::env::args_os().collect();
+        let mut matches = Self::command().get_matches_from(&argv);
+        if let Some(path) = matches.get_one::<PathBuf>("config") {
+            matches = FileConfig::load(path)?.merge(argv, &matches)?;
+        }
+        let args = Self::from_arg_matches(&matches)?;
+        args.exporter()?;
+        args.check_tls_files()?;
+        Ok(args)
+    }
+
+    /// Check the TLS files can be read, so a wrong path fails at startup
+    /// rather than as connection errors to the cluster
+    fn check_tls_files(&self) -> Result<(), anyhow::Error> {
+        if self.fdb_tls_cert.is_some() != self.fdb_tls_key.is_some() {
+            anyhow::bail!("fdb_tls_cert and fdb_tls_key must be given together");
+        }
+        for (name, path) in [
+            ("fdb_tls_cert", &self.fdb_tls_cert),
+            ("fdb_tls_key", &self.fdb_tls_key),
+            ("fdb_tls_ca", &self.fdb_tls_ca),
+        ] {
+            if let Some(path) = path {
+                std::fs::File::open(path)
+                    .with_context(|| format!("Couldn't read {} {}", name, path.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// FoundationDB network options of the TLS files
+    fn tls_options(&self) -> Result<Vec<NetworkOption>, anyhow::Error> {
+        let path = |path: &Path| {
+            path.to_str()
+                .map(str::to_string)
+                .with_context(|| format!("TLS file path {} is not UTF-8", path.display()))
+        };
+        let mut options = Vec::new();
+        if let Some(cert) = &self.fdb_tls_cert {
+            options.push(NetworkOption::TLSCertPath(path(cert)?));
+        }
+        if let Some(key) = &self.fdb_tls_key {
+            options.push(NetworkOption::TLSKeyPath(path(key)?));
+        }
+        if let Some(ca) = &self.fdb_tls_ca {
+            options.push(NetworkOption::TLSCaPath(path(ca)?));
+        }
+        Ok(options)
+    }
+}
+
+/// Install the global tracing subscriber, directives of `RUST_LOG` override
+/// the `level` given on the command line
+fn init_tracing(format: LogFormat, level: LevelFilter) {
+    let filter = EnvFilter::builder()
+        .with_default_directive(level.into())
+        .from_env_lossy();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+fn parse_log_level(arg: &str) -> Result<LevelFilter, String> {
+    arg.parse().map_err(|_| {
+        format!(
+            "Invalid log level '{}', expected one of off, error, warn, info, debug or trace",
+            arg
+        )
+    })
+}
+
+/// Parse a duration made of an integer and an optional unit among `ms`, `s`,
+/// `m` and `h`, a bare integer is a number of seconds
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (value, unit) = arg.split_at(split);
+    let value: u64 = value.parse().map_err(|_| {
+        format!(
+            "Invalid duration '{}', expected e.g. 15, 15s, 2m or 500ms",
+            arg
+        )
+    })?;
+
+    let duration = match unit {
+        "ms" => Duration::from_millis(value),
+        "" | "s" => Duration::from_secs(value),
+        "m" => value
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or("Duration too large")?,
+        "h" => value
+            .checked_mul(3600)
+            .map(Duration::from_secs)
+            .ok_or("Duration too large")?,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit '{}', expected one of ms, s, m or h",
+                unit
+            ))
+        }
+    };
+    Ok(duration)
+}
+
+fn parse_smoothing_decay(arg: &str) -> Result<f64, String> {
+    let decay: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid smoothing decay: {}", e))?;
+    if !(0.0..1.0).contains(&decay) {
+        return Err("Smoothing decay must be at least 0 and lower than 1".to_string());
+    }
+    Ok(decay)
+}
+
+fn parse_low_space_threshold(arg: &str) -> Result<f64, String> {
+    let threshold: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid low space threshold: {}", e))?;
+    if !(0.0..=100.0).contains(&threshold) {
+        return Err("Low space threshold must be a percentage between 0 and 100".to_string());
+    }
+    Ok(threshold)
+}
+
+fn parse_coordinator(arg: &str) -> Result<FdbProcessAddress, String> {
+    FdbProcessAddress::parse(arg)
+        .map_err(|e| format!("Invalid coordinator '{}', expected host:port: {}", arg, e))
+}
+
+fn parse_scrape_jitter(arg: &str) -> Result<f64, String> {
+    let jitter: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid scrape jitter: {}", e))?;
+    if !(0.0..1.0).contains(&jitter) {
+        return Err("Scrape jitter must be at least 0 and lower than 1".to_string());
+    }
+    Ok(jitter)
+}
+
+/// Offset `delay` by a random amount of up to `jitter` times itself, in
+/// either direction
+fn jittered(delay: Duration, jitter: f64) -> Duration {
+    if jitter == 0.0 {
+        return delay;
+    }
+    delay.mul_f64(1.0 + rand::random_range(-jitter..=jitter))
 }
 
 fn parse_fdb_timeout(arg: &str) -> Result<Duration, String> {
@@ -119,27 +1635,70 @@ fn parse_fdb_timeout(arg: &str) -> Result<Duration, String> {
 
 #[tokio::main]
 pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    tracing_subscriber::fmt::init();
+    let cli = CommandArgs::load()?;
+    init_tracing(cli.log_format, cli.log_level);
 
-    // Initialize FoundationDB client
+    // Initialize FoundationDB client, network options must be set before
+    // the network starts
+    let mut network = FdbApiBuilder::default().build()?;
+    for option in cli.tls_options()? {
+        network = network.set_option(option)?;
+    }
     // Safe because we drop it before the program exits
-    let _fdb_network = unsafe { foundationdb::boot() };
+    let _fdb_network = unsafe { network.boot()? };
 
-    let cli = CommandArgs::parse();
-
-    tokio::select! {
-        server = run_http_server(&cli) => {
-            if let Err(err) = server {
-                error!("HTTP server thread failed, {:?}", err);
+    if cli.validate {
+        let result = run_validate(&cli).await;
+        drop(_fdb_network);
+        match result {
+            Ok(status) => {
+                let processes = status.cluster.map_or(0, |cluster| cluster.processes.len());
+                println!("Status is valid, {} processes parsed", processes);
+                return Ok(());
             }
-        },
-        fetcher = run_status_fetcher(&cli) => {
-            if let Err(err) = fetcher {
-                error!("HTTP fetcher thread failed, {:?}", err);
+            Err(e) => {
+                eprintln!("Status is invalid: {}", error_chain(&e));
+                std::process::exit(1);
             }
+        }
+    }
+
+    if cli.once {
+        let result = run_once(&cli).await;
+        drop(_fdb_network);
+        return result.map_err(Into::into);
+    }
+
+    let cache = StatusCache::default();
+    let exporter = Arc::new(cli.exporter()?);
+    let mut connections = JoinSet::new();
+
+    match cli.mode {
+        Mode::Metrics => tokio::select! {
+            server = run_http_server(&cli, cache.clone(), exporter.clone(), &mut connections, None) => {
+                if let Err(err) = server {
+                    error!("HTTP server thread failed, {:?}", err);
+                }
+            },
+            fetcher = run_status_fetcher(&cli, &exporter, cache) => {
+                if let Err(err) = fetcher {
+                    error!("HTTP fetcher thread failed, {:?}", err);
+                }
+            },
         },
+        Mode::Proxy => {
+            let proxy = StatusProxy::new(&cli);
+            let server = run_http_server(&cli, cache, exporter, &mut connections, Some(proxy));
+            if let Err(err) = server.await {
+                error!("HTTP server thread failed, {:?}", err);
+            }
+        }
     };
 
+    // The server future has been dropped by select!, so no new connection is
+    // accepted, the FDB network must outlive the ones still in flight
+    drain_connections(connections).await;
+
     // Clean shutdown of FDB network
     drop(_fdb_network);
 
@@ -148,19 +1707,724 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
 #[cfg(test)]
 mod tests {
-    use std::{net::Ipv4Addr, time::Duration};
+    use std::{ffi::OsString, net::Ipv4Addr, sync::Arc, time::Duration};
+
+    use std::io::Write;
+
+    use bytes::Bytes;
+    use http_body_util::{channel::Channel, BodyExt, Empty};
+    use hyper::{Request, StatusCode};
+    use tokio::runtime::Handle;
+    use tokio::time::Instant;
+
+    use crate::{
+        bind_listener, count_fetch_error, dump_metrics, error_chain, jittered, metrics,
+        parse_duration, parse_log_level, parse_low_space_threshold, parse_scrape_jitter,
+        parse_smoothing_decay, parse_vm_rss, parsed_status, ready, serve, status_subtree,
+        with_overall_timeout, ChunkWriter, CommandArgs, ConstLabel, FileConfig, LogFormat, Mode,
+        Routes, StatusCache, StatusKey, StatusPathError, StatusProxy, StatusSource,
+        DATA_AGE_HEADER, STATUS_HEADER, STREAMING_CHUNK_SIZE,
+    };
+    #[cfg(feature = "remote-write")]
+    use crate::{
+        parse_remote_write_url, remote_write_backoff, RemoteWriter, REMOTE_WRITE_MAX_BACKOFF,
+    };
+    use clap::{CommandFactory, FromArgMatches};
+    use fdbexporter::{Exporter, FetchError, HealthWeights, MetricGroup, STATUS_KEY};
+    use foundationdb::{FdbBindingError, FdbError};
+    use tracing_subscriber::filter::LevelFilter;
 
-    use crate::CommandArgs;
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../tests/fixtures/simple_fdb.json");
 
     impl Default for CommandArgs {
         fn default() -> Self {
             CommandArgs {
+                config: None,
                 port: 9090,
                 addr: std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+                tcp_backlog: 1024,
+                tcp_nodelay: false,
                 cluster: None,
+                fdb_tls_cert: None,
+                fdb_tls_key: None,
+                fdb_tls_ca: None,
                 delay_sec: Duration::from_secs(1),
+                scrape_jitter: 0.0,
                 fdb_timeout: Duration::from_secs(60),
+                fetch_retries: None,
+                overall_timeout: None,
+                process_refresh_multiplier: 1,
+                cluster_name: "default".to_string(),
+                const_label: Vec::new(),
+                reset_missing_data: false,
+                disable: Vec::new(),
+                process_include: None,
+                process_exclude: None,
+                smoothing_decay: None,
+                recovery_window: Duration::from_secs(300),
+                warmup: Duration::ZERO,
+                health_weights: HealthWeights::default(),
+                low_space_threshold: 10.0,
+                expected_coordinators: Vec::new(),
+                lenient: false,
+                log_format: LogFormat::Text,
+                log_level: LevelFilter::INFO,
+                source: StatusSource::Fdb,
+                status_key: StatusKey(STATUS_KEY.to_vec()),
+                shard_stats: false,
+                debug_endpoints: false,
+                max_response_size: None,
+                dump_file: None,
+                #[cfg(feature = "remote-write")]
+                remote_write: None,
+                mode: Mode::Metrics,
+                once: false,
+                validate: false,
             }
         }
     }
+
+    #[test]
+    fn status_subtree_known_path() {
+        let subtree = status_subtree(SIMPLE_STATUS, "cluster.qos.performance_limited_by").unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&subtree).unwrap();
+        assert_eq!(value["name"], "workload");
+        assert_eq!(value["reason_id"], 6);
+    }
+
+    #[test]
+    fn status_subtree_missing_path() {
+        assert_eq!(
+            status_subtree(SIMPLE_STATUS, "cluster.does_not_exist"),
+            Err(StatusPathError::NotFound)
+        );
+    }
+
+    #[test]
+    fn status_subtree_invalid_path() {
+        assert_eq!(
+            status_subtree(SIMPLE_STATUS, "cluster..qos"),
+            Err(StatusPathError::InvalidPath)
+        );
+        assert_eq!(
+            status_subtree(SIMPLE_STATUS, ""),
+            Err(StatusPathError::InvalidPath)
+        );
+    }
+
+    #[tokio::test]
+    async fn overall_timeout_stops_slow_fetch() {
+        let slow_fetch = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(Vec::<u8>::new())
+        };
+        let fetched = with_overall_timeout(Duration::from_millis(10), slow_fetch).await;
+        assert!(matches!(fetched, Err(FetchError::Timeout(_))));
+
+        let fast_fetch = async { Ok(b"{}".to_vec()) };
+        let fetched = with_overall_timeout(Duration::from_secs(5), fast_fetch).await;
+        assert_eq!(fetched.unwrap(), b"{}");
+    }
+
+    #[test]
+    fn retry_limit_errors_are_counted() {
+        let exporter = Exporter::new();
+        // What `Database::run` returns once `--fetch-retries` is reached
+        let exhausted = FdbBindingError::NonRetryableFdbError(FdbError::from_code(1020));
+        assert!(count_fetch_error(&exporter, exhausted.into()).is_ok());
+        let fdb_errors = exporter
+            .gather()
+            .iter()
+            .find(|family| family.get_name() == "fdb_exporter_fdb_error_count")
+            .map(|family| family.get_metric()[0].get_counter().get_value());
+        assert_eq!(fdb_errors, Some(1.0));
+
+        let misuse = FdbBindingError::ReferenceToTransactionKept;
+        assert!(count_fetch_error(&exporter, misuse.into()).is_err());
+    }
+
+    #[test]
+    fn overall_timeout_defaults_to_delay() {
+        let args = CommandArgs {
+            delay_sec: Duration::from_secs(15),
+            ..Default::default()
+        };
+        assert_eq!(args.overall_timeout(), Duration::from_secs(15));
+        let args = CommandArgs {
+            overall_timeout: Some(Duration::from_secs(5)),
+            ..args
+        };
+        assert_eq!(args.overall_timeout(), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn chunk_writer_streams_everything() {
+        let payload = vec![b'a'; STREAMING_CHUNK_SIZE * 2 + 10];
+        let (sender, body) = Channel::<Bytes>::new(1);
+        let mut writer = ChunkWriter::new(sender, Handle::current());
+
+        let expected = payload.clone();
+        let writing = tokio::task::spawn_blocking(move || {
+            writer.write_all(&payload).unwrap();
+            writer.flush().unwrap();
+        });
+
+        let received = body.collect().await.unwrap().to_bytes();
+        writing.await.unwrap();
+        assert_eq!(received, expected);
+    }
+
+    #[tokio::test]
+    async fn metrics_report_last_fetch() {
+        let exporter = Arc::new(Exporter::new());
+        let request = || Request::new(Empty::<Bytes>::new());
+
+        let response = metrics(request(), exporter.clone(), None).await.unwrap();
+        assert_eq!(response.headers()[STATUS_HEADER], "degraded");
+        assert!(!response.headers().contains_key(DATA_AGE_HEADER));
+
+        exporter.count_fetch(true);
+        let response = metrics(request(), exporter.clone(), None).await.unwrap();
+        assert_eq!(response.headers()[STATUS_HEADER], "ok");
+        assert_eq!(response.headers()[DATA_AGE_HEADER], "0");
+
+        // Metrics of the last successful fetch are still served
+        exporter.count_fetch(false);
+        let response = metrics(request(), exporter.clone(), None).await.unwrap();
+        assert_eq!(response.headers()[STATUS_HEADER], "degraded");
+        assert!(response.headers().contains_key(DATA_AGE_HEADER));
+    }
+
+    #[test]
+    fn ready_after_warmup_and_successful_fetch() {
+        let exporter = Exporter::new();
+        let now = Instant::now();
+        exporter.count_fetch(true);
+        let warming_up = ready(&exporter, now + Duration::from_secs(60));
+        assert_eq!(warming_up.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready(&exporter, now).status(), StatusCode::OK);
+
+        exporter.count_fetch(false);
+        assert_eq!(
+            ready(&exporter, now).status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[tokio::test]
+    async fn large_responses_are_counted() {
+        let exporter = Arc::new(Exporter::new());
+        let request = || Request::new(Empty::<Bytes>::new());
+        let truncated = |exporter: &Exporter| {
+            exporter
+                .gather()
+                .iter()
+                .find(|family| family.get_name() == "fdb_exporter_response_truncated_count")
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+                .unwrap()
+        };
+
+        let body = metrics(request(), exporter.clone(), Some(usize::MAX))
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(truncated(&exporter), 0.0);
+
+        // Served whole even when too large
+        let large = metrics(request(), exporter.clone(), Some(10))
+            .await
+            .unwrap()
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(truncated(&exporter), 1.0);
+        assert_eq!(large.len(), body.len());
+    }
+
+    #[test]
+    fn metrics_are_dumped_atomically() {
+        let exporter = Exporter::new();
+        exporter.count_fetch(true);
+        let path =
+            std::env::temp_dir().join(format!("fdbexporter-dump-{}.prom", std::process::id()));
+
+        dump_metrics(&exporter, &path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("fdb_exporter_fetch_total 1"));
+        assert!(!path.with_extension("prom.tmp").exists());
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = std::env::temp_dir().join("fdbexporter-missing-dir/fdb.prom");
+        assert!(dump_metrics(&exporter, &missing).is_err());
+    }
+
+    #[tokio::test]
+    async fn proxy_mode_fetches_on_demand() {
+        let config = CommandArgs {
+            mode: Mode::Proxy,
+            source: StatusSource::Fdbcli,
+            cluster: Some("/nonexistent/fdb.cluster".into()),
+            ..Default::default()
+        };
+        let exporter = Arc::new(Exporter::new());
+        let cache = StatusCache::default();
+        let req = Request::get("/status").body(Empty::<Bytes>::new()).unwrap();
+
+        let response = serve(
+            req,
+            cache.clone(),
+            exporter.clone(),
+            Routes {
+                debug_endpoints: false,
+                lenient: false,
+                max_response_size: None,
+                ready_at: Instant::now(),
+            },
+            Some(StatusProxy::new(&config)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.starts_with(b"Couldn't fetch the status: "));
+        // Nothing is cached nor converted to metrics
+        assert!(cache.read().unwrap().is_none());
+        let counter = |name: &str| {
+            exporter
+                .gather()
+                .iter()
+                .find(|family| family.get_name() == name)
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+        };
+        assert_eq!(counter("fdb_exporter_fetch_total"), Some(1.0));
+        assert_eq!(counter("fdb_exporter_fetch_success_total"), Some(0.0));
+    }
+
+    #[test]
+    fn tls_files_are_checked() {
+        let cert = std::env::temp_dir().join(format!("fdbexporter-{}.pem", std::process::id()));
+        std::fs::write(&cert, "").unwrap();
+        let args = CommandArgs {
+            fdb_tls_cert: Some(cert.clone()),
+            fdb_tls_key: Some(cert.clone()),
+            ..Default::default()
+        };
+        assert!(args.check_tls_files().is_ok());
+        assert_eq!(args.tls_options().unwrap().len(), 2);
+
+        let missing_ca = CommandArgs {
+            fdb_tls_ca: Some("/nonexistent/ca.pem".into()),
+            ..Default::default()
+        };
+        let error = missing_ca.check_tls_files().unwrap_err();
+        assert!(error.to_string().contains("fdb_tls_ca /nonexistent/ca.pem"));
+
+        let without_key = CommandArgs {
+            fdb_tls_cert: Some(cert.clone()),
+            ..Default::default()
+        };
+        assert!(without_key.check_tls_files().is_err());
+        assert!(CommandArgs::command()
+            .try_get_matches_from(["fdbexporter", "--fdb-tls-cert", "cert.pem"])
+            .is_err());
+        std::fs::remove_file(&cert).unwrap();
+    }
+
+    #[test]
+    fn error_chain_shows_parse_path() {
+        let mut status: serde_json::Value = serde_json::from_slice(SIMPLE_STATUS).unwrap();
+        status["cluster"]["generation"] = serde_json::Value::from("second");
+        let error = fdbexporter::parse_cluster_status(&serde_json::to_vec(&status).unwrap())
+            .err()
+            .unwrap();
+
+        let chain = error_chain(&error);
+        assert!(chain.starts_with("Failed to parse status JSON: cluster.generation: "));
+        // The parse error is given once, not repeated for each source
+        assert_eq!(chain.matches("invalid type").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn listener_accepts_connections() {
+        let listener = bind_listener((Ipv4Addr::LOCALHOST, 0).into(), 16).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::net::TcpStream::connect(addr);
+        let (accepted, client) = tokio::join!(listener.accept(), client);
+        let (tcp, _) = accepted.unwrap();
+        tcp.set_nodelay(true).unwrap();
+        assert!(tcp.nodelay().unwrap());
+        assert_eq!(client.unwrap().peer_addr().unwrap(), addr);
+    }
+
+    #[tokio::test]
+    async fn parsed_status_is_served() {
+        let cache = StatusCache::default();
+        let response = parsed_status(cache.clone(), false).unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        *cache.write().unwrap() = Some(Bytes::from_static(SIMPLE_STATUS));
+        let response = parsed_status(cache.clone(), false).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let raw: serde_json::Value = serde_json::from_slice(SIMPLE_STATUS).unwrap();
+        assert_eq!(
+            parsed["cluster"]["protocol_version"],
+            raw["cluster"]["protocol_version"]
+        );
+        let (id, process) = raw["cluster"]["processes"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .next()
+            .unwrap();
+        assert_eq!(
+            parsed["cluster"]["processes"][id]["address"],
+            process["address"]
+        );
+
+        *cache.write().unwrap() = Some(Bytes::from_static(b"{}"));
+        let response = parsed_status(cache, false).unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn log_level_validation() {
+        assert_eq!(parse_log_level("debug"), Ok(LevelFilter::DEBUG));
+        assert_eq!(parse_log_level("WARN"), Ok(LevelFilter::WARN));
+        assert!(parse_log_level("verbose")
+            .unwrap_err()
+            .contains("Invalid log level 'verbose'"));
+    }
+
+    /// Arguments parsed from `argv` and completed by the config file `content`
+    fn with_config_file(argv: &[&str], content: &str) -> Result<CommandArgs, anyhow::Error> {
+        let argv: Vec<OsString> = argv.iter().map(OsString::from).collect();
+        let matches = CommandArgs::command().try_get_matches_from(&argv)?;
+        let file: FileConfig = toml::from_str(content)?;
+        Ok(CommandArgs::from_arg_matches(&file.merge(argv, &matches)?)?)
+    }
+
+    #[test]
+    fn config_file_is_overridden_by_flags() {
+        let args = with_config_file(
+            &["fdbexporter", "--port", "1234", "--lenient"],
+            r#"
+            port = 9999
+            delay_sec = "30s"
+            lenient = false
+            cluster_name = "production"
+            log_format = "json"
+            source = "fdbcli"
+            disable = ["process", "qos"]
+            const_label = ["environment=prod"]
+            fetch_retries = 3
+            process_refresh_multiplier = 4
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(args.port, 1234);
+        assert!(args.lenient);
+        assert_eq!(args.delay_sec, Duration::from_secs(30));
+        assert_eq!(args.cluster_name, "production");
+        assert!(matches!(args.log_format, LogFormat::Json));
+        assert_eq!(args.source, StatusSource::Fdbcli);
+        assert_eq!(args.disable, [MetricGroup::Process, MetricGroup::Qos]);
+        assert_eq!(args.const_label, ["environment=prod".parse().unwrap()]);
+        assert_eq!(args.fetch_retries, Some(3));
+        assert_eq!(args.process_refresh_multiplier, 4);
+    }
+
+    #[test]
+    fn every_flag_has_an_environment_variable() {
+        // Other tests parse the real variables, so they are read from
+        // prefixed copies to avoid racing with them
+        const PREFIX: &str = "ENV_ONLY_TEST_";
+        for (name, value) in [
+            ("FDB_EXPORTER_PORT", "1234"),
+            ("FDB_EXPORTER_ADDR", "127.0.0.1"),
+            ("FDB_EXPORTER_TCP_BACKLOG", "4096"),
+            ("FDB_EXPORTER_TCP_NODELAY", "true"),
+            ("FDB_CLUSTER_FILE", "/etc/foundationdb/fdb.cluster"),
+            (
+                "FDB_EXPORTER_FDB_TLS_CERT",
+                "/etc/foundationdb/tls/cert.pem",
+            ),
+            ("FDB_EXPORTER_FDB_TLS_KEY", "/etc/foundationdb/tls/key.pem"),
+            ("FDB_EXPORTER_FDB_TLS_CA", "/etc/foundationdb/tls/ca.pem"),
+            ("FDB_EXPORTER_DELAY", "30s"),
+            ("FDB_EXPORTER_SCRAPE_JITTER", "0.1"),
+            ("FDB_TIMEOUT", "10"),
+            ("FDB_EXPORTER_FETCH_RETRIES", "3"),
+            ("FDB_EXPORTER_OVERALL_TIMEOUT", "2m"),
+            ("FDB_EXPORTER_PROCESS_REFRESH_MULTIPLIER", "4"),
+            ("FDB_CLUSTER_NAME", "production"),
+            ("FDB_EXPORTER_CONST_LABEL", "environment=prod,site=eu"),
+            ("FDB_EXPORTER_RESET_MISSING_DATA", "true"),
+            ("FDB_EXPORTER_DISABLE", "process,qos"),
+            ("FDB_EXPORTER_PROCESS_INCLUDE", "^storage"),
+            ("FDB_EXPORTER_SMOOTHING_DECAY", "0.5"),
+            ("FDB_EXPORTER_RECOVERY_WINDOW", "10m"),
+            ("FDB_EXPORTER_WARMUP", "1m"),
+            ("FDB_EXPORTER_HEALTH_WEIGHTS", "lag=0"),
+            ("FDB_EXPORTER_LOW_SPACE_THRESHOLD", "5"),
+            (
+                "FDB_EXPORTER_EXPECTED_COORDINATORS",
+                "10.0.0.1:4500,fdb-coordinator-0.fdb.svc.cluster.local:4500:tls",
+            ),
+            ("FDB_EXPORTER_LENIENT", "true"),
+            ("FDB_EXPORTER_LOG_FORMAT", "json"),
+            ("FDB_EXPORTER_LOG_LEVEL", "debug"),
+            ("FDB_EXPORTER_SOURCE", "fdbcli"),
+            ("FDB_EXPORTER_STATUS_KEY", "\\xff\\xff/status/json"),
+            ("FDB_EXPORTER_SHARD_STATS", "true"),
+            ("FDB_EXPORTER_DEBUG_ENDPOINTS", "true"),
+            ("FDB_EXPORTER_MAX_RESPONSE_SIZE", "10000000"),
+            ("FDB_EXPORTER_DUMP_FILE", "/var/lib/node_exporter/fdb.prom"),
+            ("FDB_EXPORTER_MODE", "proxy"),
+        ] {
+            std::env::set_var(format!("{}{}", PREFIX, name), value);
+        }
+        #[cfg(feature = "remote-write")]
+        std::env::set_var(
+            format!("{}FDB_EXPORTER_REMOTE_WRITE", PREFIX),
+            "http://mimir:9009/api/v1/push",
+        );
+        // Values are read when the variable names are set
+        let command = CommandArgs::command().mut_args(|arg| {
+            let name = arg
+                .get_env()
+                .unwrap_or_else(|| panic!("--{} has no environment variable", arg.get_id()))
+                .to_str()
+                .unwrap();
+            let name: &'static str = Box::leak(format!("{}{}", PREFIX, name).into_boxed_str());
+            arg.env(name)
+        });
+
+        let matches = command.try_get_matches_from(["fdbexporter"]).unwrap();
+        let args = CommandArgs::from_arg_matches(&matches).unwrap();
+
+        assert_eq!(args.port, 1234);
+        assert_eq!(args.addr, Ipv4Addr::new(127, 0, 0, 1));
+        assert_eq!(args.tcp_backlog, 4096);
+        assert!(args.tcp_nodelay);
+        assert_eq!(
+            args.cluster.as_deref(),
+            Some(std::path::Path::new("/etc/foundationdb/fdb.cluster"))
+        );
+        assert_eq!(
+            args.fdb_tls_ca.as_deref(),
+            Some(std::path::Path::new("/etc/foundationdb/tls/ca.pem"))
+        );
+        assert_eq!(args.delay_sec, Duration::from_secs(30));
+        assert_eq!(args.scrape_jitter, 0.1);
+        assert_eq!(args.fdb_timeout, Duration::from_secs(10));
+        assert_eq!(args.fetch_retries, Some(3));
+        assert_eq!(args.overall_timeout, Some(Duration::from_secs(120)));
+        assert_eq!(args.process_refresh_multiplier, 4);
+        assert_eq!(args.cluster_name, "production");
+        assert_eq!(args.const_label.len(), 2);
+        assert!(args.reset_missing_data);
+        assert_eq!(args.disable, [MetricGroup::Process, MetricGroup::Qos]);
+        assert!(args.process_include.is_some());
+        assert!(args.process_exclude.is_none());
+        assert_eq!(args.smoothing_decay, Some(0.5));
+        assert_eq!(args.recovery_window, Duration::from_secs(600));
+        assert_eq!(args.warmup, Duration::from_secs(60));
+        assert_eq!(args.health_weights.lag, 0.0);
+        assert_eq!(args.low_space_threshold, 5.0);
+        assert_eq!(args.expected_coordinators.len(), 2);
+        assert!(args.expected_coordinators[1].tls);
+        assert!(args.lenient);
+        assert!(matches!(args.log_format, LogFormat::Json));
+        assert_eq!(args.log_level, LevelFilter::DEBUG);
+        assert_eq!(args.source, StatusSource::Fdbcli);
+        assert_eq!(args.status_key.0, STATUS_KEY);
+        assert!(args.shard_stats);
+        assert!(args.debug_endpoints);
+        assert_eq!(args.max_response_size, Some(10_000_000));
+        assert_eq!(
+            args.dump_file.as_deref(),
+            Some(std::path::Path::new("/var/lib/node_exporter/fdb.prom"))
+        );
+        assert_eq!(args.mode, Mode::Proxy);
+        assert!(!args.once);
+        assert!(!args.validate);
+        #[cfg(feature = "remote-write")]
+        assert_eq!(
+            args.remote_write,
+            Some(hyper::Uri::from_static("http://mimir:9009/api/v1/push"))
+        );
+    }
+
+    #[test]
+    fn config_file_rejects_invalid_values() {
+        assert!(toml::from_str::<FileConfig>("unknown = 1").is_err());
+
+        for content in [
+            "fdb_timeout = 0",
+            "process_refresh_multiplier = 0",
+            "tcp_backlog = 0",
+            "scrape_jitter = 2.0",
+            r#"source = "unknown""#,
+        ] {
+            assert!(with_config_file(&["fdbexporter"], content).is_err());
+        }
+        assert!(CommandArgs::command()
+            .try_get_matches_from(["fdbexporter", "--tcp-backlog", "100000"])
+            .is_err());
+    }
+
+    #[test]
+    fn scrape_jitter_bounds() {
+        assert_eq!(parse_scrape_jitter("0.2"), Ok(0.2));
+        assert!(parse_scrape_jitter("1").is_err());
+        assert!(parse_scrape_jitter("-0.1").is_err());
+
+        let delay = Duration::from_secs(10);
+        assert_eq!(jittered(delay, 0.0), delay);
+        for _ in 0..100 {
+            let jittered = jittered(delay, 0.2);
+            assert!(jittered >= Duration::from_secs(8) && jittered <= Duration::from_secs(12));
+        }
+    }
+
+    #[test]
+    fn smoothing_decay_validation() {
+        assert_eq!(parse_smoothing_decay("0.8"), Ok(0.8));
+        assert_eq!(parse_smoothing_decay("0"), Ok(0.0));
+        assert!(parse_smoothing_decay("1").is_err());
+        assert!(parse_smoothing_decay("-0.5").is_err());
+        assert!(parse_smoothing_decay("high").is_err());
+    }
+
+    #[test]
+    fn status_keys() {
+        let key: StatusKey = "\\xff\\xff/status/json".parse().unwrap();
+        assert_eq!(key.0, STATUS_KEY);
+        assert_eq!(key.to_string(), "\\xff\\xff/status/json");
+        let key: StatusKey = "a\\\\b\\x0A".parse().unwrap();
+        assert_eq!(key.0, b"a\\b\n");
+        assert_eq!(key.to_string().parse(), Ok(key));
+
+        assert!("".parse::<StatusKey>().is_err());
+        assert!("\\xf".parse::<StatusKey>().is_err());
+        assert!("\\xzz".parse::<StatusKey>().is_err());
+        assert!("\\n".parse::<StatusKey>().is_err());
+    }
+
+    #[test]
+    fn vm_rss() {
+        let status = "Name:\tfdbexporter\nVmPeak:\t  20000 kB\nVmRSS:\t   12345 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(12345 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tfdbexporter\n"), None);
+    }
+
+    #[test]
+    fn const_labels() {
+        let label: ConstLabel = "environment=prod".parse().unwrap();
+        assert_eq!(label.name, "environment");
+        assert_eq!(label.value, "prod");
+        assert_eq!(
+            "url=http://host/?a=b".parse::<ConstLabel>().unwrap().value,
+            "http://host/?a=b"
+        );
+        assert!("environment".parse::<ConstLabel>().is_err());
+        assert!("=prod".parse::<ConstLabel>().is_err());
+        assert!("environment=".parse::<ConstLabel>().is_err());
+
+        let args = CommandArgs {
+            const_label: vec![label.clone(), "site=eu".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(args.const_labels().unwrap().len(), 2);
+        assert!(args.exporter().is_ok());
+        let args = CommandArgs {
+            const_label: vec![label.clone(), label],
+            ..Default::default()
+        };
+        assert!(args.const_labels().is_err());
+        let args = CommandArgs {
+            const_label: vec!["not-valid=1".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(args.exporter().is_err());
+        let args = CommandArgs {
+            const_label: vec!["cluster=x".parse().unwrap()],
+            ..Default::default()
+        };
+        assert!(args.exporter().is_err());
+    }
+
+    #[test]
+    fn low_space_threshold_validation() {
+        assert_eq!(parse_low_space_threshold("10"), Ok(10.0));
+        assert_eq!(parse_low_space_threshold("2.5"), Ok(2.5));
+        assert!(parse_low_space_threshold("101").is_err());
+        assert!(parse_low_space_threshold("-1").is_err());
+        assert!(parse_low_space_threshold("10%").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("15"), Ok(Duration::from_secs(15)));
+        assert_eq!(parse_duration("15s"), Ok(Duration::from_secs(15)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert!(parse_duration("fifteen").is_err());
+        assert!(parse_duration("15d").is_err());
+    }
+
+    #[cfg(feature = "remote-write")]
+    #[test]
+    fn remote_write_urls_must_be_http() {
+        assert!(parse_remote_write_url("http://localhost:9009/api/v1/push").is_ok());
+        assert!(parse_remote_write_url("https://localhost:9009/api/v1/push").is_err());
+        assert!(parse_remote_write_url("/api/v1/push").is_err());
+    }
+
+    #[cfg(feature = "remote-write")]
+    #[tokio::test]
+    async fn failed_pushes_back_off() {
+        assert_eq!(
+            remote_write_backoff(Duration::from_secs(15), 1),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            remote_write_backoff(Duration::from_secs(15), 10),
+            REMOTE_WRITE_MAX_BACKOFF
+        );
+
+        // Nothing listens on the port once the listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v1/push", listener.local_addr().unwrap());
+        drop(listener);
+        let exporter = Exporter::new();
+        let mut writer = RemoteWriter::new(parse_remote_write_url(&url).unwrap());
+        let errors = || {
+            exporter
+                .gather()
+                .iter()
+                .find(|family| family.get_name() == "fdb_exporter_remote_write_error_count")
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+        };
+
+        writer
+            .push(&exporter, Duration::from_secs(15), Duration::from_secs(5))
+            .await;
+        assert_eq!(errors(), Some(1.0));
+        // Skipped while backing off
+        writer
+            .push(&exporter, Duration::from_secs(15), Duration::from_secs(5))
+            .await;
+        assert_eq!(errors(), Some(1.0));
+        assert_eq!(writer.failures, 1);
+    }
 }
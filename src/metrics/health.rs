@@ -0,0 +1,188 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::status_models::cluster_recovery::ClusterRecoveryStateName;
+use crate::status_models::Status;
+
+/// Storage lag, in seconds, at which the lag component of the health score
+/// drops to 0. Past the 5 seconds MVCC window, reads at recent versions fail.
+const LAG_SCORE_LIMIT_SECONDS: f64 = 5.0;
+
+/// Weights of the components of `fdb_cluster_health_score`, parsed from a
+/// comma separated list such as `healthy=30,lag=10`. Components left out keep
+/// their default weight.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthWeights {
+    /// Database reported as healthy by the client
+    pub healthy: f64,
+    /// Recovery state is fully recovered
+    pub recovery: f64,
+    /// Ratio of the remaining replicas of the most at-risk data to the
+    /// replication factor of the redundancy mode
+    pub replicas: f64,
+    /// Headroom of the released transactions rate under the ratekeeper limit
+    pub qos: f64,
+    /// Worst storage data lag compared to [LAG_SCORE_LIMIT_SECONDS]
+    pub lag: f64,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        HealthWeights {
+            healthy: 30.0,
+            recovery: 20.0,
+            replicas: 20.0,
+            qos: 15.0,
+            lag: 15.0,
+        }
+    }
+}
+
+impl FromStr for HealthWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = HealthWeights::default();
+        for entry in s.split(',').filter(|entry| !entry.is_empty()) {
+            let (name, weight) = entry.split_once('=').ok_or_else(|| {
+                format!("Invalid health weight '{}', expected name=weight", entry)
+            })?;
+            let weight: f64 = weight
+                .parse()
+                .ok()
+                .filter(|weight: &f64| *weight >= 0.0 && weight.is_finite())
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid health weight '{}', expected a positive number",
+                        weight
+                    )
+                })?;
+            let component = match name {
+                "healthy" => &mut weights.healthy,
+                "recovery" => &mut weights.recovery,
+                "replicas" => &mut weights.replicas,
+                "qos" => &mut weights.qos,
+                "lag" => &mut weights.lag,
+                _ => {
+                    return Err(format!(
+                        "Invalid health component '{}', expected one of healthy, recovery, replicas, qos or lag",
+                        name
+                    ))
+                }
+            };
+            *component = weight;
+        }
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for HealthWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "healthy={},recovery={},replicas={},qos={},lag={}",
+            self.healthy, self.recovery, self.replicas, self.qos, self.lag
+        )
+    }
+}
+
+/// Number of replicas kept by a redundancy mode, modes with a replication
+/// spread over regions are not scored
+fn replication_factor(redundancy_mode: &str) -> Option<i64> {
+    match redundancy_mode {
+        "single" => Some(1),
+        "double" => Some(2),
+        "triple" | "three_data_hall" => Some(3),
+        _ => None,
+    }
+}
+
+/// Health score between 0 and 100 of the status, computed as
+/// `100 * sum(weight * component) / sum(weight)` where each component is
+/// between 0 (unhealthy) and 1 (healthy). Components missing from the status
+/// are left out of both sums, `None` is returned when none is available.
+pub fn health_score(status: &Status, weights: &HealthWeights) -> Option<f64> {
+    let cluster = status.cluster.as_ref();
+    let healthy = Some(status.client.database_status.healthy as u8 as f64);
+    let recovery = cluster
+        .and_then(|cluster| cluster.recovery_state.as_ref())
+        .map(|state| (state.name == ClusterRecoveryStateName::FullyRecovered) as u8 as f64);
+    let replicas = cluster.and_then(|cluster| {
+        let remaining = cluster
+            .data
+            .as_ref()?
+            .state
+            .as_ref()?
+            .min_replicas_remaining?;
+        let mode = cluster.configuration.as_ref()?.redundancy_mode.as_deref()?;
+        Some(remaining as f64 / replication_factor(mode)? as f64)
+    });
+    let qos = cluster.and_then(|cluster| cluster.qos.as_ref()).map(|qos| {
+        if qos.transactions_per_second_limit <= 0.0 {
+            return 0.0;
+        }
+        1.0 - qos.released_transactions_per_second / qos.transactions_per_second_limit
+    });
+    let lag = cluster
+        .and_then(|cluster| cluster.qos.as_ref()?.worst_data_lag_storage_server.as_ref())
+        .map(|lag| 1.0 - lag.seconds / LAG_SCORE_LIMIT_SECONDS);
+
+    let (total, weight) = [
+        (healthy, weights.healthy),
+        (recovery, weights.recovery),
+        (replicas, weights.replicas),
+        (qos, weights.qos),
+        (lag, weights.lag),
+    ]
+    .into_iter()
+    .filter_map(|(component, weight)| Some((component?.clamp(0.0, 1.0), weight)))
+    .fold((0.0, 0.0), |(total, weights), (component, weight)| {
+        (total + component * weight, weights + weight)
+    });
+    if weight == 0.0 {
+        return None;
+    }
+    Some((100.0 * total / weight).clamp(0.0, 100.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::parse_cluster_status;
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn weights_parsing() {
+        let weights: HealthWeights = "healthy=50,lag=0".parse().unwrap();
+        assert_eq!(weights.healthy, 50.0);
+        assert_eq!(weights.lag, 0.0);
+        assert_eq!(weights.qos, HealthWeights::default().qos);
+        assert_eq!(weights.to_string().parse::<HealthWeights>(), Ok(weights));
+
+        assert!("healthy".parse::<HealthWeights>().is_err());
+        assert!("healthy=-1".parse::<HealthWeights>().is_err());
+        assert!("latency=1".parse::<HealthWeights>().is_err());
+    }
+
+    #[test]
+    fn unhealthy_database_lowers_the_score() {
+        let mut status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let weights = HealthWeights::default();
+        let score = health_score(&status, &weights).unwrap();
+        assert!((0.0..=100.0).contains(&score));
+
+        status.client.database_status.healthy = false;
+        let unhealthy = health_score(&status, &weights).unwrap();
+        assert!(unhealthy < score);
+
+        let only_healthy = HealthWeights {
+            healthy: 1.0,
+            recovery: 0.0,
+            replicas: 0.0,
+            qos: 0.0,
+            lag: 0.0,
+        };
+        assert_eq!(health_score(&status, &only_healthy), Some(0.0));
+    }
+}
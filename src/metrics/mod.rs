@@ -1,19 +1,509 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use ::prometheus::proto::MetricFamily;
+use ::prometheus::Registry;
+use regex::Regex;
+use tracing::warn;
+
+use crate::fetcher::{FetchError, FetchTimings};
+use crate::status_models::address::FdbProcessAddress;
 use crate::status_models::Status;
 
+mod health;
 mod prometheus;
+#[cfg(feature = "remote-write")]
+pub mod remote_write;
+
+pub use self::health::HealthWeights;
+pub use self::prometheus::Metrics;
 
 /// Must be implemented on metrics which are updating exported metrics,
 /// this trait is useful to allow usage of feature flags to have various
 /// exporters (Prometheus, warp10...)
 pub trait MetricsConvertible {
-    fn to_metrics(&self, labels: &[&str]);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]);
 }
 
-/// Use the status to update metrics with new status given
-pub fn process_metrics(new_status: Status) {
-    let labels = vec![];
-    new_status.client.to_metrics(&labels);
-    if let Some(cluster) = new_status.cluster {
-        cluster.to_metrics(&labels);
+/// Groups of metrics which can be left out of the export, to keep the
+/// cardinality down on large clusters
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MetricGroup {
+    /// Per-process metrics (`fdb_cluster_process_*` except roles)
+    Process,
+    /// Per-machine metrics
+    Machine,
+    /// Per-role metrics of processes (`fdb_cluster_process_role_*`)
+    Role,
+    /// Quality of service metrics (`fdb_cluster_qos_*`)
+    Qos,
+}
+
+impl FromStr for MetricGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "process" => Ok(MetricGroup::Process),
+            "machine" => Ok(MetricGroup::Machine),
+            "role" => Ok(MetricGroup::Role),
+            "qos" => Ok(MetricGroup::Qos),
+            _ => Err(format!(
+                "Invalid metric group '{}', expected one of process, machine, role or qos",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MetricGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MetricGroup::Process => "process",
+            MetricGroup::Machine => "machine",
+            MetricGroup::Role => "role",
+            MetricGroup::Qos => "qos",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Options of [Exporter::process_metrics]
+#[derive(Clone, Debug)]
+pub struct MetricsOptions {
+    /// Value of the `cluster` label set on every cluster metric
+    pub cluster_name: String,
+    /// Remove the gauges exported from `cluster.data` instead of keeping
+    /// their previous values when the section is missing
+    pub reset_missing_data: bool,
+    /// Groups of metrics which are not exported
+    pub disabled_groups: HashSet<MetricGroup>,
+    /// When set, only processes whose id or address matches are exported
+    pub process_include: Option<Regex>,
+    /// Processes whose id or address matches are not exported, their series
+    /// are removed
+    pub process_exclude: Option<Regex>,
+    /// When set, noisy gauges (e.g. moving data, process CPU usage) are also
+    /// exported with a `_smoothed` suffix, averaged with this decay factor
+    /// between 0 and 1. The higher it is, the smoother the values are.
+    pub smoothing_decay: Option<f64>,
+    /// Cluster file used to connect, compared with the connection string
+    /// reported in the status to export `fdb_exporter_cluster_file_mismatch`
+    pub cluster_file: Option<PathBuf>,
+    /// Window over which generation changes are counted to export
+    /// `fdb_cluster_recoveries_in_window`
+    pub recovery_window: Duration,
+    /// Weights of the components of `fdb_cluster_health_score`
+    pub health_weights: HealthWeights,
+    /// Percentage of available kvstore space under which a storage server is
+    /// counted in `fdb_cluster_storage_servers_low_space`
+    pub low_space_threshold: f64,
+    /// When not empty, coordinators reported in the status are compared with
+    /// these to export `fdb_cluster_coordinator_drift`
+    pub expected_coordinators: Vec<FdbProcessAddress>,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        MetricsOptions {
+            cluster_name: String::from("default"),
+            reset_missing_data: false,
+            disabled_groups: HashSet::new(),
+            process_include: None,
+            process_exclude: None,
+            smoothing_decay: None,
+            cluster_file: None,
+            recovery_window: Duration::from_secs(5 * 60),
+            health_weights: HealthWeights::default(),
+            low_space_threshold: 10.0,
+            expected_coordinators: Vec::new(),
+        }
+    }
+}
+
+impl MetricsOptions {
+    pub fn is_enabled(&self, group: MetricGroup) -> bool {
+        !self.disabled_groups.contains(&group)
+    }
+
+    /// Whether metrics of the process are exported according to
+    /// [MetricsOptions::process_include] and [MetricsOptions::process_exclude]
+    pub fn is_process_included(&self, process_id: &str, address: &str) -> bool {
+        let matches = |regex: &Regex| regex.is_match(process_id) || regex.is_match(address);
+        let included = match &self.process_include {
+            Some(regex) => matches(regex),
+            None => true,
+        };
+        included && !self.process_exclude.as_ref().is_some_and(matches)
+    }
+}
+
+/// Connection string of the content of a cluster file, comments and blank
+/// lines are skipped
+fn cluster_file_connection_string(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn check_cluster_file(metrics: &Metrics, cluster_file: &Path, connection_string: &str) {
+    match std::fs::read_to_string(cluster_file) {
+        Ok(content) => metrics.set_cluster_file_mismatch(
+            cluster_file_connection_string(&content) != Some(connection_string),
+        ),
+        Err(e) => warn!(
+            "Couldn't read cluster file {}: {}",
+            cluster_file.display(),
+            e
+        ),
+    }
+}
+
+/// Labels set on histogram buckets and summary quantiles at exposition
+const RESERVED_LABEL_NAMES: &[&str] = &["le", "quantile"];
+
+/// Whether `name` matches `[a-zA-Z_][a-zA-Z0-9_]*` without the `__` prefix
+/// reserved by Prometheus
+fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
+
+/// Outcome of the last status fetch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LastFetch {
+    /// Whether the last fetch was both fetched and parsed
+    pub succeeded: bool,
+    last_success: Option<Instant>,
+}
+
+impl LastFetch {
+    /// Time elapsed since the last successful fetch, `None` before the first one
+    pub fn age(&self) -> Option<Duration> {
+        self.last_success.map(|instant| instant.elapsed())
+    }
+}
+
+/// Exported metrics along with the [Registry] they are registered on, each
+/// exporter has its own registry so several of them can run in the same
+/// process without sharing series
+pub struct Exporter {
+    registry: Registry,
+    metrics: Metrics,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Exporter {
+    pub fn new() -> Self {
+        Self::with_const_labels(HashMap::new()).expect("no constant label to validate")
+    }
+
+    /// Exporter whose metrics all carry the given constant labels (e.g.
+    /// `environment=prod`)
+    ///
+    /// Fails if a label name is not a valid Prometheus label name, or is
+    /// already set by the exporter itself such as `cluster`, `le` or
+    /// `quantile`
+    pub fn with_const_labels(labels: HashMap<String, String>) -> ::prometheus::Result<Self> {
+        if let Some(name) = labels.keys().find(|name| !is_valid_label_name(name)) {
+            return Err(::prometheus::Error::Msg(format!(
+                "'{}' is not a valid label name",
+                name
+            )));
+        }
+        let names: Vec<String> = labels.keys().cloned().collect();
+        let registry = if labels.is_empty() {
+            Registry::new()
+        } else {
+            Registry::new_custom(None, Some(labels))?
+        };
+        // Metrics names are static, registering them only fails on duplicates
+        let metrics = Metrics::new(&registry).expect("metrics are registered once");
+        let clash = names.into_iter().find(|name| {
+            RESERVED_LABEL_NAMES.contains(&name.as_str()) || metrics.label_names().contains(name)
+        });
+        if let Some(name) = clash {
+            return Err(::prometheus::Error::Msg(format!(
+                "'{}' is already a label of the exporter metrics",
+                name
+            )));
+        }
+        Ok(Exporter { registry, metrics })
+    }
+
+    /// Registry on which every metric of the exporter is registered
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Collect the current value of every metric, to be encoded
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        self.registry.gather()
+    }
+
+    /// Count a reload of the cluster file, done by the exporter on SIGHUP
+    pub fn count_config_reload(&self) {
+        self.metrics.count_config_reload();
+    }
+
+    /// Count an iteration of the exporter status fetch loop
+    pub fn count_loop_iteration(&self) {
+        self.metrics.count_loop_iteration();
+    }
+
+    /// Record the number of transaction attempts a status read took
+    pub fn observe_fetch_attempts(&self, attempts: u32) {
+        self.metrics.observe_fetch_attempts(attempts);
+    }
+
+    /// Record the size of the raw status, on every successful read
+    pub fn set_status_bytes(&self, bytes: usize) {
+        self.metrics.set_status_bytes(bytes);
+    }
+
+    /// Record the time spent fetching and converting the status in a loop
+    /// iteration, which delays the next fetch on top of the configured delay
+    pub fn set_loop_lag(&self, lag: Duration) {
+        self.metrics.set_loop_lag(lag);
+    }
+
+    /// Record the time the first read version of a freshly opened database
+    /// took, see [crate::connect_database]
+    pub fn set_coordinator_connect(&self, duration: Duration) {
+        self.metrics.set_coordinator_connect(duration);
+    }
+
+    /// Record the time spent reading the raw status, from the transaction or
+    /// `fdbcli`, and parsing it, which dominates the fetch on large clusters.
+    /// Steps which didn't run keep their previous value.
+    pub fn set_fetch_timings(&self, timings: &FetchTimings) {
+        self.metrics.set_fetch_timings(timings);
+    }
+
+    /// Record the resident memory of the exporter process
+    pub fn set_resident_memory(&self, bytes: u64) {
+        self.metrics.set_resident_memory(bytes);
+    }
+
+    /// Count a status fetch, `success` when it was both fetched and parsed
+    pub fn count_fetch(&self, success: bool) {
+        self.metrics.count_fetch(success);
+    }
+
+    /// Outcome of the last status fetch counted with [Exporter::count_fetch]
+    pub fn last_fetch(&self) -> LastFetch {
+        self.metrics.last_fetch()
+    }
+
+    /// Count a status whose conversion to metrics panicked, the exporter keeps
+    /// running with the metrics set before the panic
+    pub fn count_conversion_panic(&self) {
+        self.metrics.count_conversion_panic();
+    }
+
+    /// Count a failed write of the metrics to a file, e.g. for a textfile
+    /// collector
+    pub fn count_dump_error(&self) {
+        self.metrics.count_dump_error();
+    }
+
+    /// Count a metrics response larger than the maximum size it should have,
+    /// usually the sign of a series cardinality explosion
+    pub fn count_response_truncated(&self) {
+        self.metrics.count_response_truncated();
+    }
+
+    /// Count a failed push of the metrics to a remote-write endpoint
+    pub fn count_remote_write_error(&self) {
+        self.metrics.count_remote_write_error();
+    }
+
+    /// Count a failed status fetch by kind of error
+    pub fn count_fetch_error(&self, error: &FetchError) {
+        error.to_metrics(&self.metrics, &[]);
+    }
+
+    /// Export the shard sizes read with [crate::fetch_shard_sizes], labelled
+    /// with [MetricsOptions::cluster_name]
+    pub fn process_shard_sizes(&self, sizes: &[u64], options: &MetricsOptions) {
+        prometheus::cluster_data::shard_sizes_to_metrics(
+            &self.metrics,
+            &[options.cluster_name.as_str()],
+            sizes,
+        );
+    }
+
+    /// Use the status to update metrics with new status given
+    ///
+    /// The status is only borrowed so it can be shared (e.g. behind an `Arc`)
+    /// with other consumers without being cloned. Every cluster-level metric
+    /// is labelled with [MetricsOptions::cluster_name], groups listed in
+    /// [MetricsOptions::disabled_groups] are not exported.
+    ///
+    /// When the status has no `cluster.data` section (e.g. early in a recovery),
+    /// `fdb_cluster_data_present` is set to 0 and, if
+    /// [MetricsOptions::reset_missing_data] is set, the gauges exported from that
+    /// section are removed instead of keeping their previous values.
+    pub fn process_metrics(&self, new_status: &Status, options: &MetricsOptions) {
+        let metrics = &self.metrics;
+        metrics.reset_last_parse_error();
+
+        if new_status.skipped_objects > 0 {
+            metrics.count_partial_parse();
+        }
+
+        let labels = [options.cluster_name.as_str()];
+        new_status.client.to_metrics(metrics, &labels);
+        prometheus::cluster::coordinators_to_metrics(
+            metrics,
+            &labels,
+            new_status,
+            &options.expected_coordinators,
+        );
+        if let Some(skew) = new_status.max_clock_skew_seconds() {
+            metrics.set_max_clock_skew(&labels, skew);
+        }
+        if let Some(score) = health::health_score(new_status, &options.health_weights) {
+            metrics.set_health_score(&labels, score);
+        }
+        if let Some(cluster) = &new_status.cluster {
+            cluster.to_metrics_with(metrics, &labels, options);
+        }
+        if new_status
+            .cluster
+            .as_ref()
+            .and_then(|cluster| cluster.data.as_ref())
+            .is_none()
+        {
+            prometheus::cluster_data::missing_cluster_data(
+                metrics,
+                &labels,
+                options.reset_missing_data,
+            );
+        }
+        let connection_string = new_status
+            .cluster
+            .as_ref()
+            .and_then(|cluster| cluster.connection_string.as_deref());
+        if let (Some(cluster_file), Some(connection_string)) =
+            (&options.cluster_file, connection_string)
+        {
+            check_cluster_file(metrics, cluster_file, connection_string);
+        }
+        if new_status
+            .cluster
+            .as_ref()
+            .and_then(|cluster| cluster.recovery_state.as_ref())
+            .is_none()
+        {
+            prometheus::cluster_recovery::missing_recovery_state(metrics, &labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_string_of_cluster_file() {
+        assert_eq!(
+            cluster_file_connection_string("docker:docker@172.19.0.2:4500\n"),
+            Some("docker:docker@172.19.0.2:4500")
+        );
+        assert_eq!(
+            cluster_file_connection_string("# Managed by ops\n\n  db:abc@10.0.0.1:4500  \n"),
+            Some("db:abc@10.0.0.1:4500")
+        );
+        assert_eq!(cluster_file_connection_string("# empty\n"), None);
+    }
+
+    #[test]
+    fn exporters_do_not_share_metrics() {
+        let fetch_total = |exporter: &Exporter| {
+            exporter
+                .gather()
+                .iter()
+                .find(|family| family.get_name() == "fdb_exporter_fetch_total")
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+        };
+        let first = Exporter::new();
+        let second = Exporter::new();
+
+        first.count_fetch(true);
+        first.count_fetch(true);
+        second.count_fetch(false);
+
+        assert_eq!(fetch_total(&first), Some(2.0));
+        assert_eq!(fetch_total(&second), Some(1.0));
+    }
+
+    #[test]
+    fn const_labels_on_every_metric() {
+        let labels = HashMap::from([("environment".to_string(), "prod".to_string())]);
+        let exporter = Exporter::with_const_labels(labels).unwrap();
+        exporter.count_fetch(true);
+        let families = exporter.gather();
+        assert!(!families.is_empty());
+        for metric in families.iter().flat_map(|family| family.get_metric()) {
+            assert!(metric
+                .get_label()
+                .iter()
+                .any(|label| label.get_name() == "environment" && label.get_value() == "prod"));
+        }
+
+        for name in [
+            "",
+            "1st",
+            "with-dash",
+            "__reserved",
+            "cluster",
+            "process_id",
+            "le",
+        ] {
+            let labels = HashMap::from([(name.to_string(), "value".to_string())]);
+            assert!(Exporter::with_const_labels(labels).is_err());
+        }
+    }
+
+    #[test]
+    fn status_not_found_after_missing_recovery_state() {
+        let none_during_recovery = |exporter: &Exporter| {
+            exporter
+                .gather()
+                .iter()
+                .find(|family| {
+                    family.get_name() == "fdb_exporter_status_none_during_recovery_count"
+                })
+                .map(|family| family.get_metric()[0].get_counter().get_value())
+        };
+        let mut status = crate::fetcher::parse_cluster_status(include_bytes!(
+            "../../tests/fixtures/simple_fdb.json"
+        ))
+        .unwrap();
+        let exporter = Exporter::new();
+        let options = MetricsOptions::default();
+
+        exporter.process_metrics(&status, &options);
+        exporter.count_fetch_error(&FetchError::StatusNotFound);
+        assert_eq!(none_during_recovery(&exporter), Some(0.0));
+
+        status.cluster.as_mut().unwrap().recovery_state = None;
+        exporter.process_metrics(&status, &options);
+        exporter.count_fetch_error(&FetchError::StatusNotFound);
+        assert_eq!(none_during_recovery(&exporter), Some(1.0));
     }
 }
@@ -1,62 +1,168 @@
+use super::{Metrics, MetricsRegistry, CLUSTER_LABELS};
 use crate::metrics::MetricsConvertible;
-use crate::status_models::client::ClientStatus;
-use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, register_int_gauge_vec, IntGauge, IntGaugeVec};
-
-lazy_static! {
-    static ref P_CLIENT_TIMESTAMP: IntGauge =
-        register_int_gauge!("fdb_client_timestamp", "Client timestamp when last fetched").unwrap();
-    static ref P_CLIENT_COORDINATORS_COUNT: IntGauge = register_int_gauge!(
-        "fdb_client_coordinators_count",
-        "Number of coordinators registered in client fdb.cluster"
-    )
-    .unwrap();
-    static ref P_CLIENT_COORDINATOR_REACHABLE: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_client_coordinator_reachable",
-        "Whether the coordinator is reachable",
-        &["address"],
-    )
-    .unwrap();
-    static ref P_CLIENT_QUORUM_REACHABLE: IntGauge = register_int_gauge!(
-        "fdb_client_quorum_reachable",
-        "The quorum of coordinators is reachable"
-    )
-    .unwrap();
-    static ref P_CLIENT_MESSAGES_COUNT: IntGauge = register_int_gauge!(
-        "fdb_client_messages_count",
-        "Number of messages available when fetching status",
-    )
-    .unwrap();
-    static ref P_CLIENT_DATABASE_AVAILABLE: IntGauge = register_int_gauge!(
-        "fdb_database_available",
-        "Database can receive request (0=unavailable)"
-    )
-    .unwrap();
-    static ref P_CLIENT_DATABASE_HEALTHY: IntGauge =
-        register_int_gauge!("fdb_database_healthy", "Database healthiness (0=unhealthy)").unwrap();
+use crate::status_models::client::{ClientCoordinator, ClientStatus};
+use prometheus::{register_int_gauge_vec_with_registry, IntGaugeVec};
+
+/// Metrics of [ClientStatus]
+pub struct ClientMetrics {
+    timestamp: IntGaugeVec,
+    coordinators_count: IntGaugeVec,
+    coordinator_reachable: IntGaugeVec,
+    quorum_reachable: IntGaugeVec,
+    coordinators_quorum_reachable: IntGaugeVec,
+    messages_count: IntGaugeVec,
+    database_available: IntGaugeVec,
+    database_healthy: IntGaugeVec,
+}
+
+impl ClientMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ClientMetrics {
+            timestamp: register_int_gauge_vec_with_registry!(
+                "fdb_client_timestamp",
+                "Client timestamp when last fetched",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinators_count: register_int_gauge_vec_with_registry!(
+                "fdb_client_coordinators_count",
+                "Number of coordinators registered in client fdb.cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinator_reachable: register_int_gauge_vec_with_registry!(
+                "fdb_client_coordinator_reachable",
+                "Whether the coordinator is reachable",
+                &["cluster", "address"],
+                registry
+            )?,
+            quorum_reachable: register_int_gauge_vec_with_registry!(
+                "fdb_client_quorum_reachable",
+                "Whether the client reached a quorum of coordinators, as reported by the quorum_reachable flag of the status (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinators_quorum_reachable: register_int_gauge_vec_with_registry!(
+                "fdb_client_coordinators_quorum_reachable",
+                "Whether more than half of the coordinators are reachable, counted from the reachable flag of each coordinator (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            messages_count: register_int_gauge_vec_with_registry!(
+                "fdb_client_messages_count",
+                "Number of messages available when fetching status",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            database_available: register_int_gauge_vec_with_registry!(
+                "fdb_database_available",
+                "Database can receive request (0=unavailable)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            database_healthy: register_int_gauge_vec_with_registry!(
+                "fdb_database_healthy",
+                "Database healthiness (0=unhealthy)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Whether strictly more than half of the coordinators are reachable, e.g. 3
+/// out of 4 are needed, no coordinator at all is never a majority
+fn majority_reachable(coordinators: &[ClientCoordinator]) -> bool {
+    let reachable = coordinators.iter().filter(|c| c.reachable).count();
+    reachable > coordinators.len() / 2
 }
 
 impl MetricsConvertible for ClientStatus {
-    fn to_metrics(&self, _: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
         if let Some(timestamp) = self.timestamp {
-            P_CLIENT_TIMESTAMP.set(timestamp)
+            metrics
+                .client
+                .timestamp
+                .with_label_values(labels)
+                .set(timestamp)
         }
 
         let coordinators_count = self.coordinators.coordinators.len() as i64;
-        P_CLIENT_COORDINATORS_COUNT.set(coordinators_count);
+        metrics
+            .client
+            .coordinators_count
+            .with_label_values(labels)
+            .set(coordinators_count);
 
         for coordinator in &self.coordinators.coordinators {
             let addr = coordinator.address.to_string();
-            P_CLIENT_COORDINATOR_REACHABLE
-                .with_label_values(&[(addr.as_str())])
+            let coordinator_labels = [labels, &[addr.as_str()]].concat();
+            metrics
+                .client
+                .coordinator_reachable
+                .with_label_values(&coordinator_labels)
                 .set(coordinator.reachable as i64);
         }
 
-        P_CLIENT_QUORUM_REACHABLE.set(self.coordinators.quorum_reachable as i64);
+        metrics
+            .client
+            .quorum_reachable
+            .with_label_values(labels)
+            .set(self.coordinators.quorum_reachable as i64);
+        metrics
+            .client
+            .coordinators_quorum_reachable
+            .with_label_values(labels)
+            .set(majority_reachable(&self.coordinators.coordinators) as i64);
 
-        P_CLIENT_MESSAGES_COUNT.set(self.messages.len() as i64);
+        metrics
+            .client
+            .messages_count
+            .with_label_values(labels)
+            .set(self.messages.len() as i64);
+
+        metrics
+            .client
+            .database_healthy
+            .with_label_values(labels)
+            .set(self.database_status.healthy as i64);
+        metrics
+            .client
+            .database_available
+            .with_label_values(labels)
+            .set(self.database_status.available as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coordinators(reachable: &[bool]) -> Vec<ClientCoordinator> {
+        reachable
+            .iter()
+            .enumerate()
+            .map(|(i, reachable)| {
+                serde_json::from_value(serde_json::json!({
+                    "address": format!("10.0.0.{}:4500", i),
+                    "reachable": reachable,
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
 
-        P_CLIENT_DATABASE_HEALTHY.set(self.database_status.healthy as i64);
-        P_CLIENT_DATABASE_AVAILABLE.set(self.database_status.available as i64);
+    #[test]
+    fn strict_majority() {
+        assert!(!majority_reachable(&[]));
+        assert!(majority_reachable(&coordinators(&[true, true, false])));
+        assert!(!majority_reachable(&coordinators(&[true, false, false])));
+        // Half of an even count is not a majority
+        assert!(!majority_reachable(&coordinators(&[
+            true, true, false, false
+        ])));
+        assert!(majority_reachable(&coordinators(&[
+            true, true, true, false
+        ])));
     }
 }
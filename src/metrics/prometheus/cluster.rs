@@ -1,88 +1,1148 @@
+use super::{
+    cluster_clients, cluster_process, cluster_process_role, cluster_qos, remove_cluster_series,
+    Metrics, MetricsRegistry, CLUSTER_LABELS,
+};
+use crate::metrics::{MetricGroup, MetricsOptions};
+use crate::status_models::address::FdbProcessAddress;
 use crate::status_models::cluster::ClusterStatus;
+use crate::status_models::cluster_configuration::ClusterConfigurationRegion;
+use crate::status_models::cluster_process::{ClusterProcess, ProcessId};
+use crate::status_models::cluster_process_role::{ClusterProcessRole, LatencyStats};
+use crate::status_models::Status;
 use crate::{metrics::MetricsConvertible, status_models::cluster_process::ClusterClassType};
-use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, register_int_gauge_vec, IntGauge, IntGaugeVec};
-
-lazy_static! {
-    static ref P_CLUSTER_MACHINES_COUNT: IntGauge = register_int_gauge!(
-        "fdb_cluster_machines_count",
-        "Number of machines available in the cluster"
-    )
-    .unwrap();
-    static ref P_CLUSTER_PROCESS_ROLES_COUNT: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_processes_roles",
-        "Current number of process running a specific role",
-        &["role"]
-    )
-    .unwrap();
-    static ref P_CLUSTER_GENERATION_COUNT: IntGauge =
-        register_int_gauge!("fdb_cluster_generation_count", "Number of generations").unwrap();
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_counter_vec_with_registry,
+    register_int_gauge_vec_with_registry, GaugeVec, IntCounterVec, IntGaugeVec,
+};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Number of processes converted by a single rayon task
+#[cfg(feature = "rayon")]
+const PROCESSES_CHUNK_SIZE: usize = 32;
+
+/// Cluster wide metrics of [ClusterStatus]
+pub struct ClusterMetrics {
+    machines_count: IntGaugeVec,
+    process_roles_count: IntGaugeVec,
+    role_count_by_dc: IntGaugeVec,
+    generation_count: IntGaugeVec,
+    recoveries_in_window: IntGaugeVec,
+    class_process_count: IntGaugeVec,
+    class_disk_free_bytes: IntGaugeVec,
+    worst_storage_read_latency: GaugeVec,
+    commit_latency_p50: GaugeVec,
+    commit_latency_p99: GaugeVec,
+    grv_latency_p50: GaugeVec,
+    grv_latency_p99: GaugeVec,
+    read_latency_p50: GaugeVec,
+    storage_servers_low_space: IntGaugeVec,
+    excluded_servers_with_data: IntGaugeVec,
+    cpu_cores_used: GaugeVec,
+    cpu_utilization: GaugeVec,
+    min_disk_free_ratio: GaugeVec,
+    protocol_version_info: IntGaugeVec,
+    connection_string_info: IntGaugeVec,
+    full_replication: IntGaugeVec,
+    can_clean_bounce: IntGaugeVec,
+    coordinators_configured: IntGaugeVec,
+    coordinators_reporting: IntGaugeVec,
+    coordinator_drift: IntGaugeVec,
+    role_migrations: IntCounterVec,
+    class_shortfall: IntGaugeVec,
+    region_info: IntGaugeVec,
+    health_score: GaugeVec,
+    max_clock_skew: IntGaugeVec,
+}
+
+impl ClusterMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ClusterMetrics {
+            machines_count: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_machines_count",
+                "Number of machines available in the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            process_roles_count: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_processes_roles",
+                "Current number of process running a specific role",
+                &["cluster", "role"],
+                registry
+            )?,
+            role_count_by_dc: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_role_count",
+                "Current number of processes running a specific role in a datacenter, the datacenter is empty on single-region clusters",
+                &["cluster", "role", "dcid"],
+                registry
+            )?,
+            generation_count: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_generation_count",
+                "Number of generations",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recoveries_in_window: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_recoveries_in_window",
+                "Number of generation changes seen within the recovery window (5 minutes by default)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            class_process_count: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_class_processes",
+                "Number of processes of a class type",
+                &["cluster", "class_type"],
+                registry
+            )?,
+            class_disk_free_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_class_disk_free_bytes",
+                "Sum of the disk free bytes of processes of a class type",
+                &["cluster", "class_type"],
+                registry
+            )?,
+            worst_storage_read_latency: register_gauge_vec_with_registry!(
+                "fdb_cluster_worst_storage_read_latency_seconds",
+                "Highest p99 read latency among storage roles",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            commit_latency_p50: register_gauge_vec_with_registry!(
+                "fdb_cluster_commit_latency_p50_seconds",
+                "Highest median commit latency among commit proxies, from roles[].commit_latency_statistics.median",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            commit_latency_p99: register_gauge_vec_with_registry!(
+                "fdb_cluster_commit_latency_p99_seconds",
+                "Highest p99 commit latency among commit proxies, from roles[].commit_latency_statistics.p99",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            grv_latency_p50: register_gauge_vec_with_registry!(
+                "fdb_cluster_grv_latency_p50_seconds",
+                "Highest median latency of default priority GRVs among GRV proxies, from roles[].grv_latency_statistics.default.median",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            grv_latency_p99: register_gauge_vec_with_registry!(
+                "fdb_cluster_grv_latency_p99_seconds",
+                "Highest p99 latency of default priority GRVs among GRV proxies, from roles[].grv_latency_statistics.default.p99",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            read_latency_p50: register_gauge_vec_with_registry!(
+                "fdb_cluster_read_latency_p50_seconds",
+                "Highest median read latency among storage roles, from roles[].read_latency_statistics.median",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            storage_servers_low_space: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_storage_servers_low_space",
+                "Number of storage servers whose available kvstore space is below threshold_pct percent of their total space",
+                &["cluster", "threshold_pct"],
+                registry
+            )?,
+            excluded_servers_with_data: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_excluded_servers_with_data",
+                "Number of storage servers of excluded processes which still hold data",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            cpu_cores_used: register_gauge_vec_with_registry!(
+                "fdb_cluster_cpu_cores_used_total",
+                "Number of cores used by all processes of the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            cpu_utilization: register_gauge_vec_with_registry!(
+                "fdb_cluster_cpu_utilization_avg",
+                "Average logical core utilization of the machines of the cluster, between 0 and 1",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            min_disk_free_ratio: register_gauge_vec_with_registry!(
+                "fdb_cluster_min_disk_free_ratio",
+                "Lowest ratio of free to total disk space among the processes of the cluster, between 0 and 1",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            protocol_version_info: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_protocol_version_info",
+                "Protocol version negotiated by the cluster, with the FoundationDB version it encodes",
+                &["cluster", "protocol_version", "version"],
+                registry
+            )?,
+            connection_string_info: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_connection_string_info",
+                "Connection string reported by the cluster",
+                &["cluster", "connection_string"],
+                registry
+            )?,
+            full_replication: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_full_replication",
+                "Whether all data is fully replicated (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            can_clean_bounce: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_can_clean_bounce",
+                "Whether all processes can be restarted at once without data movement (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinators_configured: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_coordinators_configured",
+                "Number of coordinators the cluster is configured with",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinators_reporting: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_coordinators_reporting",
+                "Number of coordinators reachable by the exporter",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            coordinator_drift: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_coordinator_drift",
+                "Number of coordinators reported in the status but not expected, plus the expected ones not reported",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            role_migrations: register_int_counter_vec_with_registry!(
+                "fdb_cluster_role_migrations_total",
+                "Number of times a singleton role moved to another process",
+                &["cluster", "role"],
+                registry
+            )?,
+            class_shortfall: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_class_shortfall",
+                "Number of processes missing to reach the configured count of a role",
+                &["cluster", "role"],
+                registry
+            )?,
+            region_info: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_region_info",
+                "Datacenters of the configured regions of a multi-region cluster",
+                &["cluster", "dcid", "priority", "satellite", "satellite_logs"],
+                registry
+            )?,
+            health_score: register_gauge_vec_with_registry!(
+                "fdb_cluster_health_score",
+                "Weighted health score of the cluster, between 0 (unhealthy) and 100",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            max_clock_skew: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_max_clock_skew_seconds",
+                "Difference between the clocks of the client and of the cluster controller reported in the status",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Export the datacenters of the configured regions, single-region clusters
+/// are skipped
+fn regions_to_metrics(metrics: &Metrics, labels: &[&str], regions: &[ClusterConfigurationRegion]) {
+    if regions.len() < 2 {
+        return;
+    }
+    for region in regions {
+        for datacenter in &region.datacenters {
+            let priority = datacenter.priority.to_string();
+            let satellite_logs = datacenter
+                .satellite_logs
+                .or(region.satellite_logs)
+                .map(|logs| logs.to_string())
+                .unwrap_or_default();
+            let region_labels = [
+                labels,
+                &[
+                    datacenter.id.as_str(),
+                    priority.as_str(),
+                    if datacenter.satellite == 1 { "1" } else { "0" },
+                    satellite_logs.as_str(),
+                ],
+            ]
+            .concat();
+            metrics
+                .cluster
+                .region_info
+                .with_label_values(&region_labels)
+                .set(1);
+        }
+    }
+}
+
+/// Export the number of configured coordinators along with the number of
+/// reachable ones, a coordinator which doesn't report makes them differ.
+/// When `expected` is not empty, the coordinators missing from either list
+/// are counted in `fdb_cluster_coordinator_drift`.
+pub fn coordinators_to_metrics(
+    metrics: &Metrics,
+    labels: &[&str],
+    status: &Status,
+    expected: &[FdbProcessAddress],
+) {
+    let reporting = status
+        .client
+        .coordinators
+        .coordinators
+        .iter()
+        .filter(|coordinator| coordinator.reachable)
+        .count();
+    metrics
+        .cluster
+        .coordinators_reporting
+        .with_label_values(labels)
+        .set(reporting as i64);
+
+    let configured = status
+        .cluster
+        .as_ref()
+        .and_then(|cluster| cluster.configuration.as_ref())
+        .and_then(|configuration| configuration.coordinators_count);
+    if let Some(configured) = configured {
+        metrics
+            .cluster
+            .coordinators_configured
+            .with_label_values(labels)
+            .set(configured as i64);
+    }
+
+    if !expected.is_empty() {
+        let reported: Vec<&FdbProcessAddress> = status
+            .client
+            .coordinators
+            .coordinators
+            .iter()
+            .map(|coordinator| &coordinator.address)
+            .collect();
+        let unexpected = reported
+            .iter()
+            .filter(|address| !expected.iter().any(|e| e.same_endpoint(address)))
+            .count();
+        let missing = expected
+            .iter()
+            .filter(|e| !reported.iter().any(|address| e.same_endpoint(address)))
+            .count();
+        metrics
+            .cluster
+            .coordinator_drift
+            .with_label_values(labels)
+            .set((unexpected + missing) as i64);
+    }
+}
+
+impl Metrics {
+    /// Set the health score computed from the whole status
+    pub fn set_health_score(&self, labels: &[&str], score: f64) {
+        self.cluster
+            .health_score
+            .with_label_values(labels)
+            .set(score);
+    }
+
+    /// Set the largest difference between the clocks reported in the status
+    pub fn set_max_clock_skew(&self, labels: &[&str], seconds: i64) {
+        self.cluster
+            .max_clock_skew
+            .with_label_values(labels)
+            .set(seconds);
+    }
+}
+
+/// Build [super::PROCESS_LABELS] values of a process, processes without
+/// machine_id are not exported
+fn process_labels(
+    cluster_labels: &[&str],
+    process_id: &ProcessId,
+    process: &ClusterProcess,
+) -> Option<Vec<String>> {
+    let machine_id = process.machine_id.as_ref()?;
+    let class_type = process
+        .class_type
+        .as_ref()
+        .unwrap_or(&ClusterClassType::Unset)
+        .to_string();
+    let mut labels: Vec<String> = cluster_labels.iter().map(|l| l.to_string()).collect();
+    labels.extend([
+        machine_id.0.clone(),
+        process_id.0.clone(),
+        class_type,
+        process.address.to_string(),
+    ]);
+    Some(labels)
+}
+
+fn process_to_metrics(
+    metrics: &Metrics,
+    (process, labels): &(&ClusterProcess, Vec<String>),
+    options: &MetricsOptions,
+    default_engine: Option<&str>,
+) {
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+    if options.is_enabled(MetricGroup::Process) {
+        process.to_metrics(metrics, &labels);
+        if let Some(decay) = options.smoothing_decay {
+            cluster_process::smoothed_to_metrics(metrics, process, &labels, decay);
+        }
+    }
+    if options.is_enabled(MetricGroup::Role) {
+        for role in &process.roles {
+            role.to_metrics(metrics, &labels);
+        }
+        cluster_process_role::storage_engines_to_metrics(
+            metrics,
+            &process.roles,
+            &labels,
+            default_engine,
+        );
+    }
 }
 
 impl MetricsConvertible for ClusterStatus {
-    fn to_metrics(&self, _: &[&str]) {
-        P_CLUSTER_MACHINES_COUNT.set(self.machines.len() as i64);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        self.to_metrics_with(metrics, labels, &MetricsOptions::default());
+    }
+}
 
-        for (machine_id, machine) in &self.machines {
-            let datacenter_id = machine
-                .datacenter_id
-                .clone()
-                .unwrap_or(String::from("default"));
-            let labels = [
-                machine_id.0.as_str(),
-                datacenter_id.as_str(),
-                machine.address.as_str(),
-            ];
-            machine.to_metrics(&labels);
+impl ClusterStatus {
+    /// Same as [MetricsConvertible::to_metrics], groups disabled in `options`
+    /// are skipped
+    pub fn to_metrics_with(&self, metrics: &Metrics, labels: &[&str], options: &MetricsOptions) {
+        metrics
+            .cluster
+            .machines_count
+            .with_label_values(labels)
+            .set(self.machines.len() as i64);
+
+        if options.is_enabled(MetricGroup::Machine) {
+            self.machines_to_metrics(metrics, labels);
         }
 
         if let Some(data) = &self.data {
-            data.to_metrics(&[]);
+            data.to_metrics(metrics, labels);
+            if let Some(decay) = options.smoothing_decay {
+                data.smoothed_to_metrics(metrics, labels, decay);
+            }
         }
 
-        for (process_id, process) in &self.processes {
-            let machine_id = match &process.machine_id {
-                Some(id) => id,
-                None => continue,
-            };
-            let class_type = process
-                .class_type
-                .as_ref()
-                .unwrap_or(&ClusterClassType::Unset)
-                .to_string();
-            let labels = [
-                machine_id.0.as_str(),
-                process_id.0.as_str(),
-                class_type.as_str(),
-                &process.address.to_string(),
-            ];
-            process.to_metrics(&labels);
-        }
-
-        for (role, count) in self.cluster_roles_count() {
-            P_CLUSTER_PROCESS_ROLES_COUNT
-                .with_label_values(&[&role.to_string()])
+        if options.is_enabled(MetricGroup::Process) || options.is_enabled(MetricGroup::Role) {
+            self.processes_to_metrics(metrics, labels, options);
+        }
+
+        let roles_count = self.cluster_roles_count();
+        // Datacenters may have been removed from the configuration, and role
+        // counts set back to auto
+        remove_cluster_series(&metrics.cluster.region_info, labels[0]);
+        remove_cluster_series(&metrics.cluster.class_shortfall, labels[0]);
+        if let Some(configuration) = &self.configuration {
+            regions_to_metrics(metrics, labels, &configuration.regions);
+            for (role, desired) in configuration.desired_roles_count() {
+                let actual = roles_count.get(&role).copied().unwrap_or(0) as i64;
+                let role = role.to_string();
+                let role_labels = [labels, &[role.as_str()]].concat();
+                metrics
+                    .cluster
+                    .class_shortfall
+                    .with_label_values(&role_labels)
+                    .set((desired - actual).max(0));
+            }
+        }
+
+        for (role, count) in roles_count {
+            let role = role.to_string();
+            let role_labels = [labels, &[role.as_str()]].concat();
+            metrics
+                .cluster
+                .process_roles_count
+                .with_label_values(&role_labels)
+                .set(count as i64);
+        }
+        // Roles move between datacenters
+        remove_cluster_series(&metrics.cluster.role_count_by_dc, labels[0]);
+        for ((role, dcid), count) in self.cluster_roles_count_by_dc() {
+            let role = role.to_string();
+            let role_labels = [labels, &[role.as_str(), dcid]].concat();
+            metrics
+                .cluster
+                .role_count_by_dc
+                .with_label_values(&role_labels)
                 .set(count as i64);
         }
 
+        for (role, process_id) in self.singleton_role_holders() {
+            let role = role.to_string();
+            let role_labels = [labels, &[role.as_str()]].concat();
+            metrics.set_role_holder(
+                &metrics.cluster.role_migrations,
+                &role_labels,
+                &process_id.0,
+            );
+        }
+
+        // Classes may have no process left
+        remove_cluster_series(&metrics.cluster.class_process_count, labels[0]);
+        remove_cluster_series(&metrics.cluster.class_disk_free_bytes, labels[0]);
+        for (class_type, summary) in self.cluster_classes_summary() {
+            let class_type = class_type.to_string();
+            let class_labels = [labels, &[class_type.as_str()]].concat();
+            metrics
+                .cluster
+                .class_process_count
+                .with_label_values(&class_labels)
+                .set(summary.process_count);
+            metrics
+                .cluster
+                .class_disk_free_bytes
+                .with_label_values(&class_labels)
+                .set(summary.disk_free_bytes);
+        }
+
+        if let Some(latency) = self.worst_storage_read_latency() {
+            metrics
+                .cluster
+                .worst_storage_read_latency
+                .with_label_values(labels)
+                .set(latency);
+        }
+
+        self.latency_percentiles_to_metrics(metrics, labels);
+
+        let threshold = options.low_space_threshold.to_string();
+        let threshold_labels = [labels, &[threshold.as_str()]].concat();
+        metrics
+            .cluster
+            .storage_servers_low_space
+            .with_label_values(&threshold_labels)
+            .set(self.storage_servers_low_space(options.low_space_threshold / 100.0) as i64);
+        metrics
+            .cluster
+            .excluded_servers_with_data
+            .with_label_values(labels)
+            .set(self.excluded_servers_with_data() as i64);
+
+        if let Some(cores) = self.cpu_cores_used() {
+            metrics
+                .cluster
+                .cpu_cores_used
+                .with_label_values(labels)
+                .set(cores);
+        }
+        if let Some(utilization) = self.average_cpu_utilization() {
+            metrics
+                .cluster
+                .cpu_utilization
+                .with_label_values(labels)
+                .set(utilization);
+        }
+        if let Some(ratio) = self.min_disk_free_ratio() {
+            metrics
+                .cluster
+                .min_disk_free_ratio
+                .with_label_values(labels)
+                .set(ratio);
+        }
+
         if let Some(latency_probe) = &self.latency_probe {
-            latency_probe.to_metrics(&[]);
+            latency_probe.to_metrics(metrics, labels);
         }
 
         if let Some(layers) = &self.layers {
             if let Some(backup) = &layers.backup {
-                backup.to_metrics(&[]);
+                backup.to_metrics(metrics, labels);
             }
         }
 
+        if let Some(blob_granules) = &self.blob_granules {
+            blob_granules.to_metrics(metrics, labels);
+        }
+
         if let Some(wiggle) = &self.storage_wiggler {
-            wiggle.to_metrics(&[]);
+            wiggle.to_metrics(metrics, labels);
         }
 
-        P_CLUSTER_GENERATION_COUNT.set(self.generation);
+        if let Some(workload) = &self.workload {
+            workload.to_metrics(metrics, labels);
+        }
+
+        match &self.clients {
+            Some(clients) => clients.to_metrics(metrics, labels),
+            None => cluster_clients::remove_clients_series(metrics, labels[0]),
+        }
+
+        metrics
+            .cluster
+            .generation_count
+            .with_label_values(labels)
+            .set(self.generation);
+        metrics.set_recent_recoveries(
+            &metrics.cluster.recoveries_in_window,
+            labels,
+            self.generation,
+            options.recovery_window,
+        );
 
-        if let Some(qos) = &self.qos {
-            qos.to_metrics(&[]);
+        if let Some(qos) = self
+            .qos
+            .as_ref()
+            .filter(|_| options.is_enabled(MetricGroup::Qos))
+        {
+            qos.to_metrics(metrics, labels);
         }
+        if options.is_enabled(MetricGroup::Qos) {
+            cluster_qos::limiting_process_to_metrics(metrics, labels, self);
+        }
+
+        if let Some(recovery_state) = &self.recovery_state {
+            recovery_state.to_metrics(metrics, labels);
+        }
+
+        if let Some(protocol_version) = &self.protocol_version {
+            // Only keep the series of the current protocol
+            remove_cluster_series(&metrics.cluster.protocol_version_info, labels[0]);
+            let version = self.fdb_version().unwrap_or_else(|| "unknown".to_string());
+            let protocol_labels = [labels, &[protocol_version.as_str(), version.as_str()]].concat();
+            metrics
+                .cluster
+                .protocol_version_info
+                .with_label_values(&protocol_labels)
+                .set(1);
+        }
+        if let Some(connection_string) = &self.connection_string {
+            // Only keep the series of the current coordinators
+            remove_cluster_series(&metrics.cluster.connection_string_info, labels[0]);
+            let connection_labels = [labels, &[connection_string.as_str()]].concat();
+            metrics
+                .cluster
+                .connection_string_info
+                .with_label_values(&connection_labels)
+                .set(1);
+        }
+        match self.full_replication {
+            Some(full_replication) => metrics
+                .cluster
+                .full_replication
+                .with_label_values(labels)
+                .set(full_replication as i64),
+            // Don't keep reporting the replication of a previous status
+            None => remove_cluster_series(&metrics.cluster.full_replication, labels[0]),
+        }
+        let can_clean_bounce = self
+            .bounce_impact
+            .as_ref()
+            .and_then(|bounce_impact| bounce_impact.can_clean_bounce);
+        if let Some(can_clean_bounce) = can_clean_bounce {
+            metrics
+                .cluster
+                .can_clean_bounce
+                .with_label_values(labels)
+                .set(can_clean_bounce as i64);
+        }
+    }
+
+    /// Export the worst latency percentiles of the roles, the p99 of reads is
+    /// `fdb_cluster_worst_storage_read_latency_seconds`. Gauges are skipped
+    /// when no role reports percentiles.
+    fn latency_percentiles_to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        type Statistics = fn(&ClusterProcessRole) -> Option<&LatencyStats>;
+        let commit: Statistics = |role| role.commit_latency_statistics.as_ref();
+        let grv: Statistics = |role| {
+            role.grv_latency_statistics
+                .as_ref()
+                .and_then(|grv| grv.default.as_ref())
+        };
+        let read: Statistics = |role| role.read_latency_statistics.as_ref();
+        let median = |stats: &LatencyStats| stats.median;
+        let p99 = |stats: &LatencyStats| stats.p99;
+
+        for (metric, latency) in [
+            (
+                &metrics.cluster.commit_latency_p50,
+                self.worst_latency(commit, median),
+            ),
+            (
+                &metrics.cluster.commit_latency_p99,
+                self.worst_latency(commit, p99),
+            ),
+            (
+                &metrics.cluster.grv_latency_p50,
+                self.worst_latency(grv, median),
+            ),
+            (
+                &metrics.cluster.grv_latency_p99,
+                self.worst_latency(grv, p99),
+            ),
+            (
+                &metrics.cluster.read_latency_p50,
+                self.worst_latency(read, median),
+            ),
+        ] {
+            if let Some(latency) = latency {
+                metric.with_label_values(labels).set(latency);
+            }
+        }
+    }
+
+    fn machines_to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        for (machine_id, machine) in &self.machines {
+            let datacenter_id = machine
+                .datacenter_id
+                .clone()
+                .unwrap_or(String::from("default"));
+            let machine_labels = [
+                labels,
+                &[
+                    machine_id.0.as_str(),
+                    datacenter_id.as_str(),
+                    machine.address.as_str(),
+                ],
+            ]
+            .concat();
+            machine.to_metrics(metrics, &machine_labels);
+        }
+    }
+
+    fn processes_to_metrics(&self, metrics: &Metrics, labels: &[&str], options: &MetricsOptions) {
+        // Labels are computed once per process before conversion
+        let (processes, filtered_out): (Vec<_>, Vec<_>) = self
+            .processes
+            .iter()
+            .filter_map(|(process_id, process)| {
+                let included =
+                    options.is_process_included(&process_id.0, &process.address.to_string());
+                process_labels(labels, process_id, process)
+                    .map(|process_labels| ((process, process_labels), included))
+            })
+            .partition(|(_, included)| *included);
+        let processes: Vec<(&ClusterProcess, Vec<String>)> =
+            processes.into_iter().map(|(process, _)| process).collect();
+
+        let default_engine = self
+            .configuration
+            .as_ref()
+            .and_then(|configuration| configuration.storage_engine.as_deref());
+
+        // Series exported before the process was filtered out would be stale
+        for ((_, process_labels), _) in filtered_out {
+            let process_labels: Vec<&str> = process_labels.iter().map(String::as_str).collect();
+            cluster_process::remove_process_series(metrics, &process_labels);
+        }
+
+        #[cfg(feature = "rayon")]
+        processes
+            .par_chunks(PROCESSES_CHUNK_SIZE)
+            .for_each(|chunk| {
+                chunk.iter().for_each(|process| {
+                    process_to_metrics(metrics, process, options, default_engine)
+                })
+            });
+        #[cfg(not(feature = "rayon"))]
+        processes
+            .iter()
+            .for_each(|process| process_to_metrics(metrics, process, options, default_engine));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::parse_cluster_status;
+    use prometheus::core::Collector;
+    use prometheus::Registry;
+    use std::collections::HashSet;
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn protocol_version_info_is_replaced() {
+        let registry = Registry::new();
+        let metrics = &Metrics::new(&registry).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let labels = ["protocol-test"];
+        status.to_metrics(metrics, &labels);
+        status.protocol_version = Some("fdb00b072000000".to_string());
+        status.to_metrics(metrics, &labels);
+
+        let versions: Vec<String> = metrics.cluster.protocol_version_info.collect()[0]
+            .get_metric()
+            .iter()
+            .filter(|m| m.get_label().iter().any(|l| l.get_value() == labels[0]))
+            .flat_map(|m| m.get_label().iter())
+            .filter(|l| l.get_name() == "protocol_version")
+            .map(|l| l.get_value().to_string())
+            .collect();
+        assert_eq!(versions, ["fdb00b072000000"]);
+    }
+
+    #[test]
+    fn full_replication_is_removed_when_absent() {
+        let registry = Registry::new();
+        let metrics = &Metrics::new(&registry).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let labels = ["full-replication-test"];
+        status.to_metrics(metrics, &labels);
+        assert_eq!(
+            metrics
+                .cluster
+                .full_replication
+                .with_label_values(&labels)
+                .get(),
+            1
+        );
+
+        status.full_replication = None;
+        status.to_metrics(metrics, &labels);
+        assert!(metrics.cluster.full_replication.collect()[0]
+            .get_metric()
+            .is_empty());
+    }
+
+    #[test]
+    fn disabled_groups_are_skipped() {
+        let registry = Registry::new();
+        let metrics = &Metrics::new(&registry).unwrap();
+        let status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let options = MetricsOptions {
+            disabled_groups: [MetricGroup::Role, MetricGroup::Machine].into(),
+            ..Default::default()
+        };
+        status.to_metrics_with(metrics, &["disabled-groups"], &options);
+
+        let exported = |prefix: &str| {
+            registry
+                .gather()
+                .iter()
+                .filter(|family| family.get_name().starts_with(prefix))
+                .flat_map(|family| family.get_metric())
+                .any(|metric| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|label| label.get_value() == "disabled-groups")
+                })
+        };
+        assert!(exported("fdb_cluster_process_cpu"));
+        assert!(!exported("fdb_cluster_process_role_"));
+        assert!(!exported("fdb_cluster_machine_"));
+    }
+
+    #[test]
+    fn filtered_out_processes_are_removed() {
+        let registry = Registry::new();
+        let metrics = &Metrics::new(&registry).unwrap();
+        let status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let exported_processes = || -> HashSet<String> {
+            registry
+                .gather()
+                .iter()
+                .filter(|family| family.get_name() == "fdb_cluster_process_cpu_usage")
+                .flat_map(|family| family.get_metric())
+                .map(|metric| metric.get_label())
+                .filter(|labels| labels.iter().any(|l| l.get_value() == "process-filter"))
+                .flat_map(|labels| labels.iter())
+                .filter(|label| label.get_name() == "process_id")
+                .map(|label| label.get_value().to_string())
+                .collect()
+        };
+
+        status.to_metrics_with(metrics, &["process-filter"], &MetricsOptions::default());
+        let all = exported_processes();
+        let excluded = all.iter().next().unwrap().clone();
+        assert!(all.len() > 1);
+
+        let options = MetricsOptions {
+            process_exclude: Some(regex::Regex::new(&format!("^{}$", excluded)).unwrap()),
+            ..Default::default()
+        };
+        status.to_metrics_with(metrics, &["process-filter"], &options);
+        let remaining = exported_processes();
+        assert!(!remaining.contains(&excluded));
+        assert_eq!(remaining.len(), all.len() - 1);
+    }
+
+    #[test]
+    fn unreachable_coordinator_is_not_reporting() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let labels = ["coordinators"];
+        coordinators_to_metrics(metrics, &labels, &status, &[]);
+        let (configured, reporting) = (
+            &metrics.cluster.coordinators_configured,
+            &metrics.cluster.coordinators_reporting,
+        );
+        assert_eq!(configured.with_label_values(&labels).get(), 1);
+        assert_eq!(reporting.with_label_values(&labels).get(), 1);
+
+        status.client.coordinators.coordinators[0].reachable = false;
+        coordinators_to_metrics(metrics, &labels, &status, &[]);
+        assert_eq!(configured.with_label_values(&labels).get(), 1);
+        assert_eq!(reporting.with_label_values(&labels).get(), 0);
+    }
+
+    #[test]
+    fn coordinator_drift() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let labels = ["drift"];
+        let drift = |expected: &[&str]| {
+            let expected: Vec<FdbProcessAddress> = expected
+                .iter()
+                .map(|address| FdbProcessAddress::parse(address).unwrap())
+                .collect();
+            coordinators_to_metrics(metrics, &labels, &status, &expected);
+            metrics
+                .cluster
+                .coordinator_drift
+                .with_label_values(&labels)
+                .get()
+        };
+
+        // The TLS suffix doesn't matter
+        assert_eq!(drift(&["172.19.0.2:4500:tls"]), 0);
+        assert_eq!(drift(&["172.19.0.2:4500", "fdb.example.com:4500"]), 1);
+        assert_eq!(drift(&["172.19.0.3:4500"]), 2);
+    }
+
+    #[test]
+    fn singleton_role_migrations() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let labels = ["migrations"];
+        let migrations = || {
+            metrics
+                .cluster
+                .role_migrations
+                .with_label_values(&["migrations", "data_distributor"])
+                .get()
+        };
+
+        status.to_metrics(metrics, &labels);
+        status.to_metrics(metrics, &labels);
+        assert_eq!(migrations(), 0);
+
+        // Move the roles of the data distributor to another process
+        let holder = status.singleton_role_holders()[&ClusterClassType::DataDistributor]
+            .0
+            .clone();
+        let (_, process) = status
+            .processes
+            .iter_mut()
+            .find(|(id, _)| id.0 == holder)
+            .unwrap();
+        let roles = std::mem::take(&mut process.roles);
+        let (_, other) = status
+            .processes
+            .iter_mut()
+            .find(|(id, _)| id.0 != holder)
+            .unwrap();
+        other.roles.extend(roles);
+        status.to_metrics(metrics, &labels);
+        assert_eq!(migrations(), 1);
+    }
+
+    #[test]
+    fn emptied_classes_are_removed() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let labels = ["classes"];
+        status.to_metrics(metrics, &labels);
+        assert_eq!(
+            metrics
+                .cluster
+                .class_process_count
+                .with_label_values(&["classes", "unset"])
+                .get(),
+            3
+        );
+
+        for process in status.processes.values_mut() {
+            process.class_type = Some(ClusterClassType::Storage);
+        }
+        status.to_metrics(metrics, &labels);
+        for metric in [
+            &metrics.cluster.class_process_count,
+            &metrics.cluster.class_disk_free_bytes,
+        ] {
+            assert!(metric.remove_label_values(&["classes", "unset"]).is_err());
+        }
+        assert_eq!(
+            metrics
+                .cluster
+                .class_process_count
+                .with_label_values(&["classes", "storage"])
+                .get(),
+            3
+        );
+    }
+
+    #[test]
+    fn class_shortfall_is_clamped() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        // The sample status runs a single log and a single commit proxy
+        status.configuration = Some(
+            serde_json::from_value(serde_json::json!({
+                "logs": 3,
+                "commit_proxies": 1,
+                "grv_proxies": -1,
+            }))
+            .unwrap(),
+        );
+        status.to_metrics(metrics, &["shortfall"]);
+
+        let shortfall = |role| {
+            metrics
+                .cluster
+                .class_shortfall
+                .with_label_values(&["shortfall", role])
+                .get()
+        };
+        assert_eq!(shortfall("log"), 2);
+        assert_eq!(shortfall("commit_proxy"), 0);
+        // Left to its default, so not compared
+        assert!(metrics
+            .cluster
+            .class_shortfall
+            .remove_label_values(&["shortfall", "grv_proxy"])
+            .is_err());
+
+        // Logs set back to auto
+        status.configuration = Some(
+            serde_json::from_value(serde_json::json!({
+                "logs": -1,
+                "commit_proxies": 1,
+            }))
+            .unwrap(),
+        );
+        status.to_metrics(metrics, &["shortfall"]);
+        assert!(metrics
+            .cluster
+            .class_shortfall
+            .remove_label_values(&["shortfall", "log"])
+            .is_err());
+        assert_eq!(shortfall("commit_proxy"), 0);
+    }
+
+    #[test]
+    fn latency_percentiles_are_skipped_without_statistics() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        let worst_p99 = status
+            .processes
+            .values()
+            .flat_map(|process| &process.roles)
+            .filter_map(|role| role.commit_latency_statistics.as_ref())
+            .map(|stats| stats.p99)
+            .reduce(f64::max)
+            .unwrap();
+        status.to_metrics(metrics, &["latency"]);
+        assert_eq!(
+            metrics
+                .cluster
+                .commit_latency_p99
+                .with_label_values(&["latency"])
+                .get(),
+            worst_p99
+        );
+
+        for process in status.processes.values_mut() {
+            for role in &mut process.roles {
+                role.commit_latency_statistics = None;
+            }
+        }
+        status.to_metrics(metrics, &["no-latency"]);
+        assert!(metrics
+            .cluster
+            .commit_latency_p99
+            .remove_label_values(&["no-latency"])
+            .is_err());
+    }
+
+    #[test]
+    fn regions_of_multi_region_clusters() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS)
+            .unwrap()
+            .cluster
+            .unwrap();
+        status.configuration = Some(
+            serde_json::from_value(serde_json::json!({
+                "regions": [
+                    {
+                        "datacenters": [
+                            {"id": "dc1", "priority": 1},
+                            {"id": "dc2", "priority": 1, "satellite": 1, "satellite_logs": 4}
+                        ],
+                        "satellite_logs": 2,
+                        "satellite_redundancy_mode": "one_satellite_double"
+                    },
+                    {
+                        "datacenters": [
+                            {"id": "dc3", "priority": 0},
+                            {"id": "dc4", "priority": 1, "satellite": 1}
+                        ],
+                        "satellite_logs": 2
+                    }
+                ]
+            }))
+            .unwrap(),
+        );
+        status.to_metrics(metrics, &["regions"]);
+        let datacenters = || -> Vec<Vec<String>> {
+            metrics.cluster.region_info.collect()[0]
+                .get_metric()
+                .iter()
+                .map(|m| {
+                    m.get_label()
+                        .iter()
+                        .filter(|l| l.get_name() != "cluster")
+                        .map(|l| l.get_value().to_string())
+                        .collect()
+                })
+                .collect()
+        };
+        let mut regions = datacenters();
+        regions.sort();
+        assert_eq!(
+            regions,
+            [
+                ["dc1", "1", "0", "2"],
+                ["dc2", "1", "1", "4"],
+                ["dc3", "0", "0", "2"],
+                ["dc4", "1", "1", "2"],
+            ]
+        );
+
+        // Back to a single region
+        status.configuration.as_mut().unwrap().regions.truncate(1);
+        status.to_metrics(metrics, &["regions"]);
+        assert!(datacenters().is_empty());
     }
 }
@@ -1,8 +1,7 @@
 use std::collections::HashMap;
 
-use lazy_static::lazy_static;
 use prometheus::{
-    register_gauge, register_int_gauge, register_int_gauge_vec, Gauge, IntGauge, IntGaugeVec,
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
 };
 use tracing::warn;
 
@@ -11,74 +10,125 @@ use crate::{
     status_models::cluster_backup::{ClusterBackup, ClusterBackupTag},
 };
 
-use super::{AndSetSingle, StaticMetric};
+use super::{AndSetSingle, Metrics, MetricsRegistry, StaticMetric, CLUSTER_LABELS};
 
 const P_PREFIX: &str = "fdb_cluster_backup";
 
-lazy_static! {
-    static ref P_BACKUP_PAUSED: IntGauge = register_int_gauge!(
-        format!("{}_paused", P_PREFIX).as_str(),
-        "Backup system enabled (0=false)"
-    )
-    .unwrap();
-    static ref P_BACKUP_WORKERS_TOTAL: IntGauge = register_int_gauge!(
-        format!("{}_workers_total", P_PREFIX).as_str(),
-        "Backup system number of agent in the cluster"
-    )
-    .unwrap();
-    static ref P_BACKUP_WORKERS_RUNNING: IntGauge = register_int_gauge!(
-        format!("{}_workers_running", P_PREFIX).as_str(),
-        "Backup system number of agent running in the cluster"
-    )
-    .unwrap();
-    static ref P_BACKUP_RECENT_IO_BYTES_PER_SECOND: Gauge = register_gauge!(
-        format!("{}_recent_bytes_per_second", P_PREFIX),
-        "Rate of bytes sent per second from backup agents"
-    )
-    .unwrap();
-    static ref P_BACKUP_RECENT_IO_BYTES_SENT: IntGauge = register_int_gauge!(
-        format!("{}_recent_bytes_sent", P_PREFIX),
-        "Total number of bytes sent recently from backup agents"
-    )
-    .unwrap();
-    static ref P_BACKUP_RECENT_REQUESTS_FAILED: IntGauge = register_int_gauge!(
-        format!("{}_recent_requests_failed", P_PREFIX),
-        "Recent number of requests failed to external storage from backup agents"
-    )
-    .unwrap();
-    static ref P_BACKUP_RECENT_REQUESTS_SUCCESS: IntGauge = register_int_gauge!(
-        format!("{}_recent_requests_successful", P_PREFIX),
-        "Recent number of requests done to external storage from backup agents"
-    )
-    .unwrap();
-    static ref P_BACKUP_STATUS_TAG: HashMap<String, IntGaugeVec> = ClusterBackupTag::register(
-        format!("{}_tag", P_PREFIX).as_str(),
-        "Backup tag information"
-    );
+/// Metrics of the backup layer
+pub struct BackupMetrics {
+    paused: IntGaugeVec,
+    workers_total: IntGaugeVec,
+    workers_running: IntGaugeVec,
+    recent_io_bytes_per_second: GaugeVec,
+    recent_io_bytes_sent: IntGaugeVec,
+    recent_requests_failed: IntGaugeVec,
+    recent_requests_success: IntGaugeVec,
+    status_tag: HashMap<String, IntGaugeVec>,
+}
+
+impl BackupMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(BackupMetrics {
+            paused: register_int_gauge_vec_with_registry!(
+                format!("{}_paused", P_PREFIX).as_str(),
+                "Whether backups are paused cluster-wide (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            workers_total: register_int_gauge_vec_with_registry!(
+                format!("{}_workers_total", P_PREFIX).as_str(),
+                "Backup system number of agent in the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            workers_running: register_int_gauge_vec_with_registry!(
+                format!("{}_workers_running", P_PREFIX).as_str(),
+                "Backup system number of agent running in the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recent_io_bytes_per_second: register_gauge_vec_with_registry!(
+                format!("{}_recent_bytes_per_second", P_PREFIX),
+                "Rate of bytes sent per second from backup agents",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recent_io_bytes_sent: register_int_gauge_vec_with_registry!(
+                format!("{}_recent_bytes_sent", P_PREFIX),
+                "Total number of bytes sent recently from backup agents",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recent_requests_failed: register_int_gauge_vec_with_registry!(
+                format!("{}_recent_requests_failed", P_PREFIX),
+                "Recent number of requests failed to external storage from backup agents",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recent_requests_success: register_int_gauge_vec_with_registry!(
+                format!("{}_recent_requests_successful", P_PREFIX),
+                "Recent number of requests done to external storage from backup agents",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            status_tag: ClusterBackupTag::register(
+                registry,
+                format!("{}_tag", P_PREFIX).as_str(),
+                "Backup tag information",
+            )?,
+        })
+    }
 }
 
 impl MetricsConvertible for ClusterBackup {
-    fn to_metrics(&self, _: &[&str]) {
-        P_BACKUP_PAUSED.set(self.paused as i64);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .backup
+            .paused
+            .with_label_values(labels)
+            .set(self.paused as i64);
 
-        self.total_workers.and_set(&P_BACKUP_WORKERS_TOTAL);
-        self.instances_running.and_set(&P_BACKUP_WORKERS_RUNNING);
+        self.total_workers
+            .and_set(&metrics.backup.workers_total.with_label_values(labels));
+        self.instances_running
+            .and_set(&metrics.backup.workers_running.with_label_values(labels));
 
         if let Some(io) = &self.blob_recent_io {
-            P_BACKUP_RECENT_IO_BYTES_SENT.set(io.bytes_sent);
-            P_BACKUP_RECENT_IO_BYTES_PER_SECOND.set(io.bytes_per_second);
-            P_BACKUP_RECENT_REQUESTS_FAILED.set(io.requests_failed);
-            P_BACKUP_RECENT_REQUESTS_SUCCESS.set(io.requests_successful);
+            metrics
+                .backup
+                .recent_io_bytes_sent
+                .with_label_values(labels)
+                .set(io.bytes_sent);
+            metrics
+                .backup
+                .recent_io_bytes_per_second
+                .with_label_values(labels)
+                .set(io.bytes_per_second);
+            metrics
+                .backup
+                .recent_requests_failed
+                .with_label_values(labels)
+                .set(io.requests_failed);
+            metrics
+                .backup
+                .recent_requests_success
+                .with_label_values(labels)
+                .set(io.requests_successful);
         }
 
         for (tag, backup) in &self.tags {
-            backup.set(&P_BACKUP_STATUS_TAG, &[tag.0.as_str()])
+            let tag_labels = [labels, &[tag.0.as_str()]].concat();
+            backup.set(&metrics.backup.status_tag, &tag_labels)
         }
     }
 }
 
 impl StaticMetric<IntGaugeVec> for ClusterBackupTag {
-    fn register(prefix: &str, desc: &str) -> HashMap<String, IntGaugeVec> {
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, IntGaugeVec>> {
         let stat_name = &[
             "last_restorable_behind_seconds",
             "last_restorable_version",
@@ -91,10 +141,15 @@ impl StaticMetric<IntGaugeVec> for ClusterBackupTag {
         for name in stat_name {
             metrics.insert(
                 name.to_string(),
-                register_int_gauge_vec!(format!("{}_{}", prefix, name), desc, &["tag"],).unwrap(),
+                register_int_gauge_vec_with_registry!(
+                    format!("{}_{}", prefix, name),
+                    desc,
+                    &["cluster", "tag"],
+                    registry
+                )?,
             );
         }
-        metrics
+        Ok(metrics)
     }
     fn set(&self, metrics: &HashMap<String, IntGaugeVec>, labels: &[&str]) {
         let stat_name = &[
@@ -133,3 +188,35 @@ impl StaticMetric<IntGaugeVec> for ClusterBackupTag {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn paused_backups() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let backup: ClusterBackup = serde_json::from_value(serde_json::json!({
+            "paused": true,
+            "total_workers": 3,
+            "instances_running": 2,
+            "tags": {}
+        }))
+        .unwrap();
+        backup.to_metrics(metrics, &["backup"]);
+
+        assert_eq!(
+            metrics.backup.paused.with_label_values(&["backup"]).get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .backup
+                .workers_running
+                .with_label_values(&["backup"])
+                .get(),
+            2
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use prometheus::{register_int_gauge_vec_with_registry, IntGaugeVec};
+
+use super::{AndSetSingle, Metrics, MetricsRegistry, CLUSTER_LABELS};
+use crate::{
+    metrics::MetricsConvertible, status_models::cluster_blob_granules::ClusterBlobGranules,
+};
+
+/// Metrics of blob granules, only exported when they are enabled
+pub struct BlobGranulesMetrics {
+    workers: IntGaugeVec,
+    key_ranges: IntGaugeVec,
+}
+
+impl BlobGranulesMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(BlobGranulesMetrics {
+            workers: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_blob_granules_workers",
+                "Number of blob workers, only exported when blob granules are enabled",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            key_ranges: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_blob_granules_key_ranges",
+                "Number of key ranges stored as blob granules, only exported when blob granules are enabled",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+impl MetricsConvertible for ClusterBlobGranules {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        self.number_of_blob_workers
+            .and_set(&metrics.blob_granules.workers.with_label_values(labels));
+        self.number_of_key_ranges
+            .and_set(&metrics.blob_granules.key_ranges.with_label_values(labels));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn blob_granules() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let blob_granules: ClusterBlobGranules = serde_json::from_value(serde_json::json!({
+            "number_of_blob_workers": 4,
+            "number_of_key_ranges": 120
+        }))
+        .unwrap();
+        blob_granules.to_metrics(metrics, &["blob"]);
+
+        assert_eq!(
+            metrics
+                .blob_granules
+                .workers
+                .with_label_values(&["blob"])
+                .get(),
+            4
+        );
+        assert_eq!(
+            metrics
+                .blob_granules
+                .key_ranges
+                .with_label_values(&["blob"])
+                .get(),
+            120
+        );
+    }
+}
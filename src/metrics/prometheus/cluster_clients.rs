@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use prometheus::{register_int_gauge_vec_with_registry, IntGaugeVec};
+use tracing::warn;
+
+use crate::{
+    metrics::MetricsConvertible,
+    status_models::cluster_clients::{ClientVersionCount, ClusterClients},
+};
+
+use super::{remove_cluster_series, Metrics, MetricsRegistry, StaticMetric};
+
+/// Metrics of the clients connected to the cluster
+pub struct ClientsMetrics {
+    by_version: HashMap<String, IntGaugeVec>,
+}
+
+impl ClientsMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ClientsMetrics {
+            by_version: ClientVersionCount::register(
+                registry,
+                "fdb_cluster_clients",
+                "Number of clients supporting a client version (by_version) or whose newest version it is (max_protocol_by_version)",
+            )?,
+        })
+    }
+}
+
+/// Forget the clients of `cluster`, e.g. when its status doesn't report them
+pub fn remove_clients_series(metrics: &Metrics, cluster: &str) {
+    for metric in metrics.clients.by_version.values() {
+        remove_cluster_series(metric, cluster);
+    }
+}
+
+impl MetricsConvertible for ClusterClients {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        // Only keep the series of the versions still connected
+        remove_clients_series(metrics, labels[0]);
+        for (version, count) in self.by_client_version() {
+            let version_labels = [labels, &[version]].concat();
+            count.set(&metrics.clients.by_version, &version_labels);
+        }
+    }
+}
+
+impl StaticMetric<IntGaugeVec> for ClientVersionCount {
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, IntGaugeVec>> {
+        let stat_name = &["by_version", "max_protocol_by_version"];
+        let mut metrics = HashMap::new();
+        for name in stat_name {
+            metrics.insert(
+                name.to_string(),
+                register_int_gauge_vec_with_registry!(
+                    format!("{}_{}", prefix, name),
+                    desc,
+                    &["cluster", "version"],
+                    registry
+                )?,
+            );
+        }
+        Ok(metrics)
+    }
+    fn set(&self, metrics: &HashMap<String, IntGaugeVec>, labels: &[&str]) {
+        let stat_name = &["by_version", "max_protocol_by_version"];
+        for name in *stat_name {
+            // Safe as we know already the stat names
+            let metric = metrics.get(name).unwrap();
+            let value: Option<i64> = match name {
+                "by_version" => Some(self.count),
+                "max_protocol_by_version" => Some(self.max_protocol_count),
+                // Impossible case
+                &_ => {
+                    warn!(
+                        "ClientVersionCount::set() went through irregular case for {}",
+                        name
+                    );
+                    None
+                }
+            };
+
+            if let Some(value_i64) = value {
+                metric.with_label_values(labels).set(value_i64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::core::Collector;
+    use prometheus::Registry;
+
+    use crate::fetcher::parse_cluster_status;
+    use crate::metrics::{Metrics, MetricsConvertible};
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn disconnected_versions_are_removed() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let clients = status.cluster.as_mut().unwrap().clients.as_mut().unwrap();
+        let labels = ["clients-test"];
+        clients.to_metrics(metrics, &labels);
+        assert_eq!(
+            metrics.clients.by_version["by_version"]
+                .with_label_values(&["clients-test", "7.1.37"])
+                .get(),
+            117
+        );
+
+        clients.supported_versions[0].client_version = "7.1.61".to_string();
+        clients.to_metrics(metrics, &labels);
+        let versions: Vec<String> = metrics.clients.by_version["by_version"].collect()[0]
+            .get_metric()
+            .iter()
+            .flat_map(|m| m.get_label().iter())
+            .filter(|l| l.get_name() == "version")
+            .map(|l| l.get_value().to_string())
+            .collect();
+        assert_eq!(versions, ["7.1.61"]);
+    }
+}
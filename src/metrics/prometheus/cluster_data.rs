@@ -1,91 +1,664 @@
+use super::{remove_cluster_series, AndSetSingle, Metrics, MetricsRegistry, CLUSTER_LABELS};
 use crate::metrics::MetricsConvertible;
 use crate::status_models::cluster_data::ClusterData;
-use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, IntGauge};
-
-lazy_static! {
-    static ref P_CLUSTER_AVG_PARTITION_BYTES_GAUGE: IntGauge = register_int_gauge!(
-        "fdb_cluster_average_partition_size_bytes",
-        "Average size for a partition in the cluster"
-    )
-    .unwrap();
-    static ref P_CLUSTER_LEAST_SPACE_BYTES_LOG_SERVER_GAUGE: IntGauge = register_int_gauge!(
-        "fdb_cluster_least_space_log_server_bytes",
-        "Value of the log server with least space available"
-    )
-    .unwrap();
-    static ref P_CLUSTER_LEAST_SPACE_BYTES_STORAGE_SERVER: IntGauge = register_int_gauge!(
-        "fdb_cluster_least_space_storage_server_bytes",
-        "Value of the storage server with least space avaiable"
-    )
-    .unwrap();
-    static ref P_CLUSTER_PARTITION_COUNT: IntGauge =
-        register_int_gauge!("fdb_cluster_partition_count", "Number of partitions").unwrap();
-    static ref P_CLUSTER_TOTAL_DISK_USED_BYTES: IntGauge = register_int_gauge!(
-        "fdb_cluster_total_disk_used_bytes",
-        "Total number of bytes used on all disk"
-    )
-    .unwrap();
-    static ref P_CLUSTER_TOTAL_KV_SIZE_BYTES: IntGauge = register_int_gauge!(
-        "fdb_cluster_total_kv_size_bytes",
-        "Total number of bytes for all key values"
-    )
-    .unwrap();
-    static ref P_CLUSTER_STATE_HEALTHY: IntGauge = register_int_gauge!(
-        "fdb_cluster_healthy",
-        "Whether the cluster is healthy or not"
-    )
-    .unwrap();
-    static ref P_CLUSTER_STATE_CURRENT: IntGauge = register_int_gauge!(
-        "fdb_cluster_state",
-        "Current state of the cluster (see src/status_models/cluster_data.rs)"
-    )
-    .unwrap();
-    static ref P_CLUSTER_MOVING_DATA_IN_FLIGHT_BYTES: IntGauge =
-        register_int_gauge!("fdb_cluster_moving_data_in_flight_bytes", "Data in flight",).unwrap();
-    static ref P_CLUSTER_MOVING_DATA_IN_QUEUE_BYTES: IntGauge = register_int_gauge!(
-        "fdb_cluster_moving_data_in_queue_bytes",
-        "Data waiting to be transferred"
-    )
-    .unwrap();
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
+
+const TEAM_TRACKER_LABELS: &[&str] = &["cluster", "region"];
+
+/// Upper bounds in bytes of the shard sizes counted by
+/// `fdb_cluster_shards_by_size`, shards are split above 500MB by default
+const SHARD_SIZE_BUCKETS: [u64; 5] = [1_000_000, 10_000_000, 100_000_000, 250_000_000, 500_000_000];
+
+/// Metrics of [ClusterData]
+pub struct DataMetrics {
+    data_present: IntGaugeVec,
+    avg_partition_bytes: IntGaugeVec,
+    least_space_bytes_log_server: IntGaugeVec,
+    least_space_bytes_storage_server: IntGaugeVec,
+    partition_count: IntGaugeVec,
+    total_disk_used_bytes: IntGaugeVec,
+    total_kv_size_bytes: IntGaugeVec,
+    state_healthy: IntGaugeVec,
+    state_current: IntGaugeVec,
+    state_name: IntGaugeVec,
+    min_replicas_remaining: IntGaugeVec,
+    state_info: IntGaugeVec,
+    moving_data_in_flight_bytes: IntGaugeVec,
+    moving_data_in_queue_bytes: IntGaugeVec,
+    moving_data_in_flight_bytes_smoothed: GaugeVec,
+    moving_data_in_queue_bytes_smoothed: GaugeVec,
+    moving_data_highest_priority: IntGaugeVec,
+    moving_data_total_written_bytes: IntGaugeVec,
+    team_tracker_in_flight_bytes: IntGaugeVec,
+    team_tracker_unhealthy_servers: IntGaugeVec,
+    team_tracker_healthy: IntGaugeVec,
+    team_tracker_min_replicas_remaining: IntGaugeVec,
+    largest_shard_bytes: IntGaugeVec,
+    shards_by_size: IntGaugeVec,
 }
 
-impl MetricsConvertible for ClusterData {
-    fn to_metrics(&self, _: &[&str]) {
-        if let Some(total_kv_size_bytes) = self.total_kv_size_bytes {
-            P_CLUSTER_TOTAL_KV_SIZE_BYTES.set(total_kv_size_bytes);
-        }
-        if let Some(total_disk_used_bytes) = self.total_disk_used_bytes {
-            P_CLUSTER_TOTAL_DISK_USED_BYTES.set(total_disk_used_bytes);
+impl DataMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(DataMetrics {
+            data_present: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_data_present",
+                "Whether the data section is present in the status (0=false)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            avg_partition_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_average_partition_size_bytes",
+                "Average size for a partition in the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            least_space_bytes_log_server: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_least_space_log_server_bytes",
+                "Value of the log server with least space available",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            least_space_bytes_storage_server: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_least_space_storage_server_bytes",
+                "Value of the storage server with least space avaiable",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            partition_count: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_partition_count",
+                "Number of partitions",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            total_disk_used_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_total_disk_used_bytes",
+                "Total number of bytes used on all disk",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            total_kv_size_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_total_kv_size_bytes",
+                "Total number of bytes for all key values",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            state_healthy: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_healthy",
+                "Whether the cluster is healthy or not",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            state_current: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_state",
+                "Current state of the cluster (see src/status_models/cluster_data.rs)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            state_name: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_state_name",
+                "Name of the current state of the cluster, set to 1",
+                &["cluster", "name"],
+                registry
+            )?,
+            min_replicas_remaining: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_min_replicas_remaining",
+                "Lowest number of replicas remaining for any data in the cluster",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            state_info: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_state_info",
+                "Human readable description of the current state of the cluster",
+                &["cluster", "description"],
+                registry
+            )?,
+            moving_data_in_flight_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_in_flight_bytes",
+                "Data in flight",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            moving_data_in_queue_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_in_queue_bytes",
+                "Data waiting to be transferred",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            moving_data_in_flight_bytes_smoothed: register_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_in_flight_bytes_smoothed",
+                "Data in flight, smoothed with --smoothing-decay",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            moving_data_in_queue_bytes_smoothed: register_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_in_queue_bytes_smoothed",
+                "Data waiting to be transferred, smoothed with --smoothing-decay",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            moving_data_highest_priority: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_highest_priority",
+                "Highest priority of the ongoing data movements",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            moving_data_total_written_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_moving_data_total_written_bytes",
+                "Data written by data movements since the data distributor was recruited",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            team_tracker_in_flight_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_team_tracker_in_flight_bytes",
+                "Data in flight to the teams of a region",
+                TEAM_TRACKER_LABELS,
+                registry
+            )?,
+            team_tracker_unhealthy_servers: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_team_tracker_unhealthy_servers",
+                "Number of unhealthy storage servers in a region",
+                TEAM_TRACKER_LABELS,
+                registry
+            )?,
+            team_tracker_healthy: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_team_tracker_healthy",
+                "Whether the data of a region is healthy (0=false)",
+                TEAM_TRACKER_LABELS,
+                registry
+            )?,
+            team_tracker_min_replicas_remaining: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_team_tracker_min_replicas_remaining",
+                "Number of replicas remaining of the most at-risk data of a region",
+                TEAM_TRACKER_LABELS,
+                registry
+            )?,
+            largest_shard_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_largest_shard_bytes",
+                "Size of the largest shard, only exported with --shard-stats",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            shards_by_size: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_shards_by_size",
+                "Number of shards whose size is at most le bytes, only exported with --shard-stats",
+                &["cluster", "le"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Flag the data section as missing from the status, when `reset` is set the
+/// gauges previously exported from it are removed instead of being left stale
+pub fn missing_cluster_data(metrics: &Metrics, labels: &[&str], reset: bool) {
+    metrics.data.data_present.with_label_values(labels).set(0);
+    if !reset {
+        return;
+    }
+
+    for metric in [
+        &metrics.data.avg_partition_bytes,
+        &metrics.data.least_space_bytes_log_server,
+        &metrics.data.least_space_bytes_storage_server,
+        &metrics.data.partition_count,
+        &metrics.data.total_disk_used_bytes,
+        &metrics.data.total_kv_size_bytes,
+        &metrics.data.state_healthy,
+        &metrics.data.state_current,
+        &metrics.data.min_replicas_remaining,
+        &metrics.data.moving_data_in_flight_bytes,
+        &metrics.data.moving_data_in_queue_bytes,
+        &metrics.data.moving_data_highest_priority,
+        &metrics.data.moving_data_total_written_bytes,
+    ] {
+        // Fails only when the gauge was never set for this cluster
+        let _ = metric.remove_label_values(labels);
+    }
+    remove_cluster_series(&metrics.data.state_info, labels[0]);
+    remove_cluster_series(&metrics.data.state_name, labels[0]);
+    remove_team_tracker_series(metrics, labels[0]);
+    metrics.remove_smoothed(&metrics.data.moving_data_in_flight_bytes_smoothed, labels);
+    metrics.remove_smoothed(&metrics.data.moving_data_in_queue_bytes_smoothed, labels);
+}
+
+/// Export the largest shard and the number of shards by size, from the sizes
+/// read with [crate::fetch_shard_sizes]
+pub fn shard_sizes_to_metrics(metrics: &Metrics, labels: &[&str], sizes: &[u64]) {
+    if let Some(largest) = sizes.iter().max() {
+        metrics.set_saturating(
+            &metrics.data.largest_shard_bytes.with_label_values(labels),
+            *largest,
+        );
+    }
+    let buckets = SHARD_SIZE_BUCKETS
+        .iter()
+        .map(|bound| (bound.to_string(), *bound))
+        .chain([(String::from("+Inf"), u64::MAX)]);
+    for (le, bound) in buckets {
+        let count = sizes.iter().filter(|size| **size <= bound).count();
+        metrics
+            .data
+            .shards_by_size
+            .with_label_values(&[labels, &[le.as_str()]].concat())
+            .set(count as i64);
+    }
+}
+
+fn remove_team_tracker_series(metrics: &Metrics, cluster: &str) {
+    remove_cluster_series(&metrics.data.team_tracker_in_flight_bytes, cluster);
+    remove_cluster_series(&metrics.data.team_tracker_unhealthy_servers, cluster);
+    remove_cluster_series(&metrics.data.team_tracker_healthy, cluster);
+    remove_cluster_series(&metrics.data.team_tracker_min_replicas_remaining, cluster);
+}
+
+impl ClusterData {
+    /// Export the smoothed counterpart of noisy gauges, see [Metrics::set_smoothed]
+    pub fn smoothed_to_metrics(&self, metrics: &Metrics, labels: &[&str], decay: f64) {
+        let Some(moving_data) = &self.moving_data else {
+            return;
+        };
+        if let Some(in_flight_bytes) = moving_data.in_flight_bytes {
+            metrics.set_smoothed(
+                &metrics.data.moving_data_in_flight_bytes_smoothed,
+                labels,
+                in_flight_bytes as f64,
+                decay,
+            );
         }
-        if let Some(partitions_count) = self.partitions_count {
-            P_CLUSTER_PARTITION_COUNT.set(partitions_count);
+        if let Some(in_queue_bytes) = moving_data.in_queue_bytes {
+            metrics.set_smoothed(
+                &metrics.data.moving_data_in_queue_bytes_smoothed,
+                labels,
+                in_queue_bytes as f64,
+                decay,
+            );
         }
+    }
+}
+
+impl MetricsConvertible for ClusterData {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics.data.data_present.with_label_values(labels).set(1);
+        metrics.and_set_saturating(
+            self.total_kv_size_bytes,
+            &metrics.data.total_kv_size_bytes.with_label_values(labels),
+        );
+        metrics.and_set_saturating(
+            self.total_disk_used_bytes,
+            &metrics.data.total_disk_used_bytes.with_label_values(labels),
+        );
+        metrics.and_set_saturating(
+            self.partitions_count,
+            &metrics.data.partition_count.with_label_values(labels),
+        );
         if let Some(least_operating_space_bytes_log_server) =
             self.least_operating_space_bytes_log_server
         {
-            P_CLUSTER_LEAST_SPACE_BYTES_LOG_SERVER_GAUGE
+            metrics
+                .data
+                .least_space_bytes_log_server
+                .with_label_values(labels)
                 .set(least_operating_space_bytes_log_server);
         }
         if let Some(least_operating_space_bytes_storage_server) =
             self.least_operating_space_bytes_storage_server
         {
-            P_CLUSTER_LEAST_SPACE_BYTES_STORAGE_SERVER
+            metrics
+                .data
+                .least_space_bytes_storage_server
+                .with_label_values(labels)
                 .set(least_operating_space_bytes_storage_server);
         }
-        if let Some(average_partition_size_bytes) = self.average_partition_size_bytes {
-            P_CLUSTER_AVG_PARTITION_BYTES_GAUGE.set(average_partition_size_bytes);
-        }
+        metrics.and_set_saturating(
+            self.average_partition_size_bytes,
+            &metrics.data.avg_partition_bytes.with_label_values(labels),
+        );
         if let Some(state) = &self.state {
             if let Some(health) = state.healthy {
-                P_CLUSTER_STATE_HEALTHY.set(health as i64);
+                metrics
+                    .data
+                    .state_healthy
+                    .with_label_values(labels)
+                    .set(health as i64);
+            }
+            metrics
+                .data
+                .state_current
+                .with_label_values(labels)
+                .set(state.name as i64);
+            remove_cluster_series(&metrics.data.state_name, labels[0]);
+            let name = state.name.to_string();
+            metrics
+                .data
+                .state_name
+                .with_label_values(&[labels, &[name.as_str()]].concat())
+                .set(1);
+            state.min_replicas_remaining.and_set(
+                &metrics
+                    .data
+                    .min_replicas_remaining
+                    .with_label_values(labels),
+            );
+
+            // Only keep the series of the current description
+            remove_cluster_series(&metrics.data.state_info, labels[0]);
+            if let Some(description) = &state.description {
+                let info_labels = [labels, &[description.as_str()]].concat();
+                metrics
+                    .data
+                    .state_info
+                    .with_label_values(&info_labels)
+                    .set(1);
             }
-            P_CLUSTER_STATE_CURRENT.set(state.name as i64);
         }
 
         if let Some(moving_data) = &self.moving_data {
-            P_CLUSTER_MOVING_DATA_IN_FLIGHT_BYTES.set(moving_data.in_flight_bytes);
-            P_CLUSTER_MOVING_DATA_IN_QUEUE_BYTES.set(moving_data.in_queue_bytes);
+            metrics.and_set_saturating(
+                moving_data.in_flight_bytes,
+                &metrics
+                    .data
+                    .moving_data_in_flight_bytes
+                    .with_label_values(labels),
+            );
+            metrics.and_set_saturating(
+                moving_data.in_queue_bytes,
+                &metrics
+                    .data
+                    .moving_data_in_queue_bytes
+                    .with_label_values(labels),
+            );
+            moving_data.highest_priority.and_set(
+                &metrics
+                    .data
+                    .moving_data_highest_priority
+                    .with_label_values(labels),
+            );
+            metrics.and_set_saturating(
+                moving_data.total_written_bytes,
+                &metrics
+                    .data
+                    .moving_data_total_written_bytes
+                    .with_label_values(labels),
+            );
+        }
+
+        // A region may have been removed since the previous status
+        remove_team_tracker_series(metrics, labels[0]);
+        for tracker in &self.team_trackers {
+            let tracker_labels = [labels, &[tracker.region()]].concat();
+            metrics.and_set_saturating(
+                tracker.in_flight_bytes,
+                &metrics
+                    .data
+                    .team_tracker_in_flight_bytes
+                    .with_label_values(&tracker_labels),
+            );
+            tracker.unhealthy_servers.and_set(
+                &metrics
+                    .data
+                    .team_tracker_unhealthy_servers
+                    .with_label_values(&tracker_labels),
+            );
+            let Some(state) = &tracker.state else {
+                continue;
+            };
+            if let Some(healthy) = state.healthy {
+                metrics
+                    .data
+                    .team_tracker_healthy
+                    .with_label_values(&tracker_labels)
+                    .set(healthy as i64);
+            }
+            state.min_replicas_remaining.and_set(
+                &metrics
+                    .data
+                    .team_tracker_min_replicas_remaining
+                    .with_label_values(&tracker_labels),
+            );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::core::Collector;
+    use prometheus::Registry;
+
+    #[test]
+    fn shard_sizes() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = ["shards"];
+        shard_sizes_to_metrics(metrics, &labels, &[500, 20_000_000, 600_000_000]);
+
+        assert_eq!(
+            metrics
+                .data
+                .largest_shard_bytes
+                .with_label_values(&labels)
+                .get(),
+            600_000_000
+        );
+        let shards = |le| {
+            metrics
+                .data
+                .shards_by_size
+                .with_label_values(&["shards", le])
+                .get()
+        };
+        assert_eq!(shards("1000000"), 1);
+        assert_eq!(shards("100000000"), 2);
+        assert_eq!(shards("500000000"), 2);
+        assert_eq!(shards("+Inf"), 3);
+    }
+
+    #[test]
+    fn saturate_above_i64_max() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let data: ClusterData = serde_json::from_str(
+            r#"{
+                "total_kv_size_bytes": 18446744073709551615,
+                "moving_data": {
+                    "highest_priority": 700,
+                    "in_flight_bytes": 9223372036854775808,
+                    "in_queue_bytes": 12,
+                    "total_written_bytes": 0
+                }
+            }"#,
+        )
+        .unwrap();
+        data.to_metrics(metrics, &["test"]);
+
+        assert_eq!(
+            metrics
+                .data
+                .total_kv_size_bytes
+                .with_label_values(&["test"])
+                .get(),
+            i64::MAX
+        );
+        assert_eq!(
+            metrics
+                .data
+                .moving_data_in_flight_bytes
+                .with_label_values(&["test"])
+                .get(),
+            i64::MAX
+        );
+        assert_eq!(
+            metrics
+                .data
+                .moving_data_in_queue_bytes
+                .with_label_values(&["test"])
+                .get(),
+            12
+        );
+        assert_eq!(
+            metrics
+                .data
+                .moving_data_highest_priority
+                .with_label_values(&["test"])
+                .get(),
+            700
+        );
+    }
+
+    #[test]
+    fn missing_data_keeps_or_resets_gauges() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let data: ClusterData = serde_json::from_str(r#"{"partitions_count": 42}"#).unwrap();
+        let labels = &["missing"];
+
+        data.to_metrics(metrics, labels);
+        assert_eq!(metrics.data.data_present.with_label_values(labels).get(), 1);
+
+        missing_cluster_data(metrics, labels, false);
+        assert_eq!(metrics.data.data_present.with_label_values(labels).get(), 0);
+        assert_eq!(
+            metrics.data.partition_count.with_label_values(labels).get(),
+            42
+        );
+
+        missing_cluster_data(metrics, labels, true);
+        assert_eq!(metrics.data.data_present.with_label_values(labels).get(), 0);
+        assert!(metrics
+            .data
+            .partition_count
+            .remove_label_values(labels)
+            .is_err());
+    }
+
+    #[test]
+    fn state_info_follows_description() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["state"];
+        let parse = |description: &str| -> ClusterData {
+            serde_json::from_value(serde_json::json!({
+                "state": {
+                    "healthy": false,
+                    "name": "healing",
+                    "description": description,
+                    "min_replicas_remaining": 2
+                }
+            }))
+            .unwrap()
+        };
+
+        parse("Restoring replication factor").to_metrics(metrics, labels);
+        assert_eq!(
+            metrics
+                .data
+                .min_replicas_remaining
+                .with_label_values(labels)
+                .get(),
+            2
+        );
+        assert_eq!(
+            metrics
+                .data
+                .state_info
+                .with_label_values(&["state", "Restoring replication factor"])
+                .get(),
+            1
+        );
+
+        assert_eq!(
+            metrics
+                .data
+                .state_name
+                .with_label_values(&["state", "healing"])
+                .get(),
+            1
+        );
+
+        parse("Healthy").to_metrics(metrics, labels);
+        assert!(metrics
+            .data
+            .state_info
+            .remove_label_values(&["state", "Restoring replication factor"])
+            .is_err());
+        assert!(metrics
+            .data
+            .state_info
+            .remove_label_values(&["state", "Healthy"])
+            .is_ok());
+    }
+
+    #[test]
+    fn state_name_is_replaced() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["names"];
+        let parse = |name: &str| -> ClusterData {
+            serde_json::from_value(serde_json::json!({ "state": { "name": name } })).unwrap()
+        };
+
+        parse("healing").to_metrics(metrics, labels);
+        parse("healthy_rebalancing").to_metrics(metrics, labels);
+        let names: Vec<String> = metrics.data.state_name.collect()[0]
+            .get_metric()
+            .iter()
+            .flat_map(|metric| metric.get_label())
+            .filter(|label| label.get_name() == "name")
+            .map(|label| label.get_value().to_string())
+            .collect();
+        assert_eq!(names, ["healthy_rebalancing"]);
+    }
+
+    #[test]
+    fn team_trackers_by_region() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["trackers"];
+        let parse = |trackers: serde_json::Value| -> ClusterData {
+            serde_json::from_value(serde_json::json!({ "team_trackers": trackers })).unwrap()
+        };
+
+        parse(serde_json::json!([
+            {"primary": true, "in_flight_bytes": 100, "unhealthy_servers": 0, "state": {"healthy": true}},
+            {"primary": false, "in_flight_bytes": 2048, "unhealthy_servers": 1, "state": {"healthy": false, "min_replicas_remaining": 1}}
+        ]))
+        .to_metrics(metrics, labels);
+        let remote = &["trackers", "remote"];
+        assert_eq!(
+            metrics
+                .data
+                .team_tracker_in_flight_bytes
+                .with_label_values(remote)
+                .get(),
+            2048
+        );
+        assert_eq!(
+            metrics
+                .data
+                .team_tracker_unhealthy_servers
+                .with_label_values(remote)
+                .get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .data
+                .team_tracker_healthy
+                .with_label_values(remote)
+                .get(),
+            0
+        );
+        assert_eq!(
+            metrics
+                .data
+                .team_tracker_min_replicas_remaining
+                .with_label_values(remote)
+                .get(),
+            1
+        );
+
+        parse(serde_json::json!([{"primary": true, "in_flight_bytes": 0}]))
+            .to_metrics(metrics, labels);
+        assert!(metrics
+            .data
+            .team_tracker_in_flight_bytes
+            .remove_label_values(remote)
+            .is_err());
+    }
+}
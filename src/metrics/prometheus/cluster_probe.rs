@@ -1,44 +1,75 @@
-use lazy_static::lazy_static;
-use prometheus::{register_gauge, Gauge};
+use prometheus::{register_gauge_vec_with_registry, GaugeVec};
 
+use super::{Metrics, MetricsRegistry, CLUSTER_LABELS};
 use crate::{metrics::MetricsConvertible, status_models::cluster_probe::ClusterLatencyProbe};
 
-lazy_static! {
-    static ref P_CLUSTER_LATENCY_PROBE_COMMIT_SECONDS: Gauge = register_gauge!(
-        "fdb_cluster_latency_commit_seconds",
-        "Time in seconds to commit a transaction"
-    )
-    .unwrap();
-    static ref P_CLUSTER_LATENCY_READ_SECONDS: Gauge = register_gauge!(
-        "fdb_cluster_latency_read_seconds",
-        "Time in seconds to read"
-    )
-    .unwrap();
-    static ref P_CLUSTER_LATENCY_TRANSACTION_START_SECONDS: Gauge = register_gauge!(
-        "fdb_cluster_latency_transaction_start_seconds",
-        "Time in seconds to start a transaction"
-    )
-    .unwrap();
-    static ref P_CLUSTER_LATENCY_IMMEDIATE_PRIORITY_START_SECONDS: Gauge = register_gauge!(
-        "fdb_cluster_latency_immediate_priority_start_seconds",
-        "N/A"
-    )
-    .unwrap();
+/// Metrics of the latency probe
+pub struct ProbeMetrics {
+    latency_probe_commit_seconds: GaugeVec,
+    latency_read_seconds: GaugeVec,
+    latency_transaction_start_seconds: GaugeVec,
+    latency_immediate_priority_start_seconds: GaugeVec,
+}
+
+impl ProbeMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProbeMetrics {
+            latency_probe_commit_seconds: register_gauge_vec_with_registry!(
+                "fdb_cluster_latency_commit_seconds",
+                "Time in seconds to commit a transaction",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            latency_read_seconds: register_gauge_vec_with_registry!(
+                "fdb_cluster_latency_read_seconds",
+                "Time in seconds to read",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            latency_transaction_start_seconds: register_gauge_vec_with_registry!(
+                "fdb_cluster_latency_transaction_start_seconds",
+                "Time in seconds to start a transaction",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            latency_immediate_priority_start_seconds: register_gauge_vec_with_registry!(
+                "fdb_cluster_latency_immediate_priority_start_seconds",
+                "N/A",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
 }
 
 impl MetricsConvertible for ClusterLatencyProbe {
-    fn to_metrics(&self, _: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
         if let Some(commit_seconds) = self.commit_seconds {
-            P_CLUSTER_LATENCY_PROBE_COMMIT_SECONDS.set(commit_seconds);
+            metrics
+                .probe
+                .latency_probe_commit_seconds
+                .with_label_values(labels)
+                .set(commit_seconds);
         }
         if let Some(read_seconds) = self.read_seconds {
-            P_CLUSTER_LATENCY_READ_SECONDS.set(read_seconds);
+            metrics
+                .probe
+                .latency_read_seconds
+                .with_label_values(labels)
+                .set(read_seconds);
         }
         if let Some(transaction_start_seconds) = self.transaction_start_seconds {
-            P_CLUSTER_LATENCY_TRANSACTION_START_SECONDS.set(transaction_start_seconds);
+            metrics
+                .probe
+                .latency_transaction_start_seconds
+                .with_label_values(labels)
+                .set(transaction_start_seconds);
         }
         if let Some(immediate_priority_start_seconds) = self.immediate_priority_start_seconds {
-            P_CLUSTER_LATENCY_IMMEDIATE_PRIORITY_START_SECONDS
+            metrics
+                .probe
+                .latency_immediate_priority_start_seconds
+                .with_label_values(labels)
                 .set(immediate_priority_start_seconds);
         }
     }
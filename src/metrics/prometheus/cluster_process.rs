@@ -1,73 +1,241 @@
-use super::PROCESS_LABELS;
-use crate::{metrics::MetricsConvertible, status_models::cluster_process::ClusterProcess};
-use lazy_static::lazy_static;
-use prometheus::{register_gauge_vec, register_int_gauge_vec, GaugeVec, IntGaugeVec};
-
-lazy_static! {
-    static ref P_PROCESS_EXCLUDED: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_excluded",
-        "Process is being excluded by the cluster",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_CPU_USAGE: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_cpu_usage",
-        "Current usage of CPU (between 0 and 1)",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_UPTIME: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_uptime",
-        "Uptime of the process",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_RUN_LOOP_BUSY: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_busy",
-        "Busy of the process (value between 0.0 and 1.1)",
+use std::collections::HashMap;
+
+use super::{
+    remove_matching_series, unix_timestamp, Metrics, MetricsRegistry, RemoveSeries, PROCESS_LABELS,
+};
+use crate::metrics::MetricsConvertible;
+use crate::status_models::cluster_process::{ClusterProcess, ClusterProcessMessage};
+use prometheus::core::Collector;
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
+
+/// Metrics of [ClusterProcess], labelled with [PROCESS_LABELS]
+pub struct ProcessMetrics {
+    excluded: IntGaugeVec,
+    cpu_usage: GaugeVec,
+    cpu_usage_smoothed: GaugeVec,
+    uptime: GaugeVec,
+    run_loop_busy: GaugeVec,
+    last_message_timestamp: GaugeVec,
+}
+
+impl ProcessMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProcessMetrics {
+            excluded: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_excluded",
+                "Process is being excluded by the cluster",
+                PROCESS_LABELS,
+                registry
+            )?,
+            cpu_usage: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_cpu_usage",
+                "Current usage of CPU (between 0 and 1)",
+                PROCESS_LABELS,
+                registry
+            )?,
+            cpu_usage_smoothed: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_cpu_usage_smoothed",
+                "Current usage of CPU (between 0 and 1), smoothed with --smoothing-decay",
+                PROCESS_LABELS,
+                registry
+            )?,
+            uptime: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_uptime",
+                "Uptime of the process",
+                PROCESS_LABELS,
+                registry
+            )?,
+            run_loop_busy: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_busy",
+                "Busy of the process (value between 0.0 and 1.1)",
+                PROCESS_LABELS,
+                registry
+            )?,
+            last_message_timestamp: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_last_message_timestamp_seconds",
+                "Time the last message of a name currently reported by the process was raised at, or was first seen at when the status has no time",
+                &["cluster", "machine_id", "process_id", "class_type", "address", "name"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Remove every series of the process with the given [PROCESS_LABELS]
+/// values, including the ones of its disk, memory, network and roles
+pub fn remove_process_series(metrics: &Metrics, labels: &[&str]) {
+    let series: [&dyn RemoveSeries; 4] = [
+        &metrics.process.excluded,
+        &metrics.process.cpu_usage,
+        &metrics.process.uptime,
+        &metrics.process.run_loop_busy,
+    ];
+    series
+        .iter()
+        .for_each(|metric| metric.remove_series(labels));
+    metrics.remove_smoothed(&metrics.process.cpu_usage_smoothed, labels);
+    remove_matching_series(
+        &metrics.process.last_message_timestamp,
         PROCESS_LABELS,
-    )
-    .unwrap();
+        labels,
+    );
+    super::cluster_process_disk::remove_process_series(metrics, labels);
+    super::cluster_process_memory::remove_process_series(metrics, labels);
+    super::cluster_process_network::remove_process_series(metrics, labels);
+    super::cluster_process_role::remove_process_series(metrics, labels);
+}
+
+/// Export the smoothed counterpart of noisy process gauges, see [Metrics::set_smoothed]
+pub fn smoothed_to_metrics(
+    metrics: &Metrics,
+    process: &ClusterProcess,
+    labels: &[&str],
+    decay: f64,
+) {
+    if let Some(cpu) = &process.cpu {
+        metrics.set_smoothed(
+            &metrics.process.cpu_usage_smoothed,
+            labels,
+            cpu.usage_cores,
+            decay,
+        );
+    }
 }
 
 impl MetricsConvertible for ClusterProcess {
-    fn to_metrics(&self, labels: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
         if let Some(uptime) = self.uptime_seconds {
-            P_PROCESS_UPTIME.with_label_values(labels).set(uptime);
+            metrics.process.uptime.with_label_values(labels).set(uptime);
         }
 
         if let Some(run_loop_busy) = self.run_loop_busy {
-            P_PROCESS_RUN_LOOP_BUSY
+            metrics
+                .process
+                .run_loop_busy
                 .with_label_values(labels)
                 .set(run_loop_busy);
         }
 
         if let Some(excluded) = self.excluded {
-            P_PROCESS_EXCLUDED
+            metrics
+                .process
+                .excluded
                 .with_label_values(labels)
                 .set(excluded as i64);
         }
 
         if let Some(cpu) = &self.cpu {
-            P_PROCESS_CPU_USAGE
+            metrics
+                .process
+                .cpu_usage
                 .with_label_values(labels)
                 .set(cpu.usage_cores);
         }
 
         if let Some(disk) = &self.disk {
-            disk.to_metrics(labels);
+            disk.to_metrics(metrics, labels);
         }
 
         if let Some(network) = &self.network {
-            network.to_metrics(labels);
+            network.to_metrics(metrics, labels);
         }
 
         if let Some(memory) = &self.memory {
-            memory.to_metrics(labels);
+            memory.to_metrics(metrics, labels);
         }
 
-        for role in &self.roles {
-            role.to_metrics(labels);
-        }
+        messages_to_metrics(metrics, &self.messages, labels);
+    }
+}
+
+/// Export the time of the last message of each name, messages which are no
+/// longer reported are removed
+fn messages_to_metrics(metrics: &Metrics, messages: &[ClusterProcessMessage], labels: &[&str]) {
+    let gauge = &metrics.process.last_message_timestamp;
+    // Messages without a time were seen during this scrape at the latest
+    let seen = unix_timestamp() as f64;
+    let previous: HashMap<String, f64> = gauge
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter(|series| is_process_series(series, labels))
+        .filter_map(|series| {
+            let name = series
+                .get_label()
+                .iter()
+                .find(|label| label.get_name() == "name")?;
+            Some((name.get_value().to_string(), series.get_gauge().get_value()))
+        })
+        .collect();
+    remove_matching_series(gauge, PROCESS_LABELS, labels);
+
+    for message in messages {
+        let time = message
+            .time
+            .or_else(|| previous.get(&message.name).copied())
+            .unwrap_or(seen);
+        let series = gauge.with_label_values(&[labels, &[message.name.as_str()]].concat());
+        series.set(series.get().max(time));
+    }
+}
+
+/// Whether `series` has the given [PROCESS_LABELS] values
+fn is_process_series(series: &prometheus::proto::Metric, labels: &[&str]) -> bool {
+    PROCESS_LABELS.iter().zip(labels).all(|(name, value)| {
+        series
+            .get_label()
+            .iter()
+            .any(|label| label.get_name() == *name && label.get_value() == *value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn last_message_timestamps() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["cluster", "machine", "process", "storage", "10.0.0.1:4500"];
+        let timestamp = |name: &str| {
+            metrics
+                .process
+                .last_message_timestamp
+                .get_metric_with_label_values(&[labels.as_slice(), &[name]].concat())
+                .map(|gauge| gauge.get())
+                .ok()
+        };
+        let parse = |messages: serde_json::Value| -> Vec<ClusterProcessMessage> {
+            serde_json::from_value(messages).unwrap()
+        };
+
+        messages_to_metrics(
+            metrics,
+            &parse(serde_json::json!([
+                {"name": "io_timeout", "time": 100.0},
+                {"name": "io_timeout", "time": 200.0},
+                {"name": "file_open_error"}
+            ])),
+            labels,
+        );
+        assert_eq!(timestamp("io_timeout"), Some(200.0));
+        let first_seen = timestamp("file_open_error").unwrap();
+        assert!(first_seen > 0.0);
+
+        // Messages without time keep the time they were first seen at
+        messages_to_metrics(
+            metrics,
+            &parse(serde_json::json!([{"name": "file_open_error"}])),
+            labels,
+        );
+        assert_eq!(timestamp("file_open_error"), Some(first_seen));
+        assert!(metrics
+            .process
+            .last_message_timestamp
+            .remove_label_values(&[labels.as_slice(), &["io_timeout"]].concat())
+            .is_err());
     }
 }
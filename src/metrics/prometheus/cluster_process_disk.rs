@@ -1,92 +1,148 @@
-use crate::metrics::prometheus::PROCESS_LABELS;
+use crate::metrics::prometheus::{Metrics, MetricsRegistry, RemoveSeries, PROCESS_LABELS};
 use crate::{metrics::MetricsConvertible, status_models::cluster_process_disk::ClusterProcessDisk};
-use lazy_static::lazy_static;
-use prometheus::{register_gauge_vec, register_int_gauge_vec, GaugeVec, IntGaugeVec};
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
 
-lazy_static! {
-    static ref P_PROCESS_DISK_BUSY: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_disk_busy",
-        "Disk is being busy (0.0 to 1.0 value)",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_FREE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_disk_free_bytes",
-        "Bytes available on the disk used by process",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_TOTAL_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_disk_total_bytes",
-        "Bytes total on the disk used by process",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_READS_COUNTER: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_disk_reads_count",
-        "Number of reads on the disk",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_READS_FREQ: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_disk_reads_frequency",
-        "Frequency of reads on the disk",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_READS_SECTORS: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_disk_reads_sectors",
-        "N/A",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_WRITES_COUNTER: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_disk_writes_count",
-        "Number of writes on the disk",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_WRITES_FREQ: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_disk_writes_frequency",
-        "Frequency of writes on the disk",
-        PROCESS_LABELS
-    )
-    .unwrap();
-    static ref P_PROCESS_DISK_WRITES_SECTORS: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_disk_writes_sectors",
-        "N/A",
-        PROCESS_LABELS
-    )
-    .unwrap();
+/// Metrics of the disk of a process, labelled with [PROCESS_LABELS]
+pub struct ProcessDiskMetrics {
+    busy: GaugeVec,
+    free_bytes: IntGaugeVec,
+    total_bytes: IntGaugeVec,
+    reads_counter: IntGaugeVec,
+    reads_freq: GaugeVec,
+    reads_sectors: GaugeVec,
+    writes_counter: IntGaugeVec,
+    writes_freq: GaugeVec,
+    writes_sectors: GaugeVec,
+}
+
+impl ProcessDiskMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProcessDiskMetrics {
+            busy: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_busy",
+                "Disk is being busy (0.0 to 1.0 value)",
+                PROCESS_LABELS,
+                registry
+            )?,
+            free_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_free_bytes",
+                "Bytes available on the disk used by process",
+                PROCESS_LABELS,
+                registry
+            )?,
+            total_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_total_bytes",
+                "Bytes total on the disk used by process",
+                PROCESS_LABELS,
+                registry
+            )?,
+            reads_counter: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_reads_count",
+                "Number of reads on the disk",
+                PROCESS_LABELS,
+                registry
+            )?,
+            reads_freq: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_reads_frequency",
+                "Frequency of reads on the disk",
+                PROCESS_LABELS,
+                registry
+            )?,
+            reads_sectors: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_reads_sectors",
+                "N/A",
+                PROCESS_LABELS,
+                registry
+            )?,
+            writes_counter: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_writes_count",
+                "Number of writes on the disk",
+                PROCESS_LABELS,
+                registry
+            )?,
+            writes_freq: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_writes_frequency",
+                "Frequency of writes on the disk",
+                PROCESS_LABELS,
+                registry
+            )?,
+            writes_sectors: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_disk_writes_sectors",
+                "N/A",
+                PROCESS_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Remove every series of the process with the given [PROCESS_LABELS] values
+pub fn remove_process_series(metrics: &Metrics, labels: &[&str]) {
+    let series: [&dyn RemoveSeries; 9] = [
+        &metrics.process_disk.busy,
+        &metrics.process_disk.free_bytes,
+        &metrics.process_disk.total_bytes,
+        &metrics.process_disk.reads_counter,
+        &metrics.process_disk.reads_freq,
+        &metrics.process_disk.reads_sectors,
+        &metrics.process_disk.writes_counter,
+        &metrics.process_disk.writes_freq,
+        &metrics.process_disk.writes_sectors,
+    ];
+    series
+        .iter()
+        .for_each(|metric| metric.remove_series(labels));
 }
 
 impl MetricsConvertible for ClusterProcessDisk {
-    fn to_metrics(&self, labels: &[&str]) {
-        P_PROCESS_DISK_BUSY.with_label_values(labels).set(self.busy);
-        P_PROCESS_DISK_FREE_BYTES
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .process_disk
+            .busy
+            .with_label_values(labels)
+            .set(self.busy);
+        metrics
+            .process_disk
+            .free_bytes
             .with_label_values(labels)
             .set(self.free_bytes);
-        P_PROCESS_DISK_TOTAL_BYTES
+        metrics
+            .process_disk
+            .total_bytes
             .with_label_values(labels)
             .set(self.total_bytes);
 
-        P_PROCESS_DISK_READS_FREQ
+        metrics
+            .process_disk
+            .reads_freq
             .with_label_values(labels)
             .set(self.reads.hz);
-        P_PROCESS_DISK_READS_COUNTER
+        metrics
+            .process_disk
+            .reads_counter
             .with_label_values(labels)
             .set(self.reads.counter);
-        P_PROCESS_DISK_READS_SECTORS
+        metrics
+            .process_disk
+            .reads_sectors
             .with_label_values(labels)
             .set(self.reads.sectors);
 
-        P_PROCESS_DISK_WRITES_FREQ
+        metrics
+            .process_disk
+            .writes_freq
             .with_label_values(labels)
             .set(self.writes.hz);
-        P_PROCESS_DISK_WRITES_COUNTER
+        metrics
+            .process_disk
+            .writes_counter
             .with_label_values(labels)
             .set(self.writes.counter);
-        P_PROCESS_DISK_WRITES_SECTORS
+        metrics
+            .process_disk
+            .writes_sectors
             .with_label_values(labels)
             .set(self.writes.sectors);
     }
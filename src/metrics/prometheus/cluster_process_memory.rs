@@ -1,70 +1,156 @@
-use crate::metrics::prometheus::PROCESS_LABELS;
+use crate::metrics::prometheus::{Metrics, MetricsRegistry, RemoveSeries, PROCESS_LABELS};
 use crate::{
     metrics::MetricsConvertible, status_models::cluster_process_memory::ClusterProcessMemory,
 };
-use lazy_static::lazy_static;
-use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
+
+/// Metrics of the memory of a process, labelled with [PROCESS_LABELS]
+pub struct ProcessMemoryMetrics {
+    available_bytes: IntGaugeVec,
+    limit_bytes: IntGaugeVec,
+    rss_bytes: IntGaugeVec,
+    unused_bytes: IntGaugeVec,
+    used_bytes: IntGaugeVec,
+    used_ratio: GaugeVec,
+}
 
-lazy_static! {
-    static ref P_PROCESS_MEMORY_AVAILABLE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_memory_available_bytes",
-        "Available bytes for the current process",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_MEMORY_LIMIT_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_memory_limit_bytes",
-        "Limiting bytes for the current process",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_MEMORY_RSS_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_memory_rss_bytes",
-        "N/A",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_MEMORY_UNUSED_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_memory_unused_allocated_bytes",
-        "N/A",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_MEMORY_USED_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_memory_used_bytes",
-        "N/A",
-        PROCESS_LABELS,
-    )
-    .unwrap();
+impl ProcessMemoryMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProcessMemoryMetrics {
+            available_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_available_bytes",
+                "Available bytes for the current process",
+                PROCESS_LABELS,
+                registry
+            )?,
+            limit_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_limit_bytes",
+                "Limiting bytes for the current process",
+                PROCESS_LABELS,
+                registry
+            )?,
+            rss_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_rss_bytes",
+                "N/A",
+                PROCESS_LABELS,
+                registry
+            )?,
+            unused_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_unused_allocated_bytes",
+                "N/A",
+                PROCESS_LABELS,
+                registry
+            )?,
+            used_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_used_bytes",
+                "N/A",
+                PROCESS_LABELS,
+                registry
+            )?,
+            used_ratio: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_memory_used_ratio",
+                "Ratio of the memory used to the memory limit of the process",
+                PROCESS_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Remove every series of the process with the given [PROCESS_LABELS] values
+pub fn remove_process_series(metrics: &Metrics, labels: &[&str]) {
+    let series: [&dyn RemoveSeries; 6] = [
+        &metrics.process_memory.available_bytes,
+        &metrics.process_memory.limit_bytes,
+        &metrics.process_memory.rss_bytes,
+        &metrics.process_memory.unused_bytes,
+        &metrics.process_memory.used_bytes,
+        &metrics.process_memory.used_ratio,
+    ];
+    series
+        .iter()
+        .for_each(|metric| metric.remove_series(labels));
 }
 
 impl MetricsConvertible for ClusterProcessMemory {
-    fn to_metrics(&self, labels: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
         if let Some(available_bytes) = self.available_bytes {
-            P_PROCESS_MEMORY_AVAILABLE_BYTES
+            metrics
+                .process_memory
+                .available_bytes
                 .with_label_values(labels)
                 .set(available_bytes);
         }
         if let Some(limit_bytes) = self.limit_bytes {
-            P_PROCESS_MEMORY_LIMIT_BYTES
+            metrics
+                .process_memory
+                .limit_bytes
                 .with_label_values(labels)
                 .set(limit_bytes);
         }
         if let Some(rss_bytes) = self.rss_bytes {
-            P_PROCESS_MEMORY_RSS_BYTES
+            metrics
+                .process_memory
+                .rss_bytes
                 .with_label_values(labels)
                 .set(rss_bytes);
         }
 
         if let Some(unused_allocated_memory) = self.unused_allocated_memory {
-            P_PROCESS_MEMORY_UNUSED_BYTES
+            metrics
+                .process_memory
+                .unused_bytes
                 .with_label_values(labels)
                 .set(unused_allocated_memory);
         }
         if let Some(used_bytes) = self.used_bytes {
-            P_PROCESS_MEMORY_USED_BYTES
+            metrics
+                .process_memory
+                .used_bytes
                 .with_label_values(labels)
                 .set(used_bytes);
         }
+        if let Some(used_ratio) = self.used_ratio() {
+            metrics
+                .process_memory
+                .used_ratio
+                .with_label_values(labels)
+                .set(used_ratio);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn used_ratio() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["test", "machine", "process", "storage", "1.2.3.4:4500"];
+        let memory = ClusterProcessMemory {
+            used_bytes: Some(3),
+            limit_bytes: Some(4),
+            ..Default::default()
+        };
+        memory.to_metrics(metrics, labels);
+        assert_eq!(
+            metrics
+                .process_memory
+                .used_ratio
+                .with_label_values(labels)
+                .get(),
+            0.75
+        );
+
+        let unlimited = ClusterProcessMemory {
+            used_bytes: Some(3),
+            limit_bytes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(unlimited.used_ratio(), None);
     }
 }
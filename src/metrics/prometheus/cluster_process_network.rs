@@ -1,58 +1,109 @@
-use super::PROCESS_LABELS;
+use super::{Metrics, MetricsRegistry, RemoveSeries, PROCESS_LABELS};
 use crate::{
     metrics::MetricsConvertible, status_models::cluster_process_network::ClusterProcessNetwork,
 };
-use lazy_static::lazy_static;
-use prometheus::{register_gauge_vec, GaugeVec};
+use prometheus::{register_gauge_vec_with_registry, GaugeVec};
 
-lazy_static! {
-    static ref P_PROCESS_NETWORK_CONN_ERRORS: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_network_connection_errors_freq",
-        "Frequency of connection errors",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_NETWORK_CONN_CLOSED: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_network_connections_closed",
-        "Frequency of connection closed",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_NETWORK_CONN_ESTABLISHED: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_network_connections_established",
-        "Frequency of connection established",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_NETWORK_MEGABITS_RECEIVED: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_network_received_megabits",
-        "Megabits received on network",
-        PROCESS_LABELS,
-    )
-    .unwrap();
-    static ref P_PROCESS_NETWORK_MEGABITS_SENT: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_network_sent_megabits",
-        "Megabits sent on network",
-        PROCESS_LABELS,
-    )
-    .unwrap();
+/// Metrics of the network of a process, labelled with [PROCESS_LABELS]
+pub struct ProcessNetworkMetrics {
+    conn_errors: GaugeVec,
+    tls_policy_failures: GaugeVec,
+    conn_closed: GaugeVec,
+    conn_established: GaugeVec,
+    megabits_received: GaugeVec,
+    megabits_sent: GaugeVec,
+}
+
+impl ProcessNetworkMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProcessNetworkMetrics {
+            conn_errors: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_connection_errors_freq",
+                "Frequency of connection errors",
+                PROCESS_LABELS,
+                registry
+            )?,
+            tls_policy_failures: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_tls_policy_failures_freq",
+                "Frequency of connections rejected by the TLS peer verification policy",
+                PROCESS_LABELS,
+                registry
+            )?,
+            conn_closed: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_connections_closed",
+                "Frequency of connection closed",
+                PROCESS_LABELS,
+                registry
+            )?,
+            conn_established: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_connections_established",
+                "Frequency of connection established",
+                PROCESS_LABELS,
+                registry
+            )?,
+            megabits_received: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_received_megabits",
+                "Megabits received on network",
+                PROCESS_LABELS,
+                registry
+            )?,
+            megabits_sent: register_gauge_vec_with_registry!(
+                "fdb_cluster_process_network_sent_megabits",
+                "Megabits sent on network",
+                PROCESS_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Remove every series of the process with the given [PROCESS_LABELS] values
+pub fn remove_process_series(metrics: &Metrics, labels: &[&str]) {
+    let series: [&dyn RemoveSeries; 6] = [
+        &metrics.process_network.conn_errors,
+        &metrics.process_network.tls_policy_failures,
+        &metrics.process_network.conn_closed,
+        &metrics.process_network.conn_established,
+        &metrics.process_network.megabits_received,
+        &metrics.process_network.megabits_sent,
+    ];
+    series
+        .iter()
+        .for_each(|metric| metric.remove_series(labels));
 }
 
 impl MetricsConvertible for ClusterProcessNetwork {
-    fn to_metrics(&self, labels: &[&str]) {
-        P_PROCESS_NETWORK_CONN_ERRORS
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .process_network
+            .conn_errors
             .with_label_values(labels)
             .set(self.connection_errors.hz);
-        P_PROCESS_NETWORK_CONN_CLOSED
+        if let Some(tls_policy_failures) = self.tls_policy_failures {
+            metrics
+                .process_network
+                .tls_policy_failures
+                .with_label_values(labels)
+                .set(tls_policy_failures.into());
+        }
+        metrics
+            .process_network
+            .conn_closed
             .with_label_values(labels)
             .set(self.connections_closed.hz);
-        P_PROCESS_NETWORK_CONN_ESTABLISHED
+        metrics
+            .process_network
+            .conn_established
             .with_label_values(labels)
             .set(self.connections_established.into());
-        P_PROCESS_NETWORK_MEGABITS_RECEIVED
+        metrics
+            .process_network
+            .megabits_received
             .with_label_values(labels)
             .set(self.megabits_received.into());
-        P_PROCESS_NETWORK_MEGABITS_SENT
+        metrics
+            .process_network
+            .megabits_sent
             .with_label_values(labels)
             .set(self.megabits_sent.into());
     }
@@ -1,113 +1,211 @@
 use std::collections::HashMap;
 
-use lazy_static::lazy_static;
-use prometheus::{register_gauge_vec, register_int_gauge_vec, GaugeVec, IntGaugeVec};
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
 use tracing::warn;
 
 use crate::metrics::prometheus::PROCESS_LABELS;
 use crate::{
     metrics::{prometheus::AndSet, MetricsConvertible},
+    status_models::cluster_process::ClusterClassType,
     status_models::cluster_process_role::{
         ClusterProcessRole, ClusterProcessRoleFreq, LatencyStats,
     },
 };
 
-use super::StaticMetric;
+use super::{remove_matching_series, Metrics, MetricsRegistry, RemoveSeries, StaticMetric};
 
-lazy_static! {
+/// Metrics of [ClusterProcessRole], labelled with [PROCESS_LABELS]
+pub struct ProcessRoleMetrics {
     // KvStore
-    static ref P_KVSTORE_USED_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_kvstore_used_bytes",
-        "KVStore used bytes",
-        PROCESS_LABELS,
-    ).unwrap();
-
-    static ref P_KVSTORE_AVAILABLE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_kvstore_available_bytes",
-        "KVStore available bytes",
-        PROCESS_LABELS,
-    ).unwrap();
-
-    static ref P_KVSTORE_FREE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_kvstore_free_bytes",
-        "KVStore free bytes",
-        PROCESS_LABELS,
-    ).unwrap();
+    kvstore_used_bytes: IntGaugeVec,
+    kvstore_available_bytes: IntGaugeVec,
+    kvstore_free_bytes: IntGaugeVec,
+    kvstore_total_bytes: IntGaugeVec,
     // Queue related
-    static ref P_QUERY_QUEUE_MAX: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_role_queue_max",
-        "Queue of read queries",
-        PROCESS_LABELS,
-    ).unwrap();
-    static ref P_QUEUE_DISK_USED_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_queue_disk_used_bytes",
-        "Used bytes in the queue of a process",
-        PROCESS_LABELS,
-    ).unwrap();
-
-    static ref P_QUEUE_DISK_AVAILABLE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_queue_disk_available_bytes",
-        "Available bytes in the queue of a process",
-        PROCESS_LABELS,
-    ).unwrap();
-
-    static ref P_QUEUE_DISK_FREE_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_queue_disk_free_bytes",
-        "Free bytes in the queue of a process",
-        PROCESS_LABELS,
-    ).unwrap();
-
-    static ref P_QUEUE_DISK_TOTAL_BYTES: IntGaugeVec = register_int_gauge_vec!(
-        "fdb_cluster_process_role_queue_disk_total_bytes",
-        "Total bytes in the queue of a process",
-        PROCESS_LABELS,
-    ).unwrap();
-
+    query_queue_max: GaugeVec,
+    queue_disk_used_bytes: IntGaugeVec,
+    queue_disk_available_bytes: IntGaugeVec,
+    queue_disk_free_bytes: IntGaugeVec,
+    queue_disk_total_bytes: IntGaugeVec,
     // Lag related
-    static ref P_DATA_LAG_SECONDS: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_role_data_lag_seconds",
-        "Lag in seconds of the process role",
-        PROCESS_LABELS,
-    ).unwrap();
+    data_lag_seconds: GaugeVec,
+    data_durable_lag_seconds: GaugeVec,
+    // Storage saturation
+    storage_local_rate: GaugeVec,
+    // Log backlog
+    log_unflushed_bytes: IntGaugeVec,
+    // Storage engine, labelled with the engine on top of PROCESS_LABELS
+    storage_engine_info: IntGaugeVec,
+    // Latency related
+    data_read_latency: HashMap<String, GaugeVec>,
+    data_commit_latency: HashMap<String, GaugeVec>,
+    data_commit_batching_window_size: HashMap<String, GaugeVec>,
+    data_grv_proxy_latency: HashMap<String, GaugeVec>,
+    data_grv_proxy_batching_latency: HashMap<String, GaugeVec>,
+    // Frequencies related
+    data_freq_total_queries: HashMap<String, GaugeVec>,
+    data_freq_finished_queries: HashMap<String, GaugeVec>,
+    data_freq_low_priority_queries: HashMap<String, GaugeVec>,
+    data_freq_bytes_queried: HashMap<String, GaugeVec>,
+    data_freq_keys_queried: HashMap<String, GaugeVec>,
+    data_freq_mutation_bytes: HashMap<String, GaugeVec>,
+    data_freq_mutation: HashMap<String, GaugeVec>,
+    data_freq_fetched_versions: HashMap<String, GaugeVec>,
+    data_freq_fetches_from_log: HashMap<String, GaugeVec>,
+    data_freq_input_bytes: HashMap<String, GaugeVec>,
+    data_freq_durable_bytes: HashMap<String, GaugeVec>,
+}
 
-    static ref P_DATA_DURABLE_LAG_SECONDS: GaugeVec = register_gauge_vec!(
-        "fdb_cluster_process_role_durable_lag_seconds",
-        "Lag in seconds of data being durable of the process role",
-        PROCESS_LABELS,
-    ).unwrap();
+impl ProcessRoleMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ProcessRoleMetrics {
+            kvstore_used_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_kvstore_used_bytes",
+"KVStore used bytes",
+PROCESS_LABELS, registry)?,
+            kvstore_available_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_kvstore_available_bytes",
+"KVStore available bytes",
+PROCESS_LABELS, registry)?,
+            kvstore_free_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_kvstore_free_bytes",
+"KVStore free bytes",
+PROCESS_LABELS, registry)?,
+            kvstore_total_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_kvstore_total_bytes",
+"KVStore total bytes",
+PROCESS_LABELS, registry)?,
+            query_queue_max: register_gauge_vec_with_registry!("fdb_cluster_process_role_queue_max",
+"Queue of read queries",
+PROCESS_LABELS, registry)?,
+            queue_disk_used_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_queue_disk_used_bytes",
+"Used bytes in the queue of a process",
+PROCESS_LABELS, registry)?,
+            queue_disk_available_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_queue_disk_available_bytes",
+"Available bytes in the queue of a process",
+PROCESS_LABELS, registry)?,
+            queue_disk_free_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_queue_disk_free_bytes",
+"Free bytes in the queue of a process",
+PROCESS_LABELS, registry)?,
+            queue_disk_total_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_queue_disk_total_bytes",
+"Total bytes in the queue of a process",
+PROCESS_LABELS, registry)?,
+            data_lag_seconds: register_gauge_vec_with_registry!("fdb_cluster_process_role_data_lag_seconds",
+"Lag in seconds of the process role",
+PROCESS_LABELS, registry)?,
+            data_durable_lag_seconds: register_gauge_vec_with_registry!("fdb_cluster_process_role_durable_lag_seconds",
+"Lag in seconds of data being durable of the process role",
+PROCESS_LABELS, registry)?,
+            storage_local_rate: register_gauge_vec_with_registry!("fdb_cluster_process_role_storage_local_rate",
+"Percentage of its normal rate a storage server accepts, lowered when the storage server is saturated (100=not limited)",
+PROCESS_LABELS, registry)?,
+            log_unflushed_bytes: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_log_unflushed_bytes",
+"Bytes received by a log which are not durable yet (input_bytes - durable_bytes)",
+PROCESS_LABELS, registry)?,
+            storage_engine_info: register_int_gauge_vec_with_registry!("fdb_cluster_process_role_storage_engine_info",
+"Storage engine of a storage server, the configured one when the status doesn't report it per server",
+&["cluster", "machine_id", "process_id", "class_type", "address", "engine"], registry)?,
+            data_read_latency: LatencyStats::register(registry, "fdb_cluster_process_role_read_latency", "Latency of read")?,
+            data_commit_latency: LatencyStats::register(registry, "fdb_cluster_process_role_commit_latency", "Latency for proxies")?,
+            data_commit_batching_window_size: LatencyStats::register(registry, "fdb_cluster_process_role_commit_batching_window", "Commit batching window size latency ")?,
+            data_grv_proxy_latency: LatencyStats::register(registry, "fdb_cluster_process_role_grv_proxy_latency", "GRV proxies latency")?,
+            data_grv_proxy_batching_latency: LatencyStats::register(registry, "fdb_cluster_process_role_grv_proxy_batching", "GRV proxies commit batching latency")?,
+            data_freq_total_queries: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_total_queries", "Total number of queries")?,
+            data_freq_finished_queries: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_finished_queries", "Number of finished queries")?,
+            data_freq_low_priority_queries: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_low_priority_queries", "Number of low prio queries")?,
+            data_freq_bytes_queried: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_bytes_queried", "Bytes read from a storage server")?,
+            data_freq_keys_queried: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_keys_queried", "Frequency of read storage server operations in bytes")?,
+            data_freq_mutation_bytes: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_mutation_bytes", "Frequency of mutations in bytes")?,
+            data_freq_mutation: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_mutation", "Frequency of mutation")?,
+            data_freq_fetched_versions: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_fetched_versions", "Frequency of fetched versions in control plane")?,
+            data_freq_fetches_from_log: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_fetches_from_log", "Frequency of fetched data from T logs")?,
+            data_freq_input_bytes: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_input_bytes", "Bytes received by a storage server or a log")?,
+            data_freq_durable_bytes: ClusterProcessRoleFreq::register(registry, "fdb_cluster_process_role_durable_bytes", "Bytes made durable by a storage server or a log")?,
+        })
+    }
+}
 
-    // Latency related
-    static ref P_DATA_READ_LATENCY: HashMap<String, GaugeVec> = LatencyStats::register("fdb_cluster_process_role_read_latency", "Latency of read");
-    static ref P_DATA_COMMIT_LATENCY: HashMap<String, GaugeVec> = LatencyStats::register("fdb_cluster_process_role_commit_latency", "Latency for proxies");
-    static ref P_DATA_COMMIT_BATCHING_WINDOW_SIZE: HashMap<String, GaugeVec> = LatencyStats::register("fdb_cluster_process_role_commit_batching_window", "Commit batching window size latency ");
-    static ref P_DATA_GRV_PROXY_LATENCY: HashMap<String, GaugeVec> = LatencyStats::register("fdb_cluster_process_role_grv_proxy_latency", "GRV proxies latency");
-    static ref P_DATA_GRV_PROXY_BATCHING_LATENCY: HashMap<String, GaugeVec> = LatencyStats::register("fdb_cluster_process_role_grv_proxy_batching", "GRV proxies commit batching latency");
+/// Remove every series of the process with the given [PROCESS_LABELS] values
+pub fn remove_process_series(metrics: &Metrics, labels: &[&str]) {
+    let series: [&dyn RemoveSeries; 29] = [
+        &metrics.process_role.kvstore_used_bytes,
+        &metrics.process_role.kvstore_available_bytes,
+        &metrics.process_role.kvstore_free_bytes,
+        &metrics.process_role.kvstore_total_bytes,
+        &metrics.process_role.query_queue_max,
+        &metrics.process_role.queue_disk_used_bytes,
+        &metrics.process_role.queue_disk_available_bytes,
+        &metrics.process_role.queue_disk_free_bytes,
+        &metrics.process_role.queue_disk_total_bytes,
+        &metrics.process_role.data_lag_seconds,
+        &metrics.process_role.data_durable_lag_seconds,
+        &metrics.process_role.storage_local_rate,
+        &metrics.process_role.log_unflushed_bytes,
+        &metrics.process_role.data_read_latency,
+        &metrics.process_role.data_commit_latency,
+        &metrics.process_role.data_commit_batching_window_size,
+        &metrics.process_role.data_grv_proxy_latency,
+        &metrics.process_role.data_grv_proxy_batching_latency,
+        &metrics.process_role.data_freq_total_queries,
+        &metrics.process_role.data_freq_finished_queries,
+        &metrics.process_role.data_freq_low_priority_queries,
+        &metrics.process_role.data_freq_bytes_queried,
+        &metrics.process_role.data_freq_keys_queried,
+        &metrics.process_role.data_freq_mutation_bytes,
+        &metrics.process_role.data_freq_mutation,
+        &metrics.process_role.data_freq_fetched_versions,
+        &metrics.process_role.data_freq_fetches_from_log,
+        &metrics.process_role.data_freq_input_bytes,
+        &metrics.process_role.data_freq_durable_bytes,
+    ];
+    series
+        .iter()
+        .for_each(|metric| metric.remove_series(labels));
+    remove_matching_series(
+        &metrics.process_role.storage_engine_info,
+        PROCESS_LABELS,
+        labels,
+    );
+}
 
-    // Frequencies related
-    static ref P_DATA_FREQ_TOTAL_QUERIES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_total_queries", "Total number of queries");
-    static ref P_DATA_FREQ_FINISHED_QUERIES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_finished_queries", "Number of finished queries");
-    static ref P_DATA_FREQ_LOW_PRIORITY_QUERIES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_low_priority_queries", "Number of low prio queries");
-    static ref P_DATA_FREQ_BYTES_QUERIED: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_bytes_queried", "Frequency of write storage server operations in bytes");
-    static ref P_DATA_FREQ_KEYS_QUERIED: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_keys_queried", "Frequency of read storage server operations in bytes");
-    static ref P_DATA_FREQ_MUTATION_BYTES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_mutation_bytes", "Frequency of mutations in bytes");
-    static ref P_DATA_FREQ_MUTATION: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_mutation", "Frequency of mutation");
-    static ref P_DATA_FREQ_FETCHED_VERSIONS: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_fetched_versions", "Frequency of fetched versions in control plane");
-    static ref P_DATA_FREQ_FETCHES_FROM_LOG: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_fetches_from_log", "Frequency of fetched data from T logs");
-    static ref P_DATA_FREQ_INPUT_BYTES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_input_bytes", "Storage and Log Input Rates");
-    static ref P_DATA_FREQ_DURABLE_BYTES: HashMap<String, GaugeVec> = ClusterProcessRoleFreq::register("fdb_cluster_process_role_durable_bytes", "Storage and Log input rates durable");
+/// Export the storage engine of each storage role of a process, replacing
+/// the engines previously exported for it
+pub fn storage_engines_to_metrics(
+    metrics: &Metrics,
+    roles: &[ClusterProcessRole],
+    labels: &[&str],
+    default_engine: Option<&str>,
+) {
+    let gauge = &metrics.process_role.storage_engine_info;
+    remove_matching_series(gauge, PROCESS_LABELS, labels);
+    for engine in roles
+        .iter()
+        .filter_map(|role| role.storage_engine(default_engine))
+    {
+        gauge
+            .with_label_values(&[labels, &[engine]].concat())
+            .set(1);
+    }
 }
 
 impl StaticMetric<GaugeVec> for ClusterProcessRoleFreq {
-    fn register(prefix: &str, desc: &str) -> HashMap<String, GaugeVec> {
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, GaugeVec>> {
         let stat_name = &["counter", "hz", "roughness"];
         let mut metrics = HashMap::new();
         for name in stat_name {
             metrics.insert(
                 name.to_string(),
-                register_gauge_vec!(format!("{}_{}", prefix, name), desc, PROCESS_LABELS).unwrap(),
+                register_gauge_vec_with_registry!(
+                    format!("{}_{}", prefix, name),
+                    desc,
+                    PROCESS_LABELS,
+                    registry
+                )?,
             );
         }
-        metrics
+        Ok(metrics)
     }
     fn set(&self, metric: &HashMap<String, GaugeVec>, labels: &[&str]) {
         let stat_name = &["counter", "hz", "roughness"];
@@ -136,7 +234,11 @@ impl StaticMetric<GaugeVec> for ClusterProcessRoleFreq {
 }
 
 impl StaticMetric<GaugeVec> for LatencyStats {
-    fn register(prefix: &str, desc: &str) -> HashMap<String, GaugeVec> {
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, GaugeVec>> {
         let stat_name = &[
             "count", "min", "max", "median", "mean", "p25", "p90", "p95", "p99", "p99_9",
         ];
@@ -144,10 +246,15 @@ impl StaticMetric<GaugeVec> for LatencyStats {
         for name in stat_name {
             metrics.insert(
                 name.to_string(),
-                register_gauge_vec!(format!("{}_{}", prefix, name), desc, PROCESS_LABELS,).unwrap(),
+                register_gauge_vec_with_registry!(
+                    format!("{}_{}", prefix, name),
+                    desc,
+                    PROCESS_LABELS,
+                    registry
+                )?,
             );
         }
-        metrics
+        Ok(metrics)
     }
     fn set(&self, metrics: &HashMap<String, GaugeVec>, labels: &[&str]) {
         let stat_name = &[
@@ -185,100 +292,336 @@ impl StaticMetric<GaugeVec> for LatencyStats {
 }
 
 impl MetricsConvertible for ClusterProcessRole {
-    fn to_metrics(&self, labels: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
         // Kv store related
         if let Some(used_bytes) = self.kvstore_used_bytes {
-            P_KVSTORE_USED_BYTES
+            metrics
+                .process_role
+                .kvstore_used_bytes
                 .with_label_values(labels)
                 .set(used_bytes)
         }
         if let Some(available_bytes) = self.kvstore_available_bytes {
-            P_KVSTORE_AVAILABLE_BYTES
+            metrics
+                .process_role
+                .kvstore_available_bytes
                 .with_label_values(labels)
                 .set(available_bytes)
         }
         if let Some(free_bytes) = self.kvstore_free_bytes {
-            P_KVSTORE_FREE_BYTES
+            metrics
+                .process_role
+                .kvstore_free_bytes
                 .with_label_values(labels)
                 .set(free_bytes)
         }
+        if let Some(total_bytes) = self.kvstore_total_bytes {
+            metrics
+                .process_role
+                .kvstore_total_bytes
+                .with_label_values(labels)
+                .set(total_bytes)
+        }
         // Queue related
         if let Some(queue_max) = self.query_queue_max {
-            P_QUERY_QUEUE_MAX.with_label_values(labels).set(queue_max);
+            metrics
+                .process_role
+                .query_queue_max
+                .with_label_values(labels)
+                .set(queue_max);
         }
         if let Some(used_bytes) = self.queue_disk_used_bytes {
-            P_QUEUE_DISK_USED_BYTES
+            metrics
+                .process_role
+                .queue_disk_used_bytes
                 .with_label_values(labels)
                 .set(used_bytes);
         }
         if let Some(available_bytes) = self.queue_disk_available_bytes {
-            P_QUEUE_DISK_AVAILABLE_BYTES
+            metrics
+                .process_role
+                .queue_disk_available_bytes
                 .with_label_values(labels)
                 .set(available_bytes);
         }
         if let Some(free_bytes) = self.queue_disk_free_bytes {
-            P_QUEUE_DISK_FREE_BYTES
+            metrics
+                .process_role
+                .queue_disk_free_bytes
                 .with_label_values(labels)
                 .set(free_bytes);
         }
         if let Some(total_bytes) = self.queue_disk_total_bytes {
-            P_QUEUE_DISK_TOTAL_BYTES
+            metrics
+                .process_role
+                .queue_disk_total_bytes
                 .with_label_values(labels)
                 .set(total_bytes);
         }
 
         // Lag related
         if let Some(data_lag) = &self.data_lag {
-            P_DATA_LAG_SECONDS
+            metrics
+                .process_role
+                .data_lag_seconds
                 .with_label_values(labels)
                 .set(data_lag.seconds);
         }
         if let Some(durable_lag) = &self.durability_lag {
-            P_DATA_DURABLE_LAG_SECONDS
+            metrics
+                .process_role
+                .data_durable_lag_seconds
                 .with_label_values(labels)
                 .set(durable_lag.seconds);
         }
 
+        // Storage saturation
+        if self.role == Some(ClusterClassType::Storage) {
+            if let Some(local_rate) = self.local_rate {
+                metrics
+                    .process_role
+                    .storage_local_rate
+                    .with_label_values(labels)
+                    .set(local_rate);
+            }
+        }
+
+        if let Some(unflushed_bytes) = self.log_unflushed_bytes() {
+            metrics
+                .process_role
+                .log_unflushed_bytes
+                .with_label_values(labels)
+                .set(unflushed_bytes);
+        }
+
         // Roles global latency stats (storage, commit_proxy...)
         self.read_latency_statistics
-            .and_set_with_labels(&P_DATA_READ_LATENCY, labels);
+            .and_set_with_labels(&metrics.process_role.data_read_latency, labels);
         self.commit_latency_statistics
-            .and_set_with_labels(&P_DATA_COMMIT_LATENCY, labels);
+            .and_set_with_labels(&metrics.process_role.data_commit_latency, labels);
 
         // grv_proxy roles latency stats
         if let Some(default_latencies) = &self.grv_latency_statistics {
             default_latencies
                 .default
-                .and_set_with_labels(&P_DATA_GRV_PROXY_LATENCY, labels);
-            default_latencies
-                .batch
-                .and_set_with_labels(&P_DATA_GRV_PROXY_BATCHING_LATENCY, labels);
+                .and_set_with_labels(&metrics.process_role.data_grv_proxy_latency, labels);
+            default_latencies.batch.and_set_with_labels(
+                &metrics.process_role.data_grv_proxy_batching_latency,
+                labels,
+            );
         }
-        self.commit_batching_window_size
-            .and_set_with_labels(&P_DATA_COMMIT_BATCHING_WINDOW_SIZE, labels);
+        self.commit_batching_window_size.and_set_with_labels(
+            &metrics.process_role.data_commit_batching_window_size,
+            labels,
+        );
 
         // Frequencies related
         self.total_queries
-            .and_set_with_labels(&P_DATA_FREQ_TOTAL_QUERIES, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_total_queries, labels);
         self.finished_queries
-            .and_set_with_labels(&P_DATA_FREQ_FINISHED_QUERIES, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_finished_queries, labels);
         self.low_priority_queries
-            .and_set_with_labels(&P_DATA_FREQ_LOW_PRIORITY_QUERIES, labels);
-        self.bytes_queried
-            .and_set_with_labels(&P_DATA_FREQ_BYTES_QUERIED, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_low_priority_queries, labels);
+        if self.role == Some(ClusterClassType::Storage) {
+            self.bytes_queried
+                .and_set_with_labels(&metrics.process_role.data_freq_bytes_queried, labels);
+        }
         self.keys_queried
-            .and_set_with_labels(&P_DATA_FREQ_KEYS_QUERIED, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_keys_queried, labels);
         self.mutation_bytes
-            .and_set_with_labels(&P_DATA_FREQ_MUTATION_BYTES, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_mutation_bytes, labels);
         self.mutations
-            .and_set_with_labels(&P_DATA_FREQ_MUTATION, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_mutation, labels);
         self.fetched_versions
-            .and_set_with_labels(&P_DATA_FREQ_FETCHED_VERSIONS, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_fetched_versions, labels);
         self.fetches_from_logs
-            .and_set_with_labels(&P_DATA_FREQ_FETCHES_FROM_LOG, labels);
-        self.input_bytes
-            .and_set_with_labels(&P_DATA_FREQ_INPUT_BYTES, labels);
-        self.durable_bytes
-            .and_set_with_labels(&P_DATA_FREQ_DURABLE_BYTES, labels);
+            .and_set_with_labels(&metrics.process_role.data_freq_fetches_from_log, labels);
+        if matches!(
+            self.role,
+            Some(ClusterClassType::Storage | ClusterClassType::Log)
+        ) {
+            self.input_bytes
+                .and_set_with_labels(&metrics.process_role.data_freq_input_bytes, labels);
+            self.durable_bytes
+                .and_set_with_labels(&metrics.process_role.data_freq_durable_bytes, labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status_models::cluster_process_role::ClusterProcessRoleStorageMetadata;
+    use prometheus::Registry;
+
+    #[test]
+    fn storage_engines_are_replaced() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["engines", "machine", "process", "storage", "1.2.3.4:4500"];
+        let engine = |engine: &str| {
+            metrics
+                .process_role
+                .storage_engine_info
+                .get_metric_with_label_values(&[labels.as_slice(), &[engine]].concat())
+                .map(|gauge| gauge.get())
+        };
+        let storage = |engine: Option<&str>| ClusterProcessRole {
+            role: Some(ClusterClassType::Storage),
+            storage_metadata: Some(ClusterProcessRoleStorageMetadata {
+                storage_engine: engine.map(String::from),
+            }),
+            ..Default::default()
+        };
+        let log = ClusterProcessRole {
+            role: Some(ClusterClassType::Log),
+            ..Default::default()
+        };
+
+        storage_engines_to_metrics(metrics, &[storage(None), log], labels, Some("ssd-2"));
+        assert_eq!(engine("ssd-2").unwrap(), 1);
+
+        storage_engines_to_metrics(
+            metrics,
+            &[storage(Some("ssd-redwood-1"))],
+            labels,
+            Some("ssd-2"),
+        );
+        assert_eq!(engine("ssd-redwood-1").unwrap(), 1);
+        assert!(metrics
+            .process_role
+            .storage_engine_info
+            .remove_label_values(&[labels.as_slice(), &["ssd-2"]].concat())
+            .is_err());
+    }
+
+    #[test]
+    fn storage_local_rate() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = &["test", "machine", "process", "storage", "1.2.3.4:4500"];
+        let storage = ClusterProcessRole {
+            role: Some(ClusterClassType::Storage),
+            local_rate: Some(42.0),
+            ..Default::default()
+        };
+        storage.to_metrics(metrics, labels);
+        assert_eq!(
+            metrics
+                .process_role
+                .storage_local_rate
+                .with_label_values(labels)
+                .get(),
+            42.0
+        );
+
+        let labels = &["test", "machine", "process", "log", "1.2.3.4:4501"];
+        let log = ClusterProcessRole {
+            role: Some(ClusterClassType::Log),
+            local_rate: Some(42.0),
+            ..Default::default()
+        };
+        log.to_metrics(metrics, labels);
+        assert!(metrics
+            .process_role
+            .storage_local_rate
+            .remove_label_values(labels)
+            .is_err());
+    }
+
+    #[test]
+    fn data_rates_by_role() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let rate = |hz| {
+            Some(ClusterProcessRoleFreq {
+                counter: 0,
+                hz,
+                roughness: 0.0,
+            })
+        };
+        let hz = |metric: &HashMap<String, GaugeVec>, labels: &[&str]| {
+            metric["hz"].with_label_values(labels).get()
+        };
+
+        let labels = &["test", "machine", "process", "storage", "1.2.3.4:4500"];
+        let storage = ClusterProcessRole {
+            role: Some(ClusterClassType::Storage),
+            input_bytes: rate(10.0),
+            durable_bytes: rate(8.0),
+            bytes_queried: rate(100.0),
+            ..Default::default()
+        };
+        storage.to_metrics(metrics, labels);
+        assert_eq!(
+            hz(&metrics.process_role.data_freq_input_bytes, labels),
+            10.0
+        );
+        assert_eq!(
+            hz(&metrics.process_role.data_freq_durable_bytes, labels),
+            8.0
+        );
+        assert_eq!(
+            hz(&metrics.process_role.data_freq_bytes_queried, labels),
+            100.0
+        );
+
+        let labels = &["test", "machine", "process", "log", "1.2.3.4:4501"];
+        let log = ClusterProcessRole {
+            role: Some(ClusterClassType::Log),
+            input_bytes: rate(20.0),
+            bytes_queried: rate(100.0),
+            ..Default::default()
+        };
+        log.to_metrics(metrics, labels);
+        assert_eq!(
+            hz(&metrics.process_role.data_freq_input_bytes, labels),
+            20.0
+        );
+        assert!(metrics.process_role.data_freq_bytes_queried["hz"]
+            .remove_label_values(labels)
+            .is_err());
+    }
+
+    #[test]
+    fn log_unflushed_bytes() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let freq = |counter| {
+            Some(ClusterProcessRoleFreq {
+                counter,
+                hz: 0.0,
+                roughness: 0.0,
+            })
+        };
+        let labels = &["test", "machine", "process", "log", "1.2.3.4:4502"];
+        let log = ClusterProcessRole {
+            role: Some(ClusterClassType::Log),
+            input_bytes: freq(1500),
+            durable_bytes: freq(1000),
+            ..Default::default()
+        };
+        log.to_metrics(metrics, labels);
+        assert_eq!(
+            metrics
+                .process_role
+                .log_unflushed_bytes
+                .with_label_values(labels)
+                .get(),
+            500
+        );
+
+        // Counters read on each side of a reset
+        let log = ClusterProcessRole {
+            role: Some(ClusterClassType::Log),
+            input_bytes: freq(10),
+            durable_bytes: freq(1000),
+            ..Default::default()
+        };
+        assert_eq!(log.log_unflushed_bytes(), Some(0));
+
+        let storage = ClusterProcessRole {
+            role: Some(ClusterClassType::Storage),
+            input_bytes: freq(1500),
+            durable_bytes: freq(1000),
+            ..Default::default()
+        };
+        assert_eq!(storage.log_unflushed_bytes(), None);
     }
 }
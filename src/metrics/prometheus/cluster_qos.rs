@@ -1,99 +1,235 @@
-use lazy_static::lazy_static;
-use prometheus::{register_gauge, register_int_gauge, Gauge, IntGauge};
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
 use std::collections::HashMap;
 use tracing::warn;
 
 use crate::{
     metrics::MetricsConvertible,
-    status_models::{cluster_process_role::DataLag, cluster_qos::ClusterQos},
+    status_models::{
+        cluster::ClusterStatus, cluster_process_role::DataLag, cluster_qos::ClusterQos,
+    },
+};
+
+use super::{
+    remove_cluster_series, AndSet, Metrics, MetricsRegistry, StaticMetric, CLUSTER_LABELS,
 };
 
-use super::{AndSet, StaticMetric};
-
-lazy_static! {
-    static ref P_LIMITING_QUEUE_STORAGE_SERVER_BYTES: IntGauge = register_int_gauge!(
-        "fdb_qos_limiting_queue_storage_server_bytes",
-        "Queue of the storage server limiting the system"
-    )
-    .unwrap();
-    static ref P_LIMITING_DATA_STORAGE: HashMap<String, Gauge> = DataLag::register(
-        "fdb_qos_limiting_data_lag_storage_server",
-        "Lag of the limiting storage server"
-    );
-    static ref P_LIMITING_DURABILITY_LAG_STORAGE: HashMap<String, Gauge> = DataLag::register(
-        "fdb_qos_limiting_durability_lag_storage_server",
-        "Durability lag of the limiting storage server"
-    );
-    static ref P_WORST_DATA_LAG_STORAGE_SERVER: HashMap<String, Gauge> = DataLag::register(
-        "fdb_qos_worst_data_lag_storage_server",
-        "Storage server with the worst queue"
-    );
-    static ref P_WORST_DURABILITY_LAG_STORAGE_SERVER: HashMap<String, Gauge> = DataLag::register(
-        "fdb_qos_worst_durability_lag_storage_server",
-        "Storage server with the worst durability queue"
-    );
-    static ref P_WORST_QUEUE_BYTES_LOG_SERVER: IntGauge = register_int_gauge!(
-        "fdb_qos_worst_queue_log_server_bytes",
-        "Worst queue of log server in bytes"
-    )
-    .unwrap();
-    static ref P_WORST_QUEUE_BYTES_STORAGE_SERVER: IntGauge = register_int_gauge!(
-        "fdb_qos_worst_queue_storage_server_bytes",
-        "Worst queue of storage server",
-    )
-    .unwrap();
-    static ref P_PERFORMANCE_LIMITED_BY_REASON: IntGauge = register_int_gauge!(
-        "fdb_qos_performance_limited_by_reason",
-        "Reason of the system being limited"
-    )
-    .unwrap();
-    static ref P_TRANSACTIONS_PER_SERCOND_LIMIT: Gauge = register_gauge!(
-        "fdb_qos_transactions_per_second_limit",
-        "Number of transactions the cluster allows per second"
-    )
-    .unwrap();
-    static ref P_BATCH_TRANSACTIONS_PER_SECOND_LIMIT: Gauge = register_gauge!(
-        "fdb_qos_batch_transactions_per_second_limit",
-        "Number of batch transactions the cluster allows per second"
-    )
-    .unwrap();
+/// Quality of service metrics
+pub struct QosMetrics {
+    limiting_queue_storage_server_bytes: IntGaugeVec,
+    limiting_data_storage: HashMap<String, GaugeVec>,
+    limiting_durability_lag_storage: HashMap<String, GaugeVec>,
+    worst_data_lag_storage_server: HashMap<String, GaugeVec>,
+    worst_durability_lag_storage_server: HashMap<String, GaugeVec>,
+    worst_queue_bytes_log_server: IntGaugeVec,
+    worst_queue_bytes_storage_server: IntGaugeVec,
+    performance_limited_by_reason: IntGaugeVec,
+    limiting_process: IntGaugeVec,
+    transactions_per_sercond_limit: GaugeVec,
+    batch_transactions_per_second_limit: GaugeVec,
+    priority_transactions_per_second_limit: GaugeVec,
+    priority_released_transactions_per_second: GaugeVec,
+}
+
+impl QosMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(QosMetrics {
+            limiting_queue_storage_server_bytes: register_int_gauge_vec_with_registry!(
+                "fdb_qos_limiting_queue_storage_server_bytes",
+                "Queue of the storage server limiting the system",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            limiting_data_storage: DataLag::register(
+                registry,
+                "fdb_qos_limiting_data_lag_storage_server",
+                "Lag of the limiting storage server",
+            )?,
+            limiting_durability_lag_storage: DataLag::register(
+                registry,
+                "fdb_qos_limiting_durability_lag_storage_server",
+                "Durability lag of the limiting storage server",
+            )?,
+            worst_data_lag_storage_server: DataLag::register(
+                registry,
+                "fdb_qos_worst_data_lag_storage_server",
+                "Storage server with the worst queue",
+            )?,
+            worst_durability_lag_storage_server: DataLag::register(
+                registry,
+                "fdb_qos_worst_durability_lag_storage_server",
+                "Storage server with the worst durability queue",
+            )?,
+            worst_queue_bytes_log_server: register_int_gauge_vec_with_registry!(
+                "fdb_qos_worst_queue_log_server_bytes",
+                "Worst queue of log server in bytes",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            worst_queue_bytes_storage_server: register_int_gauge_vec_with_registry!(
+                "fdb_qos_worst_queue_storage_server_bytes",
+                "Worst queue of storage server",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            performance_limited_by_reason: register_int_gauge_vec_with_registry!(
+                "fdb_qos_performance_limited_by_reason",
+                "Reason of the system being limited",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            limiting_process: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_qos_limiting_process",
+                "Process hosting the server limiting the transaction rate, with the reason of the limit (1=limiting)",
+                &["cluster", "process_id", "reason"],
+                registry
+            )?,
+            transactions_per_sercond_limit: register_gauge_vec_with_registry!(
+                "fdb_qos_transactions_per_second_limit",
+                "Number of transactions the cluster allows per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            batch_transactions_per_second_limit: register_gauge_vec_with_registry!(
+                "fdb_qos_batch_transactions_per_second_limit",
+                "Number of batch transactions the cluster allows per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            priority_transactions_per_second_limit: register_gauge_vec_with_registry!(
+                "fdb_cluster_qos_transactions_per_second_limit",
+                "Number of transactions of a priority (normal or batch) the ratekeeper allows per second",
+                &["cluster", "priority"],
+                registry
+            )?,
+            priority_released_transactions_per_second: register_gauge_vec_with_registry!(
+                "fdb_cluster_qos_released_transactions_per_second",
+                "Number of transactions of a priority (normal or batch) released per second",
+                &["cluster", "priority"],
+                registry
+            )?,
+        })
+    }
 }
 
 impl MetricsConvertible for ClusterQos {
-    fn to_metrics(&self, _: &[&str]) {
-        P_LIMITING_QUEUE_STORAGE_SERVER_BYTES.set(self.limiting_queue_bytes_storage_server);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .qos
+            .limiting_queue_storage_server_bytes
+            .with_label_values(labels)
+            .set(self.limiting_queue_bytes_storage_server);
         self.limiting_data_lag_storage_server
-            .and_set(&P_LIMITING_DATA_STORAGE);
+            .and_set_with_labels(&metrics.qos.limiting_data_storage, labels);
         self.limiting_durability_lag_storage_server
-            .and_set(&P_LIMITING_DURABILITY_LAG_STORAGE);
+            .and_set_with_labels(&metrics.qos.limiting_durability_lag_storage, labels);
         self.worst_data_lag_storage_server
-            .and_set(&P_WORST_DATA_LAG_STORAGE_SERVER);
+            .and_set_with_labels(&metrics.qos.worst_data_lag_storage_server, labels);
         self.worst_durability_lag_storage_server
-            .and_set(&P_WORST_DURABILITY_LAG_STORAGE_SERVER);
+            .and_set_with_labels(&metrics.qos.worst_durability_lag_storage_server, labels);
 
-        P_WORST_QUEUE_BYTES_LOG_SERVER.set(self.worst_queue_bytes_log_server);
-        P_WORST_QUEUE_BYTES_STORAGE_SERVER.set(self.worst_queue_bytes_storage_server);
+        metrics
+            .qos
+            .worst_queue_bytes_log_server
+            .with_label_values(labels)
+            .set(self.worst_queue_bytes_log_server);
+        metrics
+            .qos
+            .worst_queue_bytes_storage_server
+            .with_label_values(labels)
+            .set(self.worst_queue_bytes_storage_server);
 
-        P_PERFORMANCE_LIMITED_BY_REASON.set(self.performance_limited_by.reason_id);
+        metrics
+            .qos
+            .performance_limited_by_reason
+            .with_label_values(labels)
+            .set(self.performance_limited_by.reason_id);
 
-        P_BATCH_TRANSACTIONS_PER_SECOND_LIMIT.set(self.batch_transactions_per_second_limit);
-        P_TRANSACTIONS_PER_SERCOND_LIMIT.set(self.transactions_per_second_limit);
+        metrics
+            .qos
+            .batch_transactions_per_second_limit
+            .with_label_values(labels)
+            .set(self.batch_transactions_per_second_limit);
+        metrics
+            .qos
+            .transactions_per_sercond_limit
+            .with_label_values(labels)
+            .set(self.transactions_per_second_limit);
+
+        // Batch transactions are throttled first, a limited normal priority
+        // is more severe
+        for (priority, limit, released) in [
+            (
+                "normal",
+                self.transactions_per_second_limit,
+                self.released_transactions_per_second,
+            ),
+            (
+                "batch",
+                self.batch_transactions_per_second_limit,
+                self.batch_released_transactions_per_second,
+            ),
+        ] {
+            let priority_labels = [labels, &[priority]].concat();
+            metrics
+                .qos
+                .priority_transactions_per_second_limit
+                .with_label_values(&priority_labels)
+                .set(limit);
+            metrics
+                .qos
+                .priority_released_transactions_per_second
+                .with_label_values(&priority_labels)
+                .set(released);
+        }
     }
 }
 
-impl StaticMetric<Gauge> for DataLag {
-    fn register(prefix: &str, desc: &str) -> HashMap<String, Gauge> {
+/// Flag the process of the server named by `performance_limited_by`, the
+/// previous one is cleared so a resolved limit doesn't linger
+pub fn limiting_process_to_metrics(metrics: &Metrics, labels: &[&str], cluster: &ClusterStatus) {
+    remove_cluster_series(&metrics.qos.limiting_process, labels[0]);
+    let Some(limit) = cluster.qos.as_ref().map(|qos| &qos.performance_limited_by) else {
+        return;
+    };
+    let Some(process_id) = limit
+        .reason_server_id
+        .as_ref()
+        .and_then(|role_id| cluster.role_process(role_id))
+    else {
+        return;
+    };
+    let process_labels = [labels, &[process_id.0.as_str(), limit.name.as_str()]].concat();
+    metrics
+        .qos
+        .limiting_process
+        .with_label_values(&process_labels)
+        .set(1);
+}
+
+impl StaticMetric<GaugeVec> for DataLag {
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, GaugeVec>> {
         let stat_name = &["versions", "seconds"];
         let mut metrics = HashMap::new();
         for name in stat_name {
             metrics.insert(
                 name.to_string(),
-                register_gauge!(format!("{}_{}", prefix, name), desc,).unwrap(),
+                register_gauge_vec_with_registry!(
+                    format!("{}_{}", prefix, name),
+                    desc,
+                    CLUSTER_LABELS,
+                    registry
+                )?,
             );
         }
-        metrics
+        Ok(metrics)
     }
-    fn set(&self, metrics: &HashMap<String, Gauge>, _: &[&str]) {
+    fn set(&self, metrics: &HashMap<String, GaugeVec>, labels: &[&str]) {
         let stat_name = &["versions", "seconds"];
         for name in *stat_name {
             // Safe as we know already the stat names
@@ -109,8 +245,82 @@ impl StaticMetric<Gauge> for DataLag {
             };
 
             if let Some(value_f64) = value {
-                metric.set(value_f64);
+                metric.with_label_values(labels).set(value_f64);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use prometheus::Registry;
+
+    use super::limiting_process_to_metrics;
+    use crate::fetcher::parse_cluster_status;
+    use crate::metrics::{Metrics, MetricsConvertible};
+    use crate::status_models::cluster_process_role::RoleId;
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn limiting_process() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let mut status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let cluster = status.cluster.as_mut().unwrap();
+        let limit = &mut cluster.qos.as_mut().unwrap().performance_limited_by;
+        limit.name = "storage_server_write_queue_size".to_string();
+        limit.reason_server_id = Some(RoleId("81e9b0d61299d23b".to_string()));
+
+        limiting_process_to_metrics(metrics, &["test"], cluster);
+        let labels = [
+            "test",
+            "14ad6cb7e7c2f3cfcf88dc9f2c736c78",
+            "storage_server_write_queue_size",
+        ];
+        assert_eq!(
+            metrics
+                .qos
+                .limiting_process
+                .with_label_values(&labels)
+                .get(),
+            1
+        );
+
+        // The limit is resolved
+        let limit = &mut cluster.qos.as_mut().unwrap().performance_limited_by;
+        limit.name = "workload".to_string();
+        limit.reason_server_id = None;
+        limiting_process_to_metrics(metrics, &["test"], cluster);
+        assert!(metrics
+            .qos
+            .limiting_process
+            .remove_label_values(&labels)
+            .is_err());
+    }
+
+    #[test]
+    fn rates_by_priority() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        let qos = status.cluster.as_ref().unwrap().qos.as_ref().unwrap();
+        qos.to_metrics(metrics, &["priority"]);
+
+        let limit = |priority| {
+            metrics
+                .qos
+                .priority_transactions_per_second_limit
+                .with_label_values(&["priority", priority])
+                .get()
+        };
+        assert_eq!(limit("normal"), qos.transactions_per_second_limit);
+        assert_eq!(limit("batch"), qos.batch_transactions_per_second_limit);
+        assert_eq!(
+            metrics
+                .qos
+                .priority_released_transactions_per_second
+                .with_label_values(&["priority", "batch"])
+                .get(),
+            qos.batch_released_transactions_per_second
+        );
+    }
+}
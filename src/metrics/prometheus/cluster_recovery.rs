@@ -0,0 +1,168 @@
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_gauge_vec_with_registry, GaugeVec, IntGaugeVec,
+};
+
+use super::{Metrics, MetricsRegistry, CLUSTER_LABELS};
+use crate::{
+    metrics::MetricsConvertible,
+    status_models::cluster_recovery::{ClusterRecoveryState, ClusterRecoveryStateName},
+};
+
+/// Metrics of the recovery state
+pub struct RecoveryMetrics {
+    recovery_in_progress: IntGaugeVec,
+    recovering: IntGaugeVec,
+    seconds_since_last_recovered: GaugeVec,
+    excess_generations: IntGaugeVec,
+}
+
+impl RecoveryMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(RecoveryMetrics {
+            recovery_in_progress: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_recovery_in_progress",
+                "Whether a recovery is in progress (0=fully_recovered or accepting_commits)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            recovering: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_recovering",
+                "Whether the cluster is not fully recovered, or its recovery state is unknown (0=fully_recovered)",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            seconds_since_last_recovered: register_gauge_vec_with_registry!(
+                "fdb_cluster_recovery_seconds_since_last_recovered",
+                "Time in seconds since the last recovery completed",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            excess_generations: register_int_gauge_vec_with_registry!(
+                "fdb_cluster_excess_generations",
+                "Number of active generations beyond the current one, above 0 outside of a recovery when a recovery is stuck",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Flag the cluster as recovering when the status has no recovery state,
+/// which usually means the cluster is not up
+pub fn missing_recovery_state(metrics: &Metrics, labels: &[&str]) {
+    metrics.recovery.recovering.with_label_values(labels).set(1);
+    metrics.set_recovering(true);
+}
+
+impl MetricsConvertible for ClusterRecoveryState {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .recovery
+            .recovery_in_progress
+            .with_label_values(labels)
+            .set(self.name.in_progress() as i64);
+        let recovering = self.name != ClusterRecoveryStateName::FullyRecovered;
+        metrics
+            .recovery
+            .recovering
+            .with_label_values(labels)
+            .set(recovering as i64);
+        metrics.set_recovering(recovering);
+        if let Some(seconds) = self.seconds_since_last_recovered {
+            metrics
+                .recovery
+                .seconds_since_last_recovered
+                .with_label_values(labels)
+                .set(seconds);
+        }
+        if let Some(generations) = self.active_generations {
+            metrics
+                .recovery
+                .excess_generations
+                .with_label_values(labels)
+                .set((generations - 1).max(0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn mid_recovery_sets_in_progress() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let state: ClusterRecoveryState =
+            serde_json::from_str(r#"{"name": "locking_old_transaction_servers"}"#).unwrap();
+        state.to_metrics(metrics, &["test"]);
+        assert_eq!(
+            metrics
+                .recovery
+                .recovery_in_progress
+                .with_label_values(&["test"])
+                .get(),
+            1
+        );
+    }
+
+    #[test]
+    fn excess_generations() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = ["generations"];
+        let excess = |active_generations: i64| {
+            let state: ClusterRecoveryState = serde_json::from_value(serde_json::json!({
+                "name": "fully_recovered",
+                "active_generations": active_generations
+            }))
+            .unwrap();
+            state.to_metrics(metrics, &labels);
+            metrics
+                .recovery
+                .excess_generations
+                .with_label_values(&labels)
+                .get()
+        };
+
+        assert_eq!(excess(3), 2);
+        assert_eq!(excess(1), 0);
+        assert_eq!(excess(0), 0);
+    }
+
+    #[test]
+    fn recovering_until_fully_recovered() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let labels = ["recovering"];
+        let state: ClusterRecoveryState =
+            serde_json::from_str(r#"{"name": "accepting_commits"}"#).unwrap();
+        state.to_metrics(metrics, &labels);
+        assert_eq!(
+            metrics.recovery.recovering.with_label_values(&labels).get(),
+            1
+        );
+
+        let state: ClusterRecoveryState = serde_json::from_str(
+            r#"{"name": "fully_recovered", "seconds_since_last_recovered": 42.5}"#,
+        )
+        .unwrap();
+        state.to_metrics(metrics, &labels);
+        assert_eq!(
+            metrics.recovery.recovering.with_label_values(&labels).get(),
+            0
+        );
+        assert_eq!(
+            metrics
+                .recovery
+                .seconds_since_last_recovered
+                .with_label_values(&labels)
+                .get(),
+            42.5
+        );
+
+        missing_recovery_state(metrics, &labels);
+        assert_eq!(
+            metrics.recovery.recovering.with_label_values(&labels).get(),
+            1
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use lazy_static::lazy_static;
-use prometheus::{register_int_gauge, IntGauge};
+use prometheus::{register_int_gauge_vec_with_registry, IntGaugeVec};
+
+use super::{Metrics, MetricsRegistry, CLUSTER_LABELS};
 
 use crate::{
     metrics::MetricsConvertible,
@@ -8,72 +9,139 @@ use crate::{
 
 const P_PREFIX: &str = "fdb_cluster_wiggle";
 
-lazy_static! {
+/// Metrics of the storage wiggle
+pub struct WiggleMetrics {
     /// [ClusterStorageWiggle] related
-    static ref P_CLUSTER_WIGGLE_SERVER_COUNT: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "servers_count"),
-        "Current number of storage servers being wiggle"
-    )
-    .unwrap();
-
+    server_count: IntGaugeVec,
     /// [ClusterStoragePrimaryWiggle] related
-    static ref P_CLUSTER_WIGGLE_FINISHED_ROUNDS_COUNT: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "finished_rounds_count"),
-        "Number of finished rounds",
-    ).unwrap();
-
-    static ref P_CLUSTER_WIGGLE_FINISHED_COUNT: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "finished_count"),
-        "Number of finished wiggle"
-    ).unwrap();
+    finished_rounds_count: IntGaugeVec,
+    finished_count: IntGaugeVec,
+    smoothed_round_seconds: IntGaugeVec,
+    smoothed_seconds: IntGaugeVec,
+    last_round_finish: IntGaugeVec,
+    last_finish: IntGaugeVec,
+    last_round_start: IntGaugeVec,
+    last_start: IntGaugeVec,
+}
 
-    static ref P_CLUSTER_WIGGLE_SMOOTHED_ROUND_SECONDS: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "smoothed_round_seconds"),
-        "Seconds elapsed in the current round"
-    ).unwrap();
-    static ref P_CLUSTER_WIGGLE_SMOOTHED_SECONDS: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "smoothed_seconds"),
-        "Seconds elapsed in the current wiggle"
-    ).unwrap();
-    static ref P_CLUSTER_WIGGLE_LAST_ROUND_FINISH: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "last_round_finish"),
-        "Timestamp of the last fully finished round"
-    ).unwrap();
-    static ref P_CLUSTER_WIGGLE_LAST_FINISH: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "last_finish"),
-        "Timestamp of the last fully finished wiggle"
-    ).unwrap();
-    static ref P_CLUSTER_WIGGLE_LAST_ROUND_START: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "last_round_start"),
-        "Timestamp of the start of last round"
-    ).unwrap();
-    static ref P_CLUSTER_WIGGLE_LAST_START: IntGauge = register_int_gauge!(
-        format!("{}_{}", P_PREFIX, "last_start"),
-        "Timestamp of the start of last wiggle"
-    ).unwrap();
+impl WiggleMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(WiggleMetrics {
+            server_count: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "servers_count"),
+                "Current number of storage servers being wiggle",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            finished_rounds_count: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "finished_rounds_count"),
+                "Number of finished rounds",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            finished_count: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "finished_count"),
+                "Number of finished wiggle",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            smoothed_round_seconds: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "smoothed_round_seconds"),
+                "Seconds elapsed in the current round",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            smoothed_seconds: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "smoothed_seconds"),
+                "Seconds elapsed in the current wiggle",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            last_round_finish: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "last_round_finish"),
+                "Timestamp of the last fully finished round",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            last_finish: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "last_finish"),
+                "Timestamp of the last fully finished wiggle",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            last_round_start: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "last_round_start"),
+                "Timestamp of the start of last round",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            last_start: register_int_gauge_vec_with_registry!(
+                format!("{}_{}", P_PREFIX, "last_start"),
+                "Timestamp of the start of last wiggle",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
 }
 
 impl MetricsConvertible for ClusterStorageWiggle {
-    fn to_metrics(&self, _: &[&str]) {
-        P_CLUSTER_WIGGLE_SERVER_COUNT.set(self.wiggle_server_addresses.len() as i64);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .wiggle
+            .server_count
+            .with_label_values(labels)
+            .set(self.wiggle_server_addresses.len() as i64);
         if let Some(primary) = &self.primary {
-            primary.to_metrics(&[]);
+            primary.to_metrics(metrics, labels);
         }
     }
 }
 
 impl MetricsConvertible for ClusterStoragePrimaryWiggle {
-    fn to_metrics(&self, _: &[&str]) {
-        P_CLUSTER_WIGGLE_FINISHED_ROUNDS_COUNT.set(self.finished_round as i64);
-        P_CLUSTER_WIGGLE_FINISHED_COUNT.set(self.finished_wiggle as i64);
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        metrics
+            .wiggle
+            .finished_rounds_count
+            .with_label_values(labels)
+            .set(self.finished_round as i64);
+        metrics
+            .wiggle
+            .finished_count
+            .with_label_values(labels)
+            .set(self.finished_wiggle as i64);
 
-        P_CLUSTER_WIGGLE_SMOOTHED_ROUND_SECONDS.set(self.smoothed_round_seconds.floor() as i64);
-        P_CLUSTER_WIGGLE_SMOOTHED_SECONDS.set(self.smoothed_wiggle_seconds.floor() as i64);
+        metrics
+            .wiggle
+            .smoothed_round_seconds
+            .with_label_values(labels)
+            .set(self.smoothed_round_seconds.floor() as i64);
+        metrics
+            .wiggle
+            .smoothed_seconds
+            .with_label_values(labels)
+            .set(self.smoothed_wiggle_seconds.floor() as i64);
 
-        P_CLUSTER_WIGGLE_LAST_ROUND_FINISH.set(self.last_round_finish_timestamp.floor() as i64);
-        P_CLUSTER_WIGGLE_LAST_FINISH.set(self.last_wiggle_finish_timestamp.floor() as i64);
+        metrics
+            .wiggle
+            .last_round_finish
+            .with_label_values(labels)
+            .set(self.last_round_finish_timestamp.floor() as i64);
+        metrics
+            .wiggle
+            .last_finish
+            .with_label_values(labels)
+            .set(self.last_wiggle_finish_timestamp.floor() as i64);
 
-        P_CLUSTER_WIGGLE_LAST_ROUND_START.set(self.last_round_start_timestamp.floor() as i64);
-        P_CLUSTER_WIGGLE_LAST_START.set(self.last_wiggle_start_timestamp.floor() as i64);
+        metrics
+            .wiggle
+            .last_round_start
+            .with_label_values(labels)
+            .set(self.last_round_start_timestamp.floor() as i64);
+        metrics
+            .wiggle
+            .last_start
+            .with_label_values(labels)
+            .set(self.last_wiggle_start_timestamp.floor() as i64);
     }
 }
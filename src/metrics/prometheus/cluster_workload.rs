@@ -0,0 +1,239 @@
+use prometheus::{
+    register_gauge_vec_with_registry, register_int_counter_vec_with_registry, GaugeVec,
+    IntCounterVec,
+};
+
+use super::{Metrics, MetricsRegistry, CLUSTER_LABELS};
+use crate::{
+    metrics::MetricsConvertible,
+    status_models::cluster_workload::{ClusterWorkload, WorkloadFrequency},
+};
+
+/// Cluster-wide transaction and operation rates, along with their cumulative
+/// counters
+pub struct WorkloadMetrics {
+    transactions_started_hz: GaugeVec,
+    transactions_committed_hz: GaugeVec,
+    transactions_conflicted_hz: GaugeVec,
+    operations_reads_hz: GaugeVec,
+    operations_writes_hz: GaugeVec,
+    transactions_started_total: IntCounterVec,
+    transactions_committed_total: IntCounterVec,
+    transactions_conflicted_total: IntCounterVec,
+    operations_reads_total: IntCounterVec,
+    operations_writes_total: IntCounterVec,
+}
+
+impl WorkloadMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(WorkloadMetrics {
+            transactions_started_hz: register_gauge_vec_with_registry!(
+                "fdb_cluster_workload_transactions_started_hz",
+                "Number of transactions started per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            transactions_committed_hz: register_gauge_vec_with_registry!(
+                "fdb_cluster_workload_transactions_committed_hz",
+                "Number of transactions committed per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            transactions_conflicted_hz: register_gauge_vec_with_registry!(
+                "fdb_cluster_workload_transactions_conflicted_hz",
+                "Number of transactions conflicted per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            operations_reads_hz: register_gauge_vec_with_registry!(
+                "fdb_cluster_workload_operations_reads_hz",
+                "Number of read operations per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            operations_writes_hz: register_gauge_vec_with_registry!(
+                "fdb_cluster_workload_operations_writes_hz",
+                "Number of write operations per second",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            transactions_started_total: register_int_counter_vec_with_registry!(
+                "fdb_cluster_workload_transactions_started_total",
+                "Number of transactions started",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            transactions_committed_total: register_int_counter_vec_with_registry!(
+                "fdb_cluster_workload_transactions_committed_total",
+                "Number of transactions committed",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            transactions_conflicted_total: register_int_counter_vec_with_registry!(
+                "fdb_cluster_workload_transactions_conflicted_total",
+                "Number of transactions conflicted",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            operations_reads_total: register_int_counter_vec_with_registry!(
+                "fdb_cluster_workload_operations_reads_total",
+                "Number of read operations",
+                CLUSTER_LABELS,
+                registry
+            )?,
+            operations_writes_total: register_int_counter_vec_with_registry!(
+                "fdb_cluster_workload_operations_writes_total",
+                "Number of write operations",
+                CLUSTER_LABELS,
+                registry
+            )?,
+        })
+    }
+}
+
+/// Export the rate of an event and its cumulative counter.
+///
+/// FoundationDB counters are reset when the processes reporting them restart
+/// (e.g. on a recovery), so the Prometheus counter mirrors the raw value
+/// rather than accumulating deltas: it is increased by the difference while
+/// the value grows, and reset when it goes down. `rate()` then handles the
+/// drop like any other counter reset.
+fn set_frequency(
+    rate: &GaugeVec,
+    total: &IntCounterVec,
+    labels: &[&str],
+    frequency: Option<WorkloadFrequency>,
+) {
+    let Some(frequency) = frequency else {
+        return;
+    };
+    rate.with_label_values(labels).set(frequency.hz);
+
+    let total = total.with_label_values(labels);
+    let previous = total.get();
+    if frequency.counter < previous {
+        total.reset();
+        total.inc_by(frequency.counter);
+    } else {
+        total.inc_by(frequency.counter - previous);
+    }
+}
+
+impl MetricsConvertible for ClusterWorkload {
+    fn to_metrics(&self, metrics: &Metrics, labels: &[&str]) {
+        let workload = &metrics.workload;
+        if let Some(transactions) = &self.transactions {
+            set_frequency(
+                &workload.transactions_started_hz,
+                &workload.transactions_started_total,
+                labels,
+                transactions.started,
+            );
+            set_frequency(
+                &workload.transactions_committed_hz,
+                &workload.transactions_committed_total,
+                labels,
+                transactions.committed,
+            );
+            set_frequency(
+                &workload.transactions_conflicted_hz,
+                &workload.transactions_conflicted_total,
+                labels,
+                transactions.conflicted,
+            );
+        }
+        if let Some(operations) = &self.operations {
+            set_frequency(
+                &workload.operations_reads_hz,
+                &workload.operations_reads_total,
+                labels,
+                operations.reads,
+            );
+            set_frequency(
+                &workload.operations_writes_hz,
+                &workload.operations_writes_total,
+                labels,
+                operations.writes,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::Registry;
+
+    #[test]
+    fn rates_use_hz() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let workload: ClusterWorkload = serde_json::from_str(
+            r#"{
+                "transactions": {
+                    "started": {"counter": 145364, "hz": 9.5, "roughness": 6.02},
+                    "committed": {"counter": 6683, "hz": 0.4, "roughness": 0.0002}
+                },
+                "operations": {
+                    "reads": {"counter": 258004, "hz": 15.2, "roughness": 9.67}
+                }
+            }"#,
+        )
+        .unwrap();
+        workload.to_metrics(metrics, &["test"]);
+
+        let workload = &metrics.workload;
+        assert_eq!(
+            workload
+                .transactions_started_hz
+                .with_label_values(&["test"])
+                .get(),
+            9.5
+        );
+        assert_eq!(
+            workload
+                .transactions_committed_hz
+                .with_label_values(&["test"])
+                .get(),
+            0.4
+        );
+        assert_eq!(
+            workload
+                .operations_reads_hz
+                .with_label_values(&["test"])
+                .get(),
+            15.2
+        );
+        // Absent from the status, never set
+        assert_eq!(
+            workload
+                .operations_writes_hz
+                .with_label_values(&["test"])
+                .get(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn counters_follow_resets() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        let committed = |counter| {
+            let workload: ClusterWorkload = serde_json::from_value(serde_json::json!({
+                "transactions": {
+                    "committed": {"counter": counter, "hz": 1.0, "roughness": 0.0}
+                }
+            }))
+            .unwrap();
+            workload.to_metrics(metrics, &["test"]);
+            metrics
+                .workload
+                .transactions_committed_total
+                .with_label_values(&["test"])
+                .get()
+        };
+
+        assert_eq!(committed(100), 100);
+        assert_eq!(committed(150), 150);
+        // Counters start over after a recovery
+        assert_eq!(committed(20), 20);
+    }
+}
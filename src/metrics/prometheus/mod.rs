@@ -1,13 +1,26 @@
-use lazy_static::lazy_static;
-use prometheus::{register_int_counter, IntCounter, IntGauge};
-use std::collections::HashMap;
+use prometheus::core::{Collector, MetricVec, MetricVecBuilder};
+use prometheus::{
+    register_gauge_with_registry, register_histogram_with_registry,
+    register_int_counter_vec_with_registry, register_int_counter_with_registry,
+    register_int_gauge_vec_with_registry, register_int_gauge_with_registry, Gauge, GaugeVec,
+    Histogram, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Registry,
+};
+use serde_path_to_error::Segment;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
 
-use super::MetricsConvertible;
-use crate::fetcher::FetchError;
+use super::{LastFetch, MetricsConvertible};
+use crate::fetcher::{FetchError, FetchTimings};
 
 pub mod client;
 pub mod cluster;
 pub mod cluster_backup;
+pub mod cluster_blob_granules;
+pub mod cluster_clients;
 pub mod cluster_data;
 pub mod cluster_machines;
 pub mod cluster_probe;
@@ -17,59 +30,730 @@ pub mod cluster_process_memory;
 pub mod cluster_process_network;
 pub mod cluster_process_role;
 pub mod cluster_qos;
+pub mod cluster_recovery;
 pub mod cluster_wiggle;
+pub mod cluster_workload;
 
-pub const PROCESS_LABELS: &[&str] = &["machine_id", "process_id", "class_type", "address"];
-
-lazy_static! {
-    static ref P_FDB_EXPORTER_PARSING_ERROR: IntCounter = register_int_counter! {
-        "fdb_exporter_parsing_error_count",
-        "Number of parsing errors encountered",
-    }
-    .unwrap();
-    static ref P_FDB_EXPORTER_FDB_ERROR: IntCounter = register_int_counter!(
-        "fdb_exporter_fdb_error_count",
-        "Number of FoundationDB errors"
-    )
-    .unwrap();
-    static ref P_FDB_EXPORTER_FDB_BINDING_ERROR: IntCounter = register_int_counter!(
-        "fdb_exporter_fdb_binding_error_count",
-        "Number of FoundationDB binding errors"
-    )
-    .unwrap();
-    static ref P_FDB_EXPORTER_STATUS_NOT_FOUND: IntCounter = register_int_counter!(
-        "fdb_exporter_status_not_found_count",
-        "Number of times the status key was not found"
-    )
-    .unwrap();
+/// Label distinguishing metrics of each cluster, always the first label of
+/// metrics varying per cluster
+pub const CLUSTER_LABELS: &[&str] = &["cluster"];
+
+pub const PROCESS_LABELS: &[&str] = &[
+    "cluster",
+    "machine_id",
+    "process_id",
+    "class_type",
+    "address",
+];
+
+/// Maximum length of labels describing a parse error
+const PARSE_ERROR_LABEL_MAX_LEN: usize = 128;
+
+/// Fields of the status which are maps keyed by ids, their keys are stripped
+/// from the paths of parse errors like array indices
+const STATUS_ID_MAPS: &[&str] = &["machines", "processes", "tags"];
+
+/// [Registry] on which [Metrics::new] registers every metric, keeping the
+/// names of their variable labels
+pub struct MetricsRegistry<'a> {
+    registry: &'a Registry,
+    label_names: RefCell<HashSet<String>>,
+}
+
+impl MetricsRegistry<'_> {
+    /// Used by the `register_*_with_registry!` macros
+    pub fn register(&self, collector: Box<dyn Collector>) -> prometheus::Result<()> {
+        let names = collector
+            .desc()
+            .into_iter()
+            .flat_map(|desc| desc.variable_labels.iter().cloned());
+        self.label_names.borrow_mut().extend(names);
+        self.registry.register(collector)
+    }
+}
+
+/// Every exported metric, registered on the [Registry] given to [Metrics::new]
+/// so that several exporters can live in the same process
+pub struct Metrics {
+    exporter: ExporterMetrics,
+    client: client::ClientMetrics,
+    cluster: cluster::ClusterMetrics,
+    backup: cluster_backup::BackupMetrics,
+    blob_granules: cluster_blob_granules::BlobGranulesMetrics,
+    clients: cluster_clients::ClientsMetrics,
+    data: cluster_data::DataMetrics,
+    machine: cluster_machines::MachineMetrics,
+    probe: cluster_probe::ProbeMetrics,
+    process: cluster_process::ProcessMetrics,
+    process_disk: cluster_process_disk::ProcessDiskMetrics,
+    process_memory: cluster_process_memory::ProcessMemoryMetrics,
+    process_network: cluster_process_network::ProcessNetworkMetrics,
+    process_role: cluster_process_role::ProcessRoleMetrics,
+    qos: cluster_qos::QosMetrics,
+    recovery: cluster_recovery::RecoveryMetrics,
+    wiggle: cluster_wiggle::WiggleMetrics,
+    workload: cluster_workload::WorkloadMetrics,
+    /// Last value of each smoothed series, keyed by metric name and labels
+    smoothed_values: Mutex<HashMap<(String, Vec<String>), f64>>,
+    /// Process id holding each singleton role, keyed by cluster and role
+    role_holders: Mutex<HashMap<Vec<String>, String>>,
+    /// Metrics which already received a value above [i64::MAX]
+    saturated_metrics: Mutex<HashSet<String>>,
+    /// Generation changes seen within the recovery window, keyed by labels
+    recent_recoveries: Mutex<HashMap<Vec<String>, RecentRecoveries>>,
+    last_fetch: Mutex<LastFetch>,
+    /// Whether the last status was not fully recovered, or had no recovery state
+    recovering: AtomicBool,
+    /// Names of the variable labels of every metric
+    label_names: HashSet<String>,
+}
+
+/// Last generation of a cluster and the times it changed
+struct RecentRecoveries {
+    generation: i64,
+    changes: VecDeque<Instant>,
+}
+
+impl Metrics {
+    /// Create every metric and register them on `registry`, fails if one of
+    /// them is already registered
+    pub fn new(registry: &Registry) -> prometheus::Result<Self> {
+        let registry = &MetricsRegistry {
+            registry,
+            label_names: RefCell::default(),
+        };
+        Ok(Metrics {
+            exporter: ExporterMetrics::new(registry)?,
+            client: client::ClientMetrics::new(registry)?,
+            cluster: cluster::ClusterMetrics::new(registry)?,
+            backup: cluster_backup::BackupMetrics::new(registry)?,
+            blob_granules: cluster_blob_granules::BlobGranulesMetrics::new(registry)?,
+            clients: cluster_clients::ClientsMetrics::new(registry)?,
+            data: cluster_data::DataMetrics::new(registry)?,
+            machine: cluster_machines::MachineMetrics::new(registry)?,
+            probe: cluster_probe::ProbeMetrics::new(registry)?,
+            process: cluster_process::ProcessMetrics::new(registry)?,
+            process_disk: cluster_process_disk::ProcessDiskMetrics::new(registry)?,
+            process_memory: cluster_process_memory::ProcessMemoryMetrics::new(registry)?,
+            process_network: cluster_process_network::ProcessNetworkMetrics::new(registry)?,
+            process_role: cluster_process_role::ProcessRoleMetrics::new(registry)?,
+            qos: cluster_qos::QosMetrics::new(registry)?,
+            recovery: cluster_recovery::RecoveryMetrics::new(registry)?,
+            wiggle: cluster_wiggle::WiggleMetrics::new(registry)?,
+            workload: cluster_workload::WorkloadMetrics::new(registry)?,
+            smoothed_values: Mutex::new(HashMap::new()),
+            role_holders: Mutex::new(HashMap::new()),
+            saturated_metrics: Mutex::new(HashSet::new()),
+            recent_recoveries: Mutex::new(HashMap::new()),
+            last_fetch: Mutex::new(LastFetch::default()),
+            recovering: AtomicBool::new(false),
+            label_names: registry.label_names.take(),
+        })
+    }
+
+    /// Names of the labels set by the exporter itself, which constant labels
+    /// must not reuse
+    pub fn label_names(&self) -> &HashSet<String> {
+        &self.label_names
+    }
+}
+
+/// Metrics about the exporter itself (`fdb_exporter_*`)
+pub struct ExporterMetrics {
+    parsing_error: IntCounter,
+    parsing_error_path: IntCounterVec,
+    fdb_error: IntCounter,
+    fdb_binding_error: IntCounter,
+    cmd_error: IntCounter,
+    status_not_found: IntCounter,
+    status_none_during_recovery: IntCounter,
+    fetch_timeout: IntCounter,
+    overall_timeout: IntCounter,
+    partial_parse: IntCounter,
+    fetch_total: IntCounter,
+    fetch_success_total: IntCounter,
+    last_fetch_success_timestamp: IntGauge,
+    fetch_attempts: Histogram,
+    loop_iterations_total: IntCounter,
+    config_reload: IntCounter,
+    cluster_file_mismatch: IntGauge,
+    empty_status: IntCounter,
+    last_parse_error_timestamp: IntGauge,
+    last_parse_error_info: IntGaugeVec,
+    conversion_panic: IntCounter,
+    dump_error: IntCounter,
+    response_truncated: IntCounter,
+    remote_write_error: IntCounter,
+    status_bytes: IntGauge,
+    loop_lag: Gauge,
+    resident_memory: IntGauge,
+    coordinator_connect: Gauge,
+    fetch_io: Gauge,
+    parse: Gauge,
+}
+
+impl ExporterMetrics {
+    pub fn new(registry: &MetricsRegistry) -> prometheus::Result<Self> {
+        Ok(ExporterMetrics {
+            parsing_error: register_int_counter_with_registry!(
+                "fdb_exporter_parsing_error_count",
+                "Number of parsing errors encountered",
+                registry
+            )?,
+            parsing_error_path: register_int_counter_vec_with_registry!(
+                "fdb_exporter_parsing_error_path_count",
+                "Number of parsing errors encountered by location in the status, array indices and ids stripped",
+                &["path"],
+                registry
+            )?,
+            fdb_error: register_int_counter_with_registry!(
+                "fdb_exporter_fdb_error_count",
+                "Number of FoundationDB errors",
+                registry
+            )?,
+            fdb_binding_error: register_int_counter_with_registry!(
+                "fdb_exporter_fdb_binding_error_count",
+                "Number of FoundationDB binding errors",
+                registry
+            )?,
+            cmd_error: register_int_counter_with_registry!(
+                "fdb_exporter_cmd_error_count",
+                "Number of failed run of fdbcli",
+                registry
+            )?,
+            status_not_found: register_int_counter_with_registry!(
+                "fdb_exporter_status_not_found_count",
+                "Number of times the status key was not found",
+                registry
+            )?,
+            status_none_during_recovery: register_int_counter_with_registry!(
+                "fdb_exporter_status_none_during_recovery_count",
+                "Number of times the status key was not found while the last status was not fully recovered",
+                registry
+            )?,
+            fetch_timeout: register_int_counter_with_registry!(
+                "fdb_exporter_fetch_timeout_count",
+                "Number of status reads which exceeded the transaction timeout",
+                registry
+            )?,
+            overall_timeout: register_int_counter_with_registry!(
+                "fdb_exporter_overall_timeout_count",
+                "Number of status fetches which exceeded the overall timeout",
+                registry
+            )?,
+            partial_parse: register_int_counter_with_registry!(
+                "fdb_exporter_partial_parse_count",
+                "Number of statuses parsed leniently with malformed sub-objects skipped",
+                registry
+            )?,
+            fetch_total: register_int_counter_with_registry!(
+                "fdb_exporter_fetch_total",
+                "Number of status fetches",
+                registry
+            )?,
+            fetch_success_total: register_int_counter_with_registry!(
+                "fdb_exporter_fetch_success_total",
+                "Number of status fetches which were fetched and parsed successfully",
+                registry
+            )?,
+            last_fetch_success_timestamp: register_int_gauge_with_registry!(
+                "fdb_exporter_last_fetch_success_timestamp_seconds",
+                "Timestamp of the last successful status fetch",
+                registry
+            )?,
+            fetch_attempts: register_histogram_with_registry!(
+                "fdb_exporter_fetch_attempts",
+                "Number of transaction attempts needed to read the status",
+                vec![1.0, 2.0, 3.0, 5.0, 10.0, 20.0],
+                registry
+            )?,
+            loop_iterations_total: register_int_counter_with_registry!(
+                "fdb_exporter_loop_iterations_total",
+                "Number of iterations of the status fetch loop",
+                registry
+            )?,
+            config_reload: register_int_counter_with_registry!(
+                "fdb_exporter_config_reload_count",
+                "Number of times the cluster file was reloaded",
+                registry
+            )?,
+            cluster_file_mismatch: register_int_gauge_with_registry!(
+                "fdb_exporter_cluster_file_mismatch",
+                "Whether the cluster file differs from the connection string reported in the status (0=false)",
+                registry
+            )?,
+            empty_status: register_int_counter_with_registry!(
+                "fdb_exporter_empty_status_count",
+                "Number of times the status key had an empty value",
+                registry
+            )?,
+            last_parse_error_timestamp: register_int_gauge_with_registry!(
+                "fdb_exporter_last_parse_error_timestamp_seconds",
+                "Timestamp of the last parsing error",
+                registry
+            )?,
+            last_parse_error_info: register_int_gauge_vec_with_registry!(
+                "fdb_exporter_last_parse_error_info",
+                "Location and message of the last parsing error, reset on successful parsing",
+                &["path", "message"],
+                registry
+            )?,
+            conversion_panic: register_int_counter_with_registry!(
+                "fdb_exporter_conversion_panic_count",
+                "Number of statuses whose conversion to metrics panicked",
+                registry
+            )?,
+            dump_error: register_int_counter_with_registry!(
+                "fdb_exporter_dump_error_count",
+                "Number of failed writes of the metrics to the dump file",
+                registry
+            )?,
+            response_truncated: register_int_counter_with_registry!(
+                "fdb_exporter_response_truncated_count",
+                "Number of metrics responses larger than the maximum response size, served whole",
+                registry
+            )?,
+            remote_write_error: register_int_counter_with_registry!(
+                "fdb_exporter_remote_write_error_count",
+                "Number of failed pushes of the metrics to the remote-write endpoint",
+                registry
+            )?,
+            status_bytes: register_int_gauge_with_registry!(
+                "fdb_exporter_status_bytes",
+                "Size of the last status read, in bytes",
+                registry
+            )?,
+            loop_lag: register_gauge_with_registry!(
+                "fdb_exporter_loop_lag_seconds",
+                "Time spent fetching and converting the status in the last loop iteration, on top of the delay between fetches",
+                registry
+            )?,
+            resident_memory: register_int_gauge_with_registry!(
+                "fdb_exporter_resident_memory_bytes",
+                "Resident memory of the exporter process, in bytes",
+                registry
+            )?,
+            coordinator_connect: register_gauge_with_registry!(
+                "fdb_exporter_coordinator_connect_seconds",
+                "Time the first read version of the last opened database took",
+                registry
+            )?,
+            fetch_io: register_gauge_with_registry!(
+                "fdb_exporter_fetch_io_seconds",
+                "Time spent reading the last raw status, retries included",
+                registry
+            )?,
+            parse: register_gauge_with_registry!(
+                "fdb_exporter_parse_seconds",
+                "Time spent parsing the last raw status",
+                registry
+            )?,
+        })
+    }
+}
+
+/// Path of a parse error with array indices and keys of [STATUS_ID_MAPS]
+/// replaced by `[]`, so it can be used as a label value of bounded cardinality
+/// (e.g. `cluster.processes[].roles[].role`)
+fn parse_error_path_label(path: &serde_path_to_error::Path) -> String {
+    let mut label = String::new();
+    let mut id_map = false;
+    for segment in path.iter() {
+        // Keys which aren't strings (e.g. process ids) are unknown segments
+        match segment {
+            _ if id_map => label.push_str("[]"),
+            Segment::Seq { .. } => label.push_str("[]"),
+            Segment::Map { key } | Segment::Enum { variant: key } => {
+                if !label.is_empty() {
+                    label.push('.');
+                }
+                label.push_str(key);
+            }
+            Segment::Unknown => label.push_str(".?"),
+        }
+        id_map = !id_map
+            && matches!(segment, Segment::Map { key } if STATUS_ID_MAPS.contains(&key.as_str()));
+    }
+    truncate_label(label)
+}
+
+/// Truncate a label value to [PARSE_ERROR_LABEL_MAX_LEN] characters
+fn truncate_label(value: String) -> String {
+    match value.char_indices().nth(PARSE_ERROR_LABEL_MAX_LEN) {
+        Some((index, _)) => value[..index].to_string(),
+        None => value,
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Remove every series of `metric` belonging to `cluster`, for metrics whose
+/// other labels may change from one status to another
+pub fn remove_cluster_series(metric: &IntGaugeVec, cluster: &str) {
+    for family in metric.collect() {
+        for series in family.get_metric() {
+            let labels: HashMap<&str, &str> = series
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if labels.get("cluster") == Some(&cluster) {
+                let _ = metric.remove(&labels);
+            }
+        }
+    }
+}
+
+/// Remove every series of `metric` whose labels `names` have the given
+/// `values`, whatever the values of its other labels
+pub fn remove_matching_series<T: MetricVecBuilder>(
+    metric: &MetricVec<T>,
+    names: &[&str],
+    values: &[&str],
+) {
+    for family in metric.collect() {
+        for series in family.get_metric() {
+            let labels: HashMap<&str, &str> = series
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if names
+                .iter()
+                .zip(values)
+                .all(|(name, value)| labels.get(name) == Some(value))
+            {
+                let _ = metric.remove(&labels);
+            }
+        }
+    }
+}
+
+/// Metric vectors whose series can be removed from their label values
+pub trait RemoveSeries {
+    fn remove_series(&self, labels: &[&str]);
+}
+
+impl<T: MetricVecBuilder> RemoveSeries for MetricVec<T> {
+    fn remove_series(&self, labels: &[&str]) {
+        // The series may not have been exported yet
+        let _ = self.remove_label_values(labels);
+    }
+}
+
+impl<T: RemoveSeries> RemoveSeries for HashMap<String, T> {
+    fn remove_series(&self, labels: &[&str]) {
+        self.values()
+            .for_each(|metric| metric.remove_series(labels));
+    }
+}
+
+impl Metrics {
+    /// Record when and where the last parsing error happened
+    fn set_last_parse_error(&self, error: &serde_path_to_error::Error<serde_json::Error>) {
+        self.exporter
+            .last_parse_error_timestamp
+            .set(unix_timestamp());
+
+        self.exporter
+            .parsing_error_path
+            .with_label_values(&[parse_error_path_label(error.path()).as_str()])
+            .inc();
+
+        let path = truncate_label(error.path().to_string());
+        let message = truncate_label(error.inner().to_string());
+        self.exporter.last_parse_error_info.reset();
+        self.exporter
+            .last_parse_error_info
+            .with_label_values(&[path.as_str(), message.as_str()])
+            .set(1);
+    }
+
+    /// Clear the last parsing error info, to be called once a status is parsed
+    pub fn reset_last_parse_error(&self) {
+        self.exporter.last_parse_error_info.reset();
+    }
+
+    /// Count an iteration of the status fetch loop
+    pub fn count_loop_iteration(&self) {
+        self.exporter.loop_iterations_total.inc();
+    }
+
+    /// Record the number of transaction attempts a status read took
+    pub fn observe_fetch_attempts(&self, attempts: u32) {
+        self.exporter.fetch_attempts.observe(attempts as f64);
+    }
+
+    /// Record the size of the raw status
+    pub fn set_status_bytes(&self, bytes: usize) {
+        self.exporter.status_bytes.set(bytes as i64);
+    }
+
+    /// Record the time the last loop iteration took beyond its delay
+    pub fn set_loop_lag(&self, lag: Duration) {
+        self.exporter.loop_lag.set(lag.as_secs_f64());
+    }
+
+    /// Record the time taken to connect a freshly opened database
+    pub fn set_coordinator_connect(&self, duration: Duration) {
+        self.exporter
+            .coordinator_connect
+            .set(duration.as_secs_f64());
+    }
+
+    /// Record the time taken by the steps of a status fetch which ran
+    pub fn set_fetch_timings(&self, timings: &FetchTimings) {
+        if let Some(io) = timings.io {
+            self.exporter.fetch_io.set(io.as_secs_f64());
+        }
+        if let Some(parse) = timings.parse {
+            self.exporter.parse.set(parse.as_secs_f64());
+        }
+    }
+
+    /// Record the resident memory of the exporter
+    pub fn set_resident_memory(&self, bytes: u64) {
+        self.set_saturating(&self.exporter.resident_memory, bytes);
+    }
+
+    /// Count a status fetch, a successful one also updates the freshness timestamp
+    pub fn count_fetch(&self, success: bool) {
+        self.exporter.fetch_total.inc();
+        let mut last_fetch = self
+            .last_fetch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        last_fetch.succeeded = success;
+        if success {
+            last_fetch.last_success = Some(Instant::now());
+            self.exporter.fetch_success_total.inc();
+            self.exporter
+                .last_fetch_success_timestamp
+                .set(unix_timestamp());
+        }
+    }
+
+    /// Outcome of the last status fetch
+    pub fn last_fetch(&self) -> LastFetch {
+        *self
+            .last_fetch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Count a reload of the cluster file
+    pub fn count_config_reload(&self) {
+        self.exporter.config_reload.inc();
+    }
+
+    /// Flag the local cluster file as differing from the connection string of
+    /// the status
+    pub fn set_cluster_file_mismatch(&self, mismatch: bool) {
+        self.exporter.cluster_file_mismatch.set(mismatch as i64);
+    }
+
+    /// Count a status parsed leniently where some sub-objects were skipped
+    pub fn count_partial_parse(&self) {
+        self.exporter.partial_parse.inc();
+    }
+
+    /// Count a status whose conversion to metrics panicked
+    pub fn count_conversion_panic(&self) {
+        self.exporter.conversion_panic.inc();
+    }
+
+    /// Count a failed write of the metrics to the dump file
+    pub fn count_dump_error(&self) {
+        self.exporter.dump_error.inc();
+    }
+
+    /// Count a metrics response larger than the maximum response size
+    pub fn count_response_truncated(&self) {
+        self.exporter.response_truncated.inc();
+    }
+
+    /// Count a failed push of the metrics to the remote-write endpoint
+    pub fn count_remote_write_error(&self) {
+        self.exporter.remote_write_error.inc();
+    }
+
+    /// Set the exponentially weighted moving average of the values given for a
+    /// series, computed as `decay * previous + (1 - decay) * value`. The first
+    /// value of a series is set as is.
+    pub fn set_smoothed(&self, metric: &GaugeVec, labels: &[&str], value: f64, decay: f64) {
+        let mut smoothed_values = self
+            .smoothed_values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let smoothed = smoothed_values
+            .entry(smoothed_key(metric, labels))
+            .and_modify(|previous| *previous = decay * *previous + (1.0 - decay) * value)
+            .or_insert(value);
+        metric.with_label_values(labels).set(*smoothed);
+    }
+
+    /// Remove a series set with [Metrics::set_smoothed], along with its average
+    pub fn remove_smoothed(&self, metric: &GaugeVec, labels: &[&str]) {
+        let mut smoothed_values = self
+            .smoothed_values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        smoothed_values.remove(&smoothed_key(metric, labels));
+        let _ = metric.remove_label_values(labels);
+    }
+
+    /// Set `recoveries` to the number of times `generation` increased within
+    /// `window`. The first generation seen is never counted, and a generation
+    /// going backwards (e.g. a recreated cluster) is taken as the new baseline.
+    pub fn set_recent_recoveries(
+        &self,
+        recoveries: &IntGaugeVec,
+        labels: &[&str],
+        generation: i64,
+        window: Duration,
+    ) {
+        self.set_recent_recoveries_at(recoveries, labels, generation, window, Instant::now());
+    }
+
+    fn set_recent_recoveries_at(
+        &self,
+        recoveries: &IntGaugeVec,
+        labels: &[&str],
+        generation: i64,
+        window: Duration,
+        now: Instant,
+    ) {
+        let mut recent_recoveries = self
+            .recent_recoveries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let key = labels.iter().map(|label| label.to_string()).collect();
+        let recent = recent_recoveries
+            .entry(key)
+            .or_insert_with(|| RecentRecoveries {
+                generation,
+                changes: VecDeque::new(),
+            });
+        if generation > recent.generation {
+            recent.changes.push_back(now);
+        }
+        recent.generation = generation;
+        while recent
+            .changes
+            .front()
+            .is_some_and(|change| now.duration_since(*change) > window)
+        {
+            recent.changes.pop_front();
+        }
+        recoveries
+            .with_label_values(labels)
+            .set(recent.changes.len() as i64);
+    }
+
+    /// Remember whether the cluster is recovering, to tell a status key
+    /// missing during a recovery from a broken setup
+    pub fn set_recovering(&self, recovering: bool) {
+        self.recovering.store(recovering, Ordering::Relaxed);
+    }
+
+    /// Remember the process holding a singleton role, `migrations` is
+    /// incremented when it differs from the holder given on the previous call
+    /// for the same labels. The first holder seen is never counted.
+    pub fn set_role_holder(&self, migrations: &IntCounterVec, labels: &[&str], process_id: &str) {
+        let mut role_holders = self
+            .role_holders
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let counter = migrations.with_label_values(labels);
+        let key = labels.iter().map(|label| label.to_string()).collect();
+        match role_holders.insert(key, process_id.to_string()) {
+            Some(previous) if previous != process_id => counter.inc(),
+            _ => (),
+        }
+    }
+
+    /// Set an unsigned value on an int gauge, values above [i64::MAX] are
+    /// saturated and a warning is logged the first time it happens for a metric
+    pub fn set_saturating(&self, metric: &IntGauge, value: u64) {
+        let saturated = i64::try_from(value).unwrap_or_else(|_| {
+            let name = metric
+                .desc()
+                .first()
+                .map(|desc| desc.fq_name.clone())
+                .unwrap_or_default();
+            let mut saturated_metrics = self
+                .saturated_metrics
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if saturated_metrics.insert(name.clone()) {
+                warn!(
+                    "Value {} of {} exceeds i64::MAX, it will be reported as {}",
+                    value,
+                    name,
+                    i64::MAX
+                );
+            }
+            i64::MAX
+        });
+        metric.set(saturated);
+    }
+
+    /// Same as [Metrics::set_saturating] when there is a value, like
+    /// [AndSetSingle::and_set]
+    pub fn and_set_saturating(&self, value: Option<u64>, metric: &IntGauge) {
+        if let Some(value) = value {
+            self.set_saturating(metric, value);
+        }
+    }
 }
 
 impl MetricsConvertible for FetchError {
-    fn to_metrics(&self, _: &[&str]) {
+    fn to_metrics(&self, metrics: &Metrics, _: &[&str]) {
         match self {
-            FetchError::Fdb(_) => P_FDB_EXPORTER_FDB_ERROR.inc(),
-            FetchError::FdbBinding(_) => P_FDB_EXPORTER_FDB_BINDING_ERROR.inc(),
-            FetchError::StatusNotFound => P_FDB_EXPORTER_STATUS_NOT_FOUND.inc(),
-            FetchError::Parsing(_) => P_FDB_EXPORTER_PARSING_ERROR.inc(),
+            FetchError::Fdb(_) => metrics.exporter.fdb_error.inc(),
+            FetchError::FdbBinding(_) => metrics.exporter.fdb_binding_error.inc(),
+            FetchError::TransactionTimedOut(_) => metrics.exporter.fetch_timeout.inc(),
+            FetchError::StatusNotFound => {
+                metrics.exporter.status_not_found.inc();
+                // The key may be missing transiently while the cluster recovers
+                if metrics.recovering.load(Ordering::Relaxed) {
+                    metrics.exporter.status_none_during_recovery.inc();
+                }
+            }
+            FetchError::EmptyStatus => metrics.exporter.empty_status.inc(),
+            FetchError::Parsing(e) => {
+                metrics.exporter.parsing_error.inc();
+                metrics.set_last_parse_error(e);
+            }
             FetchError::TimeoutTooLarge(_) => (),
+            FetchError::Fdbcli(_) => metrics.exporter.cmd_error.inc(),
+            FetchError::Timeout(_) => metrics.exporter.overall_timeout.inc(),
         };
     }
 }
 
 /// Implements methods that should be used to register more than one metric on a type
-/// [StaticMetric::register] should only be used in lazy_static to generate metrics
+/// [StaticMetric::register] should only be used in constructors of metrics
 /// [StaticMetric::set] should be used in methods to apply new values
 pub trait StaticMetric<T> {
     /// Generate a HashMap matching type T with all necessary values to be exposed
-    fn register(prefix: &str, desc: &str) -> HashMap<String, T>;
+    fn register(
+        registry: &MetricsRegistry,
+        prefix: &str,
+        desc: &str,
+    ) -> prometheus::Result<HashMap<String, T>>;
     /// Apply T metrics on the HashMap
     /// [StaticMetric::register] MUST have been used before calling set
     fn set(&self, metric: &HashMap<String, T>, labels: &[&str]);
 }
 
 pub trait AndSet<T> {
-    /// Borrow [Self] to update a HashMap of metrics
-    fn and_set(&self, metric: &HashMap<String, T>);
     /// Borrow [Self] to update a HashMap of metrics where each
     /// metric will have specific labels
     fn and_set_with_labels(&self, metric: &HashMap<String, T>, labels: &[&str]);
@@ -88,17 +772,163 @@ impl AndSetSingle<IntGauge> for Option<i64> {
     }
 }
 
+fn smoothed_key(metric: &GaugeVec, labels: &[&str]) -> (String, Vec<String>) {
+    let name = metric
+        .desc()
+        .first()
+        .map(|desc| desc.fq_name.clone())
+        .unwrap_or_default();
+    (name, labels.iter().map(|l| l.to_string()).collect())
+}
+
 impl<M, T> AndSet<T> for Option<M>
 where
     M: StaticMetric<T>,
 {
-    fn and_set(&self, metric: &HashMap<String, T>) {
-        self.and_set_with_labels(metric, &[]);
-    }
-
     fn and_set_with_labels(&self, metric: &HashMap<String, T>, labels: &[&str]) {
         if let Some(v) = self {
             v.set(metric, labels);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::parse_cluster_status;
+
+    const SIMPLE_STATUS: &str = include_str!("../../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn status_not_found_during_recovery() {
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+        FetchError::StatusNotFound.to_metrics(metrics, &[]);
+        metrics.set_recovering(true);
+        FetchError::StatusNotFound.to_metrics(metrics, &[]);
+
+        assert_eq!(metrics.exporter.status_not_found.get(), 2);
+        assert_eq!(metrics.exporter.status_none_during_recovery.get(), 1);
+    }
+
+    #[test]
+    fn parse_error_path_is_captured() {
+        let mut status: serde_json::Value = serde_json::from_str(SIMPLE_STATUS).unwrap();
+        status["client"]["coordinators"]["coordinators"][0]["address"] =
+            serde_json::Value::from("coordinator.invalid");
+        let raw = serde_json::to_vec(&status).unwrap();
+        let metrics = &Metrics::new(&Registry::new()).unwrap();
+
+        let error = match parse_cluster_status(&raw) {
+            Err(e @ FetchError::Parsing(_)) => e,
+            _ => panic!("status should fail to parse"),
+        };
+        error.to_metrics(metrics, &[]);
+
+        let family = &metrics.exporter.last_parse_error_info.collect()[0];
+        let labels = family.get_metric()[0].get_label();
+        let path = labels.iter().find(|l| l.get_name() == "path").unwrap();
+        assert_eq!(
+            path.get_value(),
+            "client.coordinators.coordinators[0].address"
+        );
+        assert_eq!(
+            metrics
+                .exporter
+                .parsing_error_path
+                .with_label_values(&["client.coordinators.coordinators[].address"])
+                .get(),
+            1
+        );
+        assert!(metrics.exporter.last_parse_error_timestamp.get() > 0);
+
+        metrics.reset_last_parse_error();
+        assert!(metrics.exporter.last_parse_error_info.collect()[0]
+            .get_metric()
+            .is_empty());
+    }
+
+    #[test]
+    fn parse_error_path_strips_ids() {
+        let mut status: serde_json::Value = serde_json::from_str(SIMPLE_STATUS).unwrap();
+        for process in status["cluster"]["processes"]
+            .as_object_mut()
+            .unwrap()
+            .values_mut()
+        {
+            process["roles"][0]["role"] = serde_json::Value::from(1);
+        }
+        let raw = serde_json::to_vec(&status).unwrap();
+        let Err(FetchError::Parsing(error)) = parse_cluster_status(&raw) else {
+            panic!("status should fail to parse");
+        };
+        assert_eq!(
+            parse_error_path_label(error.path()),
+            "cluster.processes[].roles[].role"
+        );
+    }
+
+    #[test]
+    fn truncate_long_label() {
+        let label = truncate_label("a".repeat(PARSE_ERROR_LABEL_MAX_LEN + 10));
+        assert_eq!(label.len(), PARSE_ERROR_LABEL_MAX_LEN);
+    }
+
+    #[test]
+    fn fetch_success_ratio() {
+        let metrics = Metrics::new(&Registry::new()).unwrap();
+
+        metrics.count_fetch(false);
+        metrics.count_fetch(true);
+
+        assert_eq!(metrics.exporter.fetch_total.get(), 2);
+        assert_eq!(metrics.exporter.fetch_success_total.get(), 1);
+        assert!(metrics.exporter.last_fetch_success_timestamp.get() > 0);
+    }
+
+    #[test]
+    fn smoothed_values_decay() {
+        let metrics = Metrics::new(&Registry::new()).unwrap();
+        let metric = GaugeVec::new(
+            prometheus::Opts::new("test_smoothed_values_decay", "Smoothing test"),
+            CLUSTER_LABELS,
+        )
+        .unwrap();
+        let labels = ["test"];
+
+        metrics.set_smoothed(&metric, &labels, 100.0, 0.75);
+        assert_eq!(metric.with_label_values(&labels).get(), 100.0);
+        metrics.set_smoothed(&metric, &labels, 0.0, 0.75);
+        assert_eq!(metric.with_label_values(&labels).get(), 75.0);
+
+        metrics.remove_smoothed(&metric, &labels);
+        metrics.set_smoothed(&metric, &labels, 10.0, 0.75);
+        assert_eq!(metric.with_label_values(&labels).get(), 10.0);
+    }
+
+    #[test]
+    fn recent_recoveries_window() {
+        let metrics = Metrics::new(&Registry::new()).unwrap();
+        let metric = IntGaugeVec::new(
+            prometheus::Opts::new("test_recent_recoveries", "Recoveries test"),
+            CLUSTER_LABELS,
+        )
+        .unwrap();
+        let labels = ["test"];
+        let window = Duration::from_secs(300);
+        let start = Instant::now();
+        let at = |generation, seconds| {
+            let now = start + Duration::from_secs(seconds);
+            metrics.set_recent_recoveries_at(&metric, &labels, generation, window, now);
+            metric.with_label_values(&labels).get()
+        };
+
+        // The first generation seen is the baseline
+        assert_eq!(at(10, 0), 0);
+        assert_eq!(at(12, 60), 1);
+        assert_eq!(at(14, 120), 2);
+        // Going backwards is not a recovery
+        assert_eq!(at(2, 180), 2);
+        assert_eq!(at(2, 361), 1);
+        assert_eq!(at(2, 421), 0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Encoding of gathered metrics as a Prometheus remote-write request, a
+//! snappy-compressed protobuf `WriteRequest`.
+
+use ::prometheus::proto::{Metric, MetricFamily, MetricType};
+use prost::Message;
+
+/// Content type of a remote-write request body
+pub const CONTENT_TYPE: &str = "application/x-protobuf";
+/// Encoding of a remote-write request body
+pub const CONTENT_ENCODING: &str = "snappy";
+/// Version of the remote-write protocol, sent in
+/// `X-Prometheus-Remote-Write-Version`
+pub const VERSION: &str = "0.1.0";
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+/// Encode `families` as the body of a remote-write request, every sample
+/// being taken at `timestamp_ms` (milliseconds since the epoch). Histograms
+/// and summaries are split in their `_bucket`, `_sum` and `_count` series.
+pub fn encode(families: &[MetricFamily], timestamp_ms: i64) -> Vec<u8> {
+    let mut request = WriteRequest::default();
+    for family in families {
+        let name = family.get_name();
+        for metric in family.get_metric() {
+            let mut push = |suffix: &str, extra: Option<(&str, String)>, value: f64| {
+                request.timeseries.push(TimeSeries {
+                    labels: labels(&format!("{}{}", name, suffix), metric, extra),
+                    samples: vec![Sample {
+                        value,
+                        timestamp: timestamp_ms,
+                    }],
+                })
+            };
+            match family.get_field_type() {
+                MetricType::COUNTER => push("", None, metric.get_counter().get_value()),
+                MetricType::GAUGE => push("", None, metric.get_gauge().get_value()),
+                MetricType::UNTYPED => push("", None, metric.get_untyped().get_value()),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    for bucket in histogram.get_bucket() {
+                        let le = bucket.get_upper_bound().to_string();
+                        push(
+                            "_bucket",
+                            Some(("le", le)),
+                            bucket.get_cumulative_count() as f64,
+                        );
+                    }
+                    let count = histogram.get_sample_count() as f64;
+                    push("_bucket", Some(("le", "+Inf".to_string())), count);
+                    push("_sum", None, histogram.get_sample_sum());
+                    push("_count", None, count);
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        let label = quantile.get_quantile().to_string();
+                        push("", Some(("quantile", label)), quantile.get_value());
+                    }
+                    push("_sum", None, summary.get_sample_sum());
+                    push("_count", None, summary.get_sample_count() as f64);
+                }
+            }
+        }
+    }
+    snap::raw::Encoder::new()
+        .compress_vec(&request.encode_to_vec())
+        .expect("remote-write request exceeds the snappy size limit")
+}
+
+/// Labels of a series sorted by name, as required by the protocol
+fn labels(name: &str, metric: &Metric, extra: Option<(&str, String)>) -> Vec<Label> {
+    let mut labels: Vec<Label> = metric
+        .get_label()
+        .iter()
+        .map(|label| Label {
+            name: label.get_name().to_string(),
+            value: label.get_value().to_string(),
+        })
+        .chain(extra.map(|(name, value)| Label {
+            name: name.to_string(),
+            value,
+        }))
+        .chain(std::iter::once(Label {
+            name: "__name__".to_string(),
+            value: name.to_string(),
+        }))
+        .collect();
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Exporter;
+
+    #[test]
+    fn series_are_decodable() {
+        let exporter = Exporter::new();
+        exporter.count_fetch(true);
+        exporter.observe_fetch_attempts(2);
+        let body = encode(&exporter.gather(), 1_700_000_000_000);
+
+        let raw = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let request = WriteRequest::decode(raw.as_slice()).unwrap();
+        let series = |name: &str| {
+            request
+                .timeseries
+                .iter()
+                .filter(|series| {
+                    series.labels[0].name == "__name__" && series.labels[0].value == name
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let fetches = series("fdb_exporter_fetch_total");
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetches[0].samples[0].value, 1.0);
+        assert_eq!(fetches[0].samples[0].timestamp, 1_700_000_000_000);
+
+        // One series per bucket, including +Inf
+        let buckets = series("fdb_exporter_fetch_attempts_bucket");
+        assert!(buckets.iter().any(|series| series
+            .labels
+            .iter()
+            .any(|label| label.name == "le" && label.value == "+Inf")));
+        assert_eq!(
+            series("fdb_exporter_fetch_attempts_count")[0].samples[0].value,
+            1.0
+        );
+    }
+}
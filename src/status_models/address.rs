@@ -1,4 +1,4 @@
-use serde::{de, Deserialize};
+use serde::{de, Deserialize, Serialize};
 
 use std::fmt;
 use url::Host;
@@ -20,6 +20,7 @@ impl fmt::Display for AddressError {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct FdbProcessAddress {
     pub host: Host<String>,
     pub port: u16,
@@ -55,13 +56,35 @@ impl FdbProcessAddress {
     }
 }
 
+impl FdbProcessAddress {
+    /// Whether both addresses designate the same host and port, whatever
+    /// their TLS suffix
+    pub fn same_endpoint(&self, other: &FdbProcessAddress) -> bool {
+        self.host == other.host && self.port == other.port
+    }
+}
+
 impl<'de> Deserialize<'de> for FdbProcessAddress {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        FdbProcessAddress::parse(s.as_str()).map_err(de::Error::custom)
+        FdbProcessAddress::parse(s.as_str()).map_err(|e| {
+            de::Error::custom(format_args!(
+                "invalid address '{}', expected an IP or DNS host followed by :port and an optional :tls suffix: {}",
+                s, e
+            ))
+        })
+    }
+}
+
+impl Serialize for FdbProcessAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
     }
 }
 
@@ -142,6 +165,21 @@ mod test {
         assert_eq!(round_trip, addr)
     }
 
+    #[test]
+    fn ipv6_expanded_form() {
+        let addr = "[2001:db8:0:0:0:0:0:1]:4500";
+        let deserialized = FdbProcessAddress::parse(addr).unwrap();
+        assert_eq!(
+            deserialized.host,
+            Host::<String>::Ipv6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))
+        );
+        assert_eq!(deserialized.port, 4500u16);
+        assert!(!deserialized.tls);
+
+        // Written back in its compressed form
+        assert_eq!(deserialized.to_string(), "[2001:db8::1]:4500")
+    }
+
     #[test]
     fn dns_without_tls() {
         let addr = "somedomain.com:4500";
@@ -171,4 +209,14 @@ mod test {
         let round_trip = deserialized.to_string();
         assert_eq!(round_trip, addr)
     }
+
+    #[test]
+    fn invalid_address_error_shows_the_address() {
+        let error = serde_json::from_str::<FdbProcessAddress>("\"somedomain.com:tls\"")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("invalid address 'somedomain.com:tls'"));
+        assert!(error.ends_with("Missing port"));
+    }
 }
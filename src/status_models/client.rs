@@ -1,9 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::status_models::address::FdbProcessAddress;
 
 /// jq: .client
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClientStatus {
     pub coordinators: ClientCoordinators,
     pub timestamp: Option<i64>,
@@ -12,7 +12,7 @@ pub struct ClientStatus {
 }
 
 /// jq: .client.messages[]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClientMessage {
     /// Can only be a discrete list of values:
     /// - inconsistent_cluster_file
@@ -30,21 +30,21 @@ pub struct ClientMessage {
 }
 
 /// jq: .client.database_status
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClientDatabaseStatus {
     pub available: bool,
     pub healthy: bool,
 }
 
 /// jq: .client.coordinators
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClientCoordinators {
     pub coordinators: Vec<ClientCoordinator>,
     pub quorum_reachable: bool,
 }
 
 /// jq: .client.coordinators.coordinator
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClientCoordinator {
     pub address: FdbProcessAddress,
     pub protocol: Option<String>,
@@ -1,42 +1,95 @@
 use crate::status_models::cluster_data::ClusterData;
 use crate::status_models::cluster_machine::{ClusterMachine, MachineId};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use super::cluster_backup::ClusterBackup;
+use super::cluster_blob_granules::ClusterBlobGranules;
+use super::cluster_clients::ClusterClients;
+use super::cluster_configuration::ClusterConfiguration;
 use super::cluster_probe::ClusterLatencyProbe;
 use super::cluster_process::{ClusterClassType, ClusterProcess, ProcessId};
+use super::cluster_process_role::{ClusterProcessRole, LatencyStats, RoleId};
 use super::cluster_qos::ClusterQos;
+use super::cluster_recovery::ClusterRecoveryState;
 use super::cluster_wiggle::ClusterStorageWiggle;
+use super::cluster_workload::ClusterWorkload;
+use super::lenient::{deserialize_lenient, deserialize_lenient_map};
 
 /// jq: .cluster
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterStatus {
     #[serde(default)]
     pub database_available: bool,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_lenient_map")]
     pub machines: HashMap<MachineId, ClusterMachine>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub data: Option<ClusterData>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_lenient_map")]
     pub processes: HashMap<ProcessId, ClusterProcess>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub latency_probe: Option<ClusterLatencyProbe>,
     #[serde(default)]
     pub generation: i64,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub qos: Option<ClusterQos>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub recovery_state: Option<ClusterRecoveryState>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub storage_wiggler: Option<ClusterStorageWiggle>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub layers: Option<ClusterStatusLayers>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub blob_granules: Option<ClusterBlobGranules>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub workload: Option<ClusterWorkload>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub configuration: Option<ClusterConfiguration>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub bounce_impact: Option<ClusterBounceImpact>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub clients: Option<ClusterClients>,
+    /// Protocol negotiated by the cluster, absent on older status formats
+    pub protocol_version: Option<String>,
+    /// Whether every replica of the data is present, a healthy cluster may
+    /// not be fully replicated while it heals
+    pub full_replication: Option<bool>,
+    /// Coordinators the cluster currently considers authoritative
+    pub connection_string: Option<String>,
+    /// Clock of the cluster controller when it built the status, in seconds
+    pub cluster_controller_timestamp: Option<i64>,
 }
 
 /// jq: .cluster.layers
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterStatusLayers {
     #[serde(rename = "_valid")]
     pub valid: bool,
     pub error: Option<String>,
 
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub backup: Option<ClusterBackup>,
 }
 
+/// jq: .cluster.bounce_impact
+#[derive(Deserialize, Serialize)]
+pub struct ClusterBounceImpact {
+    /// Whether all processes can be restarted at once without data movement,
+    /// absent from statuses of older versions
+    pub can_clean_bounce: Option<bool>,
+    /// Why a clean bounce is not possible
+    pub reason: Option<String>,
+}
+
+/// Aggregates of the processes sharing a class type
+#[derive(Debug, Default, PartialEq)]
+pub struct ClusterClassSummary {
+    pub process_count: i64,
+    /// Sum of the free bytes reported by each process, processes sharing a
+    /// disk are counted once each
+    pub disk_free_bytes: i64,
+}
+
 impl ClusterStatus {
     /// Navigate through all process available and their roles
     /// and determine the number of process allocated to a role
@@ -52,6 +105,172 @@ impl ClusterStatus {
         }
         output
     }
+
+    /// Number of processes running each role in each datacenter, processes
+    /// without datacenter (e.g. on single-region clusters) are counted in an
+    /// empty one
+    pub fn cluster_roles_count_by_dc(&self) -> HashMap<(ClusterClassType, &str), u32> {
+        let mut output = HashMap::new();
+        for process in self.processes.values() {
+            let dcid = process
+                .locality
+                .as_ref()
+                .and_then(|locality| locality.dcid.as_deref())
+                .unwrap_or("");
+            for role in process.roles.iter().filter_map(|role| role.role) {
+                *output.entry((role, dcid)).or_insert(0) += 1;
+            }
+        }
+        output
+    }
+
+    /// Navigate through all process available and aggregate them per class
+    /// type, processes without class type are counted as unset
+    pub fn cluster_classes_summary(&self) -> HashMap<ClusterClassType, ClusterClassSummary> {
+        let mut output: HashMap<ClusterClassType, ClusterClassSummary> = HashMap::new();
+        for process in self.processes.values() {
+            let class_type = process.class_type.unwrap_or(ClusterClassType::Unset);
+            let summary = output.entry(class_type).or_default();
+            summary.process_count += 1;
+            if let Some(disk) = &process.disk {
+                summary.disk_free_bytes += disk.free_bytes;
+            }
+        }
+        output
+    }
+
+    /// Highest p99 read latency among storage roles, from
+    /// `.cluster.processes[].roles[].read_latency_statistics.p99`
+    pub fn worst_storage_read_latency(&self) -> Option<f64> {
+        self.processes
+            .values()
+            .flat_map(|process| &process.roles)
+            .filter(|role| role.role == Some(ClusterClassType::Storage))
+            .filter_map(|role| role.read_latency_statistics.as_ref())
+            .map(|stats| stats.p99)
+            .reduce(f64::max)
+    }
+
+    /// Highest `percentile` of the latency statistics selected by `statistics`
+    /// among all roles, `None` when no role reports them. Statuses only
+    /// reporting means (e.g. `.cluster.latency_probe`) have no percentiles.
+    pub fn worst_latency(
+        &self,
+        statistics: fn(&ClusterProcessRole) -> Option<&LatencyStats>,
+        percentile: fn(&LatencyStats) -> f64,
+    ) -> Option<f64> {
+        self.processes
+            .values()
+            .flat_map(|process| &process.roles)
+            .filter_map(statistics)
+            .map(percentile)
+            .reduce(f64::max)
+    }
+
+    /// Number of storage roles whose available kvstore space is lower than
+    /// `ratio` of their total space, roles not reporting them are left out
+    pub fn storage_servers_low_space(&self, ratio: f64) -> usize {
+        self.processes
+            .values()
+            .flat_map(|process| &process.roles)
+            .filter(|role| role.role == Some(ClusterClassType::Storage))
+            .filter_map(|role| Some((role.kvstore_available_bytes?, role.kvstore_total_bytes?)))
+            .filter(|(available, total)| *total > 0 && (*available as f64) < ratio * *total as f64)
+            .count()
+    }
+
+    /// Lowest ratio of free to total disk space among the processes, those
+    /// not reporting their disk are left out
+    pub fn min_disk_free_ratio(&self) -> Option<f64> {
+        self.processes
+            .values()
+            .filter_map(|process| process.disk.as_ref())
+            .filter(|disk| disk.total_bytes > 0)
+            .map(|disk| disk.free_bytes as f64 / disk.total_bytes as f64)
+            .reduce(f64::min)
+    }
+
+    /// Number of storage roles of excluded processes which still hold data,
+    /// the exclusion is complete once it drops to 0
+    pub fn excluded_servers_with_data(&self) -> usize {
+        self.processes
+            .values()
+            .filter(|process| process.excluded == Some(true))
+            .flat_map(|process| &process.roles)
+            .filter(|role| role.role == Some(ClusterClassType::Storage))
+            .filter(|role| role.kvstore_used_bytes.is_some_and(|used| used > 0))
+            .count()
+    }
+
+    /// FoundationDB version of the cluster as `major.minor` (e.g. `7.3`),
+    /// decoded from [ClusterStatus::protocol_version]. The status schema is
+    /// detected at runtime this way rather than from the client version the
+    /// exporter is built for, so a cluster being upgraded is read correctly.
+    pub fn fdb_version(&self) -> Option<String> {
+        // e.g. fdb00b071010000 for 7.1, fdb00b073000000 for 7.3
+        let protocol = u64::from_str_radix(self.protocol_version.as_deref()?, 16).ok()?;
+        if protocol >> 32 != 0x0FDB00B0 {
+            return None;
+        }
+        Some(format!(
+            "{}.{}",
+            (protocol >> 28) & 0xF,
+            (protocol >> 24) & 0xF
+        ))
+    }
+
+    /// Process holding each singleton role (e.g. data distributor), from
+    /// `.cluster.processes[].roles[].role`
+    pub fn singleton_role_holders(&self) -> HashMap<ClusterClassType, &ProcessId> {
+        self.processes
+            .iter()
+            .flat_map(|(id, process)| process.roles.iter().map(move |role| (id, role)))
+            .filter_map(|(id, role)| {
+                role.role
+                    .filter(ClusterClassType::is_singleton)
+                    .map(|r| (r, id))
+            })
+            .collect()
+    }
+
+    /// Process on which the role with the given id runs
+    pub fn role_process(&self, role_id: &RoleId) -> Option<&ProcessId> {
+        self.processes
+            .iter()
+            .find(|(_, process)| {
+                process
+                    .roles
+                    .iter()
+                    .any(|role| role.id.as_ref() == Some(role_id))
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// Number of cores used by all processes, processes which don't report
+    /// their CPU usage are left out
+    pub fn cpu_cores_used(&self) -> Option<f64> {
+        self.processes
+            .values()
+            .filter_map(|process| process.cpu.as_ref())
+            .map(|cpu| cpu.usage_cores)
+            .reduce(|total, usage| total + usage)
+    }
+
+    /// Average logical core utilization of the machines which report it. The
+    /// status doesn't give the number of cores of machines, so each machine
+    /// weighs the same.
+    pub fn average_cpu_utilization(&self) -> Option<f64> {
+        let utilizations: Vec<f64> = self
+            .machines
+            .values()
+            .filter_map(|machine| machine.cpu.as_ref())
+            .map(|cpu| cpu.logical_core_utilization)
+            .collect();
+        if utilizations.is_empty() {
+            return None;
+        }
+        Some(utilizations.iter().sum::<f64>() / utilizations.len() as f64)
+    }
 }
 
 #[cfg(test)]
@@ -61,12 +280,15 @@ mod tests {
     use crate::status_models::{
         address::FdbProcessAddress,
         cluster_data::ClusterData,
-        cluster_process::{ClusterClassType, ClusterProcess, ProcessId},
+        cluster_process::{
+            ClusterClassType, ClusterProcess, ClusterProcessCpu, ClusterProcessLocality, ProcessId,
+        },
+        cluster_process_disk::{ClusterProcessDisk, ClusterProcessDiskStat},
         cluster_process_role::ClusterProcessRole,
         cluster_qos::ClusterQos,
     };
 
-    use super::ClusterStatus;
+    use super::{ClusterClassSummary, ClusterStatus};
 
     impl Default for ClusterProcess {
         fn default() -> Self {
@@ -82,6 +304,7 @@ mod tests {
                 machine_id: None,
                 excluded: None,
                 fault_domain: None,
+                locality: None,
                 memory: None,
                 network: None,
                 run_loop_busy: None,
@@ -89,6 +312,7 @@ mod tests {
                 cpu: None,
                 disk: None,
                 roles: Vec::new(),
+                messages: Vec::new(),
             }
         }
     }
@@ -103,12 +327,22 @@ mod tests {
                 latency_probe: None,
                 generation: 1,
                 qos: Some(ClusterQos::default()),
+                recovery_state: None,
                 layers: Some(super::ClusterStatusLayers {
                     valid: true,
                     error: None,
                     backup: None,
                 }),
                 storage_wiggler: None,
+                blob_granules: None,
+                workload: None,
+                configuration: None,
+                bounce_impact: None,
+                clients: None,
+                protocol_version: None,
+                full_replication: None,
+                connection_string: None,
+                cluster_controller_timestamp: None,
             }
         }
     }
@@ -182,4 +416,262 @@ mod tests {
         );
         assert_eq!(count.get(&ClusterClassType::Log).unwrap().to_owned(), 1);
     }
+
+    #[test]
+    fn summarize_classes() {
+        let storage = || ClusterProcess {
+            class_type: Some(ClusterClassType::Storage),
+            disk: serde_json::from_value(serde_json::json!({
+                "busy": 0.1,
+                "free_bytes": 100,
+                "total_bytes": 1000,
+                "reads": {"counter": 0, "hz": 0.0, "sectors": 0.0},
+                "writes": {"counter": 0, "hz": 0.0, "sectors": 0.0},
+            }))
+            .unwrap(),
+            ..Default::default()
+        };
+        let processes = HashMap::from([
+            (ProcessId("first".to_string()), storage()),
+            (ProcessId("second".to_string()), storage()),
+            (ProcessId("third".to_string()), ClusterProcess::default()),
+        ]);
+        let status = ClusterStatus {
+            processes,
+            ..Default::default()
+        };
+        let summary = status.cluster_classes_summary();
+
+        assert_eq!(
+            summary.get(&ClusterClassType::Storage),
+            Some(&ClusterClassSummary {
+                process_count: 2,
+                disk_free_bytes: 200,
+            })
+        );
+        assert_eq!(
+            summary.get(&ClusterClassType::Unset),
+            Some(&ClusterClassSummary {
+                process_count: 1,
+                disk_free_bytes: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn worst_storage_read_latency() {
+        let role = |role, p99: f64| ClusterProcessRole {
+            role: Some(role),
+            read_latency_statistics: serde_json::from_value(serde_json::json!({
+                "count": 1.0, "min": 0.0, "max": 1.0, "median": 0.0, "mean": 0.0,
+                "p25": 0.0, "p90": 0.0, "p95": 0.0, "p99": p99, "p99.9": 1.0,
+            }))
+            .unwrap(),
+            ..Default::default()
+        };
+        let processes = HashMap::from([
+            (
+                ProcessId("first".to_string()),
+                ClusterProcess {
+                    roles: vec![role(ClusterClassType::Storage, 0.002)],
+                    ..Default::default()
+                },
+            ),
+            (
+                ProcessId("second".to_string()),
+                ClusterProcess {
+                    roles: vec![
+                        role(ClusterClassType::Storage, 0.005),
+                        role(ClusterClassType::Log, 0.5),
+                    ],
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let status = ClusterStatus {
+            processes,
+            ..Default::default()
+        };
+
+        assert_eq!(status.worst_storage_read_latency(), Some(0.005));
+        assert_eq!(ClusterStatus::default().worst_storage_read_latency(), None);
+    }
+
+    #[test]
+    fn storage_servers_low_space() {
+        let role = |role, available, total| ClusterProcessRole {
+            role: Some(role),
+            kvstore_available_bytes: available,
+            kvstore_total_bytes: Some(total),
+            ..Default::default()
+        };
+        let processes = HashMap::from([(
+            ProcessId("first".to_string()),
+            ClusterProcess {
+                roles: vec![
+                    role(ClusterClassType::Storage, Some(5), 100),
+                    role(ClusterClassType::Storage, Some(50), 100),
+                    role(ClusterClassType::Storage, None, 100),
+                    role(ClusterClassType::Storage, Some(0), 0),
+                    role(ClusterClassType::Log, Some(1), 100),
+                ],
+                ..Default::default()
+            },
+        )]);
+        let status = ClusterStatus {
+            processes,
+            ..Default::default()
+        };
+
+        assert_eq!(status.storage_servers_low_space(0.1), 1);
+        assert_eq!(status.storage_servers_low_space(0.6), 2);
+        assert_eq!(status.storage_servers_low_space(0.0), 0);
+    }
+
+    #[test]
+    fn count_roles_by_dc() {
+        let process = |dcid: Option<&str>, roles: Vec<ClusterClassType>| ClusterProcess {
+            locality: Some(ClusterProcessLocality {
+                dcid: dcid.map(str::to_string),
+                machineid: None,
+                processid: None,
+                zoneid: None,
+            }),
+            ..create_process_with_roles(roles)
+        };
+        let status = ClusterStatus {
+            processes: HashMap::from([
+                (
+                    ProcessId("primary".to_string()),
+                    process(
+                        Some("dc1"),
+                        vec![ClusterClassType::Log, ClusterClassType::Storage],
+                    ),
+                ),
+                (
+                    ProcessId("remote".to_string()),
+                    process(Some("dc2"), vec![ClusterClassType::Log]),
+                ),
+                (
+                    ProcessId("remote_storage".to_string()),
+                    process(Some("dc2"), vec![ClusterClassType::Storage]),
+                ),
+                (
+                    ProcessId("no_dc".to_string()),
+                    process(None, vec![ClusterClassType::Storage]),
+                ),
+            ]),
+            ..Default::default()
+        };
+
+        let count = status.cluster_roles_count_by_dc();
+        assert_eq!(count.len(), 5);
+        assert_eq!(count[&(ClusterClassType::Log, "dc1")], 1);
+        assert_eq!(count[&(ClusterClassType::Log, "dc2")], 1);
+        assert_eq!(count[&(ClusterClassType::Storage, "dc2")], 1);
+        assert_eq!(count[&(ClusterClassType::Storage, "")], 1);
+    }
+
+    #[test]
+    fn min_disk_free_ratio() {
+        let stat = || ClusterProcessDiskStat {
+            counter: 0,
+            hz: 0.0,
+            sectors: 0.0,
+        };
+        let process = |disk: Option<(i64, i64)>| ClusterProcess {
+            disk: disk.map(|(free_bytes, total_bytes)| ClusterProcessDisk {
+                busy: 0.0,
+                free_bytes,
+                total_bytes,
+                reads: stat(),
+                writes: stat(),
+            }),
+            ..Default::default()
+        };
+        let mut status = ClusterStatus {
+            processes: HashMap::from([
+                (ProcessId("half".to_string()), process(Some((50, 100)))),
+                (ProcessId("full".to_string()), process(Some((10, 100)))),
+                (ProcessId("unknown".to_string()), process(Some((0, 0)))),
+                (ProcessId("no_disk".to_string()), process(None)),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(status.min_disk_free_ratio(), Some(0.1));
+        status.processes.retain(|_, process| process.disk.is_none());
+        assert_eq!(status.min_disk_free_ratio(), None);
+    }
+
+    #[test]
+    fn excluded_servers_with_data() {
+        let process = |excluded, role, used| ClusterProcess {
+            excluded: Some(excluded),
+            roles: vec![ClusterProcessRole {
+                role: Some(role),
+                kvstore_used_bytes: used,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let processes = HashMap::from([
+            (
+                ProcessId("draining".to_string()),
+                process(true, ClusterClassType::Storage, Some(1024)),
+            ),
+            (
+                ProcessId("drained".to_string()),
+                process(true, ClusterClassType::Storage, Some(0)),
+            ),
+            (
+                ProcessId("log".to_string()),
+                process(true, ClusterClassType::Log, Some(1024)),
+            ),
+            (
+                ProcessId("included".to_string()),
+                process(false, ClusterClassType::Storage, Some(1024)),
+            ),
+        ]);
+        let status = ClusterStatus {
+            processes,
+            ..Default::default()
+        };
+
+        assert_eq!(status.excluded_servers_with_data(), 1);
+    }
+
+    #[test]
+    fn fdb_version_from_protocol() {
+        let version = |protocol: &str| {
+            ClusterStatus {
+                protocol_version: Some(protocol.to_string()),
+                ..Default::default()
+            }
+            .fdb_version()
+        };
+        assert_eq!(version("fdb00b071010000").as_deref(), Some("7.1"));
+        assert_eq!(version("fdb00b073000000").as_deref(), Some("7.3"));
+        assert_eq!(version("0123456789"), None);
+        assert_eq!(version("not hexadecimal"), None);
+        assert_eq!(ClusterStatus::default().fdb_version(), None);
+    }
+
+    #[test]
+    fn cpu_cores_used_skips_missing_usage() {
+        let mut processes = HashMap::new();
+        for (id, usage_cores) in [("a", Some(0.5)), ("b", None), ("c", Some(1.25))] {
+            let process = ClusterProcess {
+                cpu: usage_cores.map(|usage_cores| ClusterProcessCpu { usage_cores }),
+                ..Default::default()
+            };
+            processes.insert(ProcessId(id.to_string()), process);
+        }
+        let status = ClusterStatus {
+            processes,
+            ..Default::default()
+        };
+        assert_eq!(status.cpu_cores_used(), Some(1.75));
+        assert_eq!(ClusterStatus::default().cpu_cores_used(), None);
+    }
 }
@@ -1,11 +1,11 @@
 use std::collections::HashMap;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Eq, PartialEq, PartialOrd, Hash)]
+#[derive(Deserialize, Serialize, Eq, PartialEq, PartialOrd, Hash)]
 pub struct BackupId(pub String);
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterBackup {
     pub paused: bool,
     pub total_workers: Option<i64>,
@@ -15,7 +15,7 @@ pub struct ClusterBackup {
     pub tags: HashMap<BackupId, ClusterBackupTag>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterBackupTag {
     pub last_restorable_seconds_behind: Option<f64>,
     pub last_restorable_version: Option<i64>,
@@ -25,7 +25,7 @@ pub struct ClusterBackupTag {
     pub mutation_log_bytes_written: i64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterBackupRecentIo {
     pub bytes_per_second: f64,
     pub bytes_sent: i64,
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// jq: .cluster.blob_granules, only reported from 7.2 when blob granules
+/// are enabled in the configuration
+#[derive(Deserialize, Serialize)]
+pub struct ClusterBlobGranules {
+    pub number_of_blob_workers: Option<i64>,
+    pub number_of_key_ranges: Option<i64>,
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// jq: .cluster.clients
+#[derive(Deserialize, Serialize)]
+pub struct ClusterClients {
+    pub count: Option<i64>,
+    #[serde(default)]
+    pub supported_versions: Vec<ClusterClientsVersion>,
+}
+
+/// jq: .cluster.clients.supported_versions[]
+#[derive(Deserialize, Serialize)]
+pub struct ClusterClientsVersion {
+    /// Human readable version of the client library, e.g. `7.1.61`
+    pub client_version: String,
+    pub protocol_version: Option<String>,
+    pub source_version: Option<String>,
+    /// Number of clients supporting this version
+    #[serde(default)]
+    pub count: i64,
+    /// Number of clients whose newest supported version is this one
+    #[serde(default)]
+    pub max_protocol_count: i64,
+}
+
+/// Number of clients of a client version, summed over the supported versions
+/// reporting it
+#[derive(Default, Debug, PartialEq)]
+pub struct ClientVersionCount {
+    pub count: i64,
+    pub max_protocol_count: i64,
+}
+
+impl ClusterClients {
+    /// Number of clients by client version. Several protocols may be reported
+    /// for the same client version and several client versions may share a
+    /// protocol, so counts are summed by client version only.
+    pub fn by_client_version(&self) -> BTreeMap<&str, ClientVersionCount> {
+        let mut versions: BTreeMap<&str, ClientVersionCount> = BTreeMap::new();
+        for supported in &self.supported_versions {
+            let version = versions
+                .entry(supported.client_version.as_str())
+                .or_default();
+            version.count += supported.count;
+            version.max_protocol_count += supported.max_protocol_count;
+        }
+        versions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_summed_by_client_version() {
+        let clients: ClusterClients = serde_json::from_value(serde_json::json!({
+            "count": 12,
+            "supported_versions": [
+                {
+                    "client_version": "7.1.37",
+                    "protocol_version": "fdb00b071010000",
+                    "count": 5,
+                    "max_protocol_count": 2
+                },
+                {
+                    "client_version": "7.1.61",
+                    "protocol_version": "fdb00b071010000",
+                    "count": 4,
+                    "max_protocol_count": 4
+                },
+                {
+                    "client_version": "7.1.61",
+                    "protocol_version": "fdb00b071010001",
+                    "count": 3
+                }
+            ]
+        }))
+        .unwrap();
+
+        let versions = clients.by_client_version();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            versions["7.1.37"],
+            ClientVersionCount {
+                count: 5,
+                max_protocol_count: 2
+            }
+        );
+        assert_eq!(
+            versions["7.1.61"],
+            ClientVersionCount {
+                count: 7,
+                max_protocol_count: 4
+            }
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+use super::cluster_process::ClusterClassType;
+
+/// jq: .cluster.configuration
+#[derive(Deserialize, Serialize)]
+pub struct ClusterConfiguration {
+    /// Number of coordinators the cluster is configured with
+    pub coordinators_count: Option<u32>,
+    /// e.g. single, double or triple
+    pub redundancy_mode: Option<String>,
+    /// Engine of new storage servers, e.g. ssd-2 or ssd-redwood-1
+    pub storage_engine: Option<String>,
+    /// Desired numbers of each role, absent when left to their defaults
+    pub logs: Option<i64>,
+    pub commit_proxies: Option<i64>,
+    pub grv_proxies: Option<i64>,
+    pub resolvers: Option<i64>,
+    /// Regions of a multi-region cluster, empty otherwise
+    #[serde(default)]
+    pub regions: Vec<ClusterConfigurationRegion>,
+}
+
+/// jq: .cluster.configuration.regions[]
+#[derive(Deserialize, Serialize)]
+pub struct ClusterConfigurationRegion {
+    #[serde(default)]
+    pub datacenters: Vec<ClusterConfigurationDatacenter>,
+    /// Number of logs recruited in the satellites of the region
+    pub satellite_logs: Option<i64>,
+    pub satellite_redundancy_mode: Option<String>,
+}
+
+/// jq: .cluster.configuration.regions[].datacenters[]
+#[derive(Deserialize, Serialize)]
+pub struct ClusterConfigurationDatacenter {
+    pub id: String,
+    /// The region of the datacenter with the highest priority is the primary
+    #[serde(default)]
+    pub priority: i64,
+    /// 1 for the satellites of the region
+    #[serde(default)]
+    pub satellite: i64,
+    /// Overrides the number of satellite logs of the region
+    pub satellite_logs: Option<i64>,
+}
+
+impl ClusterConfiguration {
+    /// Desired number of processes of each role explicitly configured,
+    /// negative values mean the default is used
+    pub fn desired_roles_count(&self) -> Vec<(ClusterClassType, i64)> {
+        [
+            (ClusterClassType::Log, self.logs),
+            (ClusterClassType::CommitProxy, self.commit_proxies),
+            (ClusterClassType::GrvProxy, self.grv_proxies),
+            (ClusterClassType::Resolver, self.resolvers),
+        ]
+        .into_iter()
+        .filter_map(|(role, desired)| Some((role, desired.filter(|desired| *desired >= 0)?)))
+        .collect()
+    }
+}
@@ -1,21 +1,37 @@
-use serde::Deserialize;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::lenient::deserialize_lenient;
+use super::unsigned::deserialize_option_u64;
 
 /// jq: .cluster.data
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct ClusterData {
-    pub average_partition_size_bytes: Option<i64>,
+    /// Shards are only described by their count and average size, the size
+    /// of each shard is read with [crate::fetch_shard_sizes]
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub average_partition_size_bytes: Option<u64>,
     pub least_operating_space_bytes_log_server: Option<i64>,
     pub least_operating_space_bytes_storage_server: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub moving_data: Option<ClusterDataMoving>,
-    pub partitions_count: Option<i64>,
-    pub total_disk_used_bytes: Option<i64>,
-    pub total_kv_size_bytes: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub partitions_count: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub total_disk_used_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub total_kv_size_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub state: Option<ClusterDataState>,
+    /// Absent from statuses of older versions
+    #[serde(default)]
+    pub team_trackers: Vec<ClusterDataTeamTracker>,
 }
 
 // jq: .cluster.data.state.name
-#[derive(Deserialize, Copy, Clone, Default)]
+#[derive(Deserialize, Serialize, Copy, Clone, Default)]
 pub enum ClusterDataStateName {
     #[serde(rename = "initializing")]
     Initializing,
@@ -42,8 +58,28 @@ pub enum ClusterDataStateName {
     Unknown,
 }
 
+impl fmt::Display for ClusterDataStateName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClusterDataStateName::Initializing => write!(f, "initializing"),
+            ClusterDataStateName::MissingData => write!(f, "missing_data"),
+            ClusterDataStateName::Healing => write!(f, "healing"),
+            ClusterDataStateName::OptimizingTeamCollections => {
+                write!(f, "optimizing_team_collections")
+            }
+            ClusterDataStateName::HealthyPopulatingRegion => write!(f, "healthy_populating_region"),
+            ClusterDataStateName::HealthyRepartitioning => write!(f, "healthy_repartitioning"),
+            ClusterDataStateName::HealthyRemovingServer => write!(f, "healthy_removing_server"),
+            ClusterDataStateName::HealthyRebalancing => write!(f, "healthy_rebalancing"),
+            ClusterDataStateName::Healthy => write!(f, "healthy"),
+            ClusterDataStateName::HealthyPerpetualWiggle => write!(f, "healthy_perpetual_wiggle"),
+            ClusterDataStateName::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
 /// jq: .cluster.data.state
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct ClusterDataState {
     pub healthy: Option<bool>,
@@ -54,11 +90,65 @@ pub struct ClusterDataState {
 }
 
 /// jq: .cluster.data.moving_data
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterDataMoving {
-    pub highest_priority: i64,
-    pub in_flight_bytes: i64,
-    pub in_queue_bytes: i64,
+    pub highest_priority: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub in_flight_bytes: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub in_queue_bytes: Option<u64>,
     // reset whenever data distributor is re-recruited
-    pub total_written_bytes: i64,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub total_written_bytes: Option<u64>,
+}
+
+/// jq: .cluster.data.team_trackers[]
+///
+/// There is one tracker per region, the status doesn't report the number of
+/// teams nor of unhealthy teams, only the state of the region as a whole
+#[derive(Deserialize, Serialize)]
+pub struct ClusterDataTeamTracker {
+    /// Whether the tracker is the one of the primary region
+    #[serde(default)]
+    pub primary: bool,
+    #[serde(default, deserialize_with = "deserialize_option_u64")]
+    pub in_flight_bytes: Option<u64>,
+    pub unhealthy_servers: Option<i64>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub state: Option<ClusterDataState>,
+}
+
+impl ClusterDataTeamTracker {
+    /// Region of the team tracker, `primary` or `remote`
+    pub fn region(&self) -> &'static str {
+        if self.primary {
+            "primary"
+        } else {
+            "remote"
+        }
+    }
+}
+
+/// Value of a `\xff\xff/metrics/data_distribution_stats/` special key, one
+/// per shard
+#[derive(Deserialize)]
+pub struct ShardStats {
+    pub shard_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_data_missing_total_written_bytes() {
+        let moving_data: ClusterDataMoving = serde_json::from_str(
+            r#"{"highest_priority": 0, "in_flight_bytes": 12, "in_queue_bytes": 34}"#,
+        )
+        .unwrap();
+        assert_eq!(moving_data.highest_priority, Some(0));
+        assert_eq!(moving_data.in_flight_bytes, Some(12));
+        assert_eq!(moving_data.in_queue_bytes, Some(34));
+        assert_eq!(moving_data.total_written_bytes, None);
+    }
 }
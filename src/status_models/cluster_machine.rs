@@ -1,10 +1,10 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Generally the host name, human readable name
-#[derive(Deserialize, Eq, PartialEq, Hash)]
+#[derive(Deserialize, Serialize, Eq, PartialEq, Hash)]
 pub struct MachineId(pub String);
 
-#[derive(Deserialize, Copy, Clone)]
+#[derive(Deserialize, Serialize, Copy, Clone)]
 pub struct Frequency {
     pub hz: f64,
 }
@@ -16,7 +16,7 @@ impl From<Frequency> for f64 {
 }
 
 /// jq: .cluster.machines[]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterMachine {
     pub machine_id: MachineId,
     pub address: String,
@@ -25,10 +25,18 @@ pub struct ClusterMachine {
     pub memory: ClusterMachineMemory,
     pub contributing_workers: u32,
     pub network: ClusterMachineNetwork,
+    pub cpu: Option<ClusterMachineCpu>,
+}
+
+/// jq: .cluster.machines[].cpu
+#[derive(Deserialize, Serialize)]
+pub struct ClusterMachineCpu {
+    /// Fraction of the logical cores of the machine in use, between 0 and 1
+    pub logical_core_utilization: f64,
 }
 
 /// jq: .cluster.machines[].memory
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterMachineMemory {
     pub free_bytes: i64,
     pub committed_bytes: i64,
@@ -36,7 +44,7 @@ pub struct ClusterMachineMemory {
 }
 
 /// jq: .cluster.machines[].network
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterMachineNetwork {
     pub megabits_sent: Frequency,
     pub megabits_received: Frequency,
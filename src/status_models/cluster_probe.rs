@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterLatencyProbe {
     pub commit_seconds: Option<f64>,
     pub immediate_priority_start_seconds: Option<f64>,
@@ -1,6 +1,6 @@
 use core::fmt;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::status_models::address::FdbProcessAddress;
 
@@ -9,13 +9,14 @@ use super::cluster_process_disk::ClusterProcessDisk;
 use super::cluster_process_memory::ClusterProcessMemory;
 use super::cluster_process_network::ClusterProcessNetwork;
 use super::cluster_process_role::ClusterProcessRole;
+use super::lenient::deserialize_lenient;
 
 /// A hash corresponding to the process
-#[derive(Deserialize, PartialEq, Eq, Hash)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Hash)]
 pub struct ProcessId(pub String);
 
 /// jq: .cluster.processes[]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcess {
     pub address: FdbProcessAddress,
     pub class_source: Option<ClusterClassSource>,
@@ -24,22 +25,49 @@ pub struct ClusterProcess {
     pub machine_id: Option<MachineId>,
     pub excluded: Option<bool>,
     pub fault_domain: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
+    pub locality: Option<ClusterProcessLocality>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub memory: Option<ClusterProcessMemory>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub network: Option<ClusterProcessNetwork>,
     pub run_loop_busy: Option<f64>,
     pub uptime_seconds: Option<f64>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub cpu: Option<ClusterProcessCpu>,
+    #[serde(default, deserialize_with = "deserialize_lenient")]
     pub disk: Option<ClusterProcessDisk>,
     pub roles: Vec<ClusterProcessRole>,
+    #[serde(default)]
+    pub messages: Vec<ClusterProcessMessage>,
+}
+
+/// jq: .cluster.processes[].messages[]
+#[derive(Deserialize, Serialize)]
+pub struct ClusterProcessMessage {
+    /// e.g. `file_open_error` or `io_timeout`
+    pub name: String,
+    /// Time the message was raised at, in seconds since the epoch
+    pub time: Option<f64>,
+}
+
+/// jq: .cluster.processes[].locality
+#[derive(Deserialize, Serialize)]
+pub struct ClusterProcessLocality {
+    /// Datacenter of the process, only set on multi-region clusters
+    pub dcid: Option<String>,
+    pub machineid: Option<String>,
+    pub processid: Option<String>,
+    pub zoneid: Option<String>,
 }
 
 /// jq: .cluster.processes[].cpu
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessCpu {
     pub usage_cores: f64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub enum ClusterClassSource {
     #[serde(rename = "command_line")]
     CommandLine,
@@ -49,7 +77,10 @@ pub enum ClusterClassSource {
     SetClass,
 }
 
-#[derive(Deserialize, Eq, Hash, PartialEq, Clone, Copy)]
+/// Class of a process or name of a role, classes added by a FoundationDB
+/// version the exporter doesn't know yet are parsed as
+/// [ClusterClassType::Unknown] instead of failing the parse
+#[derive(Deserialize, Serialize, Eq, Hash, PartialEq, Clone, Copy)]
 pub enum ClusterClassType {
     #[serde(rename = "unset")]
     Unset,
@@ -85,6 +116,40 @@ pub enum ClusterClassType {
     Coordinator,
     #[serde(rename = "resolver")]
     Resolver,
+    /// Class of FoundationDB 7.1 and older, replaced by the commit and GRV
+    /// proxy classes
+    #[serde(rename = "proxy")]
+    Proxy,
+    #[serde(rename = "router")]
+    Router,
+    #[serde(rename = "backup")]
+    Backup,
+    #[serde(rename = "fast_restore")]
+    FastRestore,
+    #[serde(rename = "blob_manager")]
+    BlobManager,
+    #[serde(rename = "blob_worker")]
+    BlobWorker,
+    #[serde(rename = "blob_migrator")]
+    BlobMigrator,
+    #[serde(rename = "encrypt_key_proxy")]
+    EncryptKeyProxy,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ClusterClassType {
+    /// Whether a single process of the cluster holds the role at a time
+    pub fn is_singleton(&self) -> bool {
+        matches!(
+            self,
+            ClusterClassType::Master
+                | ClusterClassType::ClusterController
+                | ClusterClassType::DataDistributor
+                | ClusterClassType::RateKeeper
+                | ClusterClassType::ConsistencyScan
+        )
+    }
 }
 
 impl fmt::Display for ClusterClassType {
@@ -107,6 +172,33 @@ impl fmt::Display for ClusterClassType {
             ClusterClassType::RateKeeper => write!(f, "rate_keeper"),
             ClusterClassType::Coordinator => write!(f, "coordinator"),
             ClusterClassType::Resolver => write!(f, "resolver"),
+            ClusterClassType::Proxy => write!(f, "proxy"),
+            ClusterClassType::Router => write!(f, "router"),
+            ClusterClassType::Backup => write!(f, "backup"),
+            ClusterClassType::FastRestore => write!(f, "fast_restore"),
+            ClusterClassType::BlobManager => write!(f, "blob_manager"),
+            ClusterClassType::BlobWorker => write!(f, "blob_worker"),
+            ClusterClassType::BlobMigrator => write!(f, "blob_migrator"),
+            ClusterClassType::EncryptKeyProxy => write!(f, "encrypt_key_proxy"),
+            ClusterClassType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterClassType;
+
+    #[test]
+    fn class_type_labels() {
+        let parse = |class: &str| {
+            serde_json::from_value::<ClusterClassType>(serde_json::json!(class)).unwrap()
+        };
+        for class in ["storage", "log", "commit_proxy", "proxy", "blob_worker"] {
+            assert_eq!(parse(class).to_string(), class);
         }
+        // Label kept from before the serde name was used
+        assert_eq!(parse("ratekeeper").to_string(), "rate_keeper");
+        assert!(parse("new_class") == ClusterClassType::Unknown);
     }
 }
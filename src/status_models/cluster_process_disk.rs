@@ -1,7 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// jq: .cluster.processes[].disk
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessDisk {
     pub busy: f64,
     pub free_bytes: i64,
@@ -11,7 +11,7 @@ pub struct ClusterProcessDisk {
 }
 
 // jq: .cluster.processes[].disk.{reads, writes}
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessDiskStat {
     pub counter: i64,
     pub hz: f64,
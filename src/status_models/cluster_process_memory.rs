@@ -1,7 +1,8 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// jq: .cluster.processes[].memory
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(test, derive(Default))]
 pub struct ClusterProcessMemory {
     pub available_bytes: Option<i64>,
     pub limit_bytes: Option<i64>,
@@ -9,3 +10,12 @@ pub struct ClusterProcessMemory {
     pub unused_allocated_memory: Option<i64>,
     pub used_bytes: Option<i64>,
 }
+
+impl ClusterProcessMemory {
+    /// Ratio of the memory used to the memory limit of the process, which
+    /// gets close to 1 before FoundationDB kills it for using too much memory
+    pub fn used_ratio(&self) -> Option<f64> {
+        let limit = self.limit_bytes.filter(|limit| *limit > 0)?;
+        Some(self.used_bytes? as f64 / limit as f64)
+    }
+}
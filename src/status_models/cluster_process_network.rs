@@ -1,8 +1,8 @@
 use super::cluster_machine::Frequency;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// jq: .cluster.processes[].network
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessNetwork {
     pub connection_errors: Frequency,
     pub connections_closed: Frequency,
@@ -10,5 +10,6 @@ pub struct ClusterProcessNetwork {
     pub current_connections: i64,
     pub megabits_received: Frequency,
     pub megabits_sent: Frequency,
-    pub tls_policy_failures: Frequency,
+    /// Absent from statuses of clusters not using TLS
+    pub tls_policy_failures: Option<Frequency>,
 }
@@ -1,12 +1,12 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::cluster_process::ClusterClassType;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, PartialEq)]
 pub struct RoleId(pub String);
 
 // jq: .cluster.processes[].roles[]
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct ClusterProcessRole {
     pub query_queue_max: Option<f64>,
@@ -53,16 +53,51 @@ pub struct ClusterProcessRole {
     pub read_latency_statistics: Option<LatencyStats>,
     pub commit_latency_statistics: Option<LatencyStats>,
     pub commit_batching_window_size: Option<LatencyStats>,
+
+    pub storage_metadata: Option<ClusterProcessRoleStorageMetadata>,
+}
+
+// jq: .cluster.processes[].roles[].storage_metadata
+#[derive(Deserialize, Serialize)]
+pub struct ClusterProcessRoleStorageMetadata {
+    /// Storage engine of the storage server (e.g. `ssd-redwood-1`), absent
+    /// from statuses of older versions
+    pub storage_engine: Option<String>,
+}
+
+impl ClusterProcessRole {
+    /// Storage engine of a storage role, `default` being the engine the
+    /// cluster is configured with, for statuses which don't report it per role
+    pub fn storage_engine<'a>(&'a self, default: Option<&'a str>) -> Option<&'a str> {
+        if self.role != Some(ClusterClassType::Storage) {
+            return None;
+        }
+        self.storage_metadata
+            .as_ref()
+            .and_then(|metadata| metadata.storage_engine.as_deref())
+            .or(default)
+    }
+
+    /// Bytes received by a log role which are not durable yet, clamped to 0
+    /// as both counters aren't read at the exact same time
+    pub fn log_unflushed_bytes(&self) -> Option<i64> {
+        if self.role != Some(ClusterClassType::Log) {
+            return None;
+        }
+        let input = self.input_bytes.as_ref()?.counter;
+        let durable = self.durable_bytes.as_ref()?.counter;
+        Some(input.saturating_sub(durable).max(0))
+    }
 }
 
 // jq: .cluster.processes[].roles[].grv_latency_statistics
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessRoleGrvLatency {
     pub default: Option<LatencyStats>,
     pub batch: Option<LatencyStats>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct LatencyStats {
     pub count: f64,
     pub min: f64,
@@ -77,13 +112,13 @@ pub struct LatencyStats {
     pub p99_9: f64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct DataLag {
     pub seconds: f64,
     pub versions: i64,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterProcessRoleFreq {
     pub counter: i64,
     pub hz: f64,
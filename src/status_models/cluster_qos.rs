@@ -1,9 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use super::{cluster_process::ProcessId, cluster_process_role::DataLag};
+use super::cluster_process_role::{DataLag, RoleId};
 
 /// jq: .cluster.qos
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct ClusterQos {
     pub worst_queue_bytes_log_server: i64,
@@ -24,10 +24,11 @@ pub struct ClusterQos {
     pub performance_limited_by: ClusterPerformanceLimit,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(test, derive(Default))]
 pub struct ClusterPerformanceLimit {
-    pub reason_server_id: Option<ProcessId>,
+    /// Id of the role limiting the cluster, e.g. a storage server
+    pub reason_server_id: Option<RoleId>,
     pub reason_id: i64,
     pub name: String,
     pub description: String,
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// jq: .cluster.recovery_state
+#[derive(Deserialize, Serialize)]
+pub struct ClusterRecoveryState {
+    pub name: ClusterRecoveryStateName,
+    pub description: Option<String>,
+    pub active_generations: Option<i64>,
+    pub seconds_since_last_recovered: Option<f64>,
+}
+
+/// jq: .cluster.recovery_state.name
+#[derive(Deserialize, Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClusterRecoveryStateName {
+    #[serde(rename = "reading_coordinated_state")]
+    ReadingCoordinatedState,
+    #[serde(rename = "locking_coordinated_state")]
+    LockingCoordinatedState,
+    #[serde(rename = "locking_old_transaction_servers")]
+    LockingOldTransactionServers,
+    #[serde(rename = "reading_transaction_system_state")]
+    ReadingTransactionSystemState,
+    #[serde(rename = "configuration_missing")]
+    ConfigurationMissing,
+    #[serde(rename = "configuration_never_created")]
+    ConfigurationNeverCreated,
+    #[serde(rename = "configuration_invalid")]
+    ConfigurationInvalid,
+    #[serde(rename = "recruiting_transaction_servers")]
+    RecruitingTransactionServers,
+    #[serde(rename = "initializing_transaction_servers")]
+    InitializingTransactionServers,
+    #[serde(rename = "recovery_transaction")]
+    RecoveryTransaction,
+    #[serde(rename = "writing_coordinated_state")]
+    WritingCoordinatedState,
+    #[serde(rename = "accepting_commits")]
+    AcceptingCommits,
+    #[serde(rename = "all_logs_recruited")]
+    AllLogsRecruited,
+    #[serde(rename = "storage_recovered")]
+    StorageRecovered,
+    #[serde(rename = "fully_recovered")]
+    FullyRecovered,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ClusterRecoveryStateName {
+    /// A recovery is considered in progress until the cluster accepts commits
+    pub fn in_progress(&self) -> bool {
+        !matches!(
+            self,
+            ClusterRecoveryStateName::FullyRecovered | ClusterRecoveryStateName::AcceptingCommits
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fully_recovered_not_in_progress() {
+        assert!(!ClusterRecoveryStateName::FullyRecovered.in_progress());
+        assert!(!ClusterRecoveryStateName::AcceptingCommits.in_progress());
+    }
+
+    #[test]
+    fn mid_recovery_in_progress() {
+        let state: ClusterRecoveryState = serde_json::from_str(
+            r#"{"name": "recruiting_transaction_servers", "description": "Recruiting new transaction servers."}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            state.name,
+            ClusterRecoveryStateName::RecruitingTransactionServers
+        );
+        assert!(state.name.in_progress());
+    }
+}
@@ -1,20 +1,19 @@
-use std::net::SocketAddrV4;
-
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+use super::address::FdbProcessAddress;
 use super::cluster_process::ProcessId;
 
 /// jq: .cluster.storage_wiggle
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterStorageWiggle {
     pub primary: Option<ClusterStoragePrimaryWiggle>,
 
-    pub wiggle_server_addresses: Vec<SocketAddrV4>,
+    pub wiggle_server_addresses: Vec<FdbProcessAddress>,
     pub wiggle_server_ids: Vec<ProcessId>,
 }
 
 /// jq: .cluster.storage_wiggle.primary
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct ClusterStoragePrimaryWiggle {
     pub finished_round: u16,
     pub finished_wiggle: u16,
@@ -27,3 +26,21 @@ pub struct ClusterStoragePrimaryWiggle {
     pub last_wiggle_finish_timestamp: f64,
     pub last_wiggle_start_timestamp: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_and_tls_wiggle_addresses() {
+        let wiggle: ClusterStorageWiggle = serde_json::from_str(
+            r#"{
+                "wiggle_server_addresses": ["10.0.0.1:4500", "[2001:db8::1]:4500:tls"],
+                "wiggle_server_ids": []
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(wiggle.wiggle_server_addresses.len(), 2);
+        assert!(wiggle.wiggle_server_addresses[1].tls);
+    }
+}
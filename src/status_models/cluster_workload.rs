@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// jq: .cluster.workload
+#[derive(Deserialize, Serialize)]
+pub struct ClusterWorkload {
+    pub transactions: Option<ClusterWorkloadTransactions>,
+    pub operations: Option<ClusterWorkloadOperations>,
+}
+
+/// jq: .cluster.workload.transactions
+#[derive(Deserialize, Serialize)]
+pub struct ClusterWorkloadTransactions {
+    pub started: Option<WorkloadFrequency>,
+    pub committed: Option<WorkloadFrequency>,
+    pub conflicted: Option<WorkloadFrequency>,
+}
+
+/// jq: .cluster.workload.operations
+#[derive(Deserialize, Serialize)]
+pub struct ClusterWorkloadOperations {
+    pub reads: Option<WorkloadFrequency>,
+    pub writes: Option<WorkloadFrequency>,
+}
+
+/// Rate of an event along with the number of times it happened since the
+/// processes reporting it started
+#[derive(Deserialize, Serialize, Copy, Clone)]
+pub struct WorkloadFrequency {
+    pub hz: f64,
+    pub counter: u64,
+}
@@ -0,0 +1,124 @@
+use std::{cell::Cell, collections::HashMap, hash::Hash};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use tracing::warn;
+
+thread_local! {
+    /// Whether malformed sub-objects are skipped instead of failing the parse
+    static LENIENT: Cell<bool> = const { Cell::new(false) };
+    /// Number of sub-objects skipped since the start of the current parse
+    static SKIPPED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Run `parse` on the current thread, with lenient deserialization enabled
+/// if `lenient` is set. Returns the result of `parse` along with the number
+/// of malformed sub-objects which were skipped.
+pub fn with_mode<T>(lenient: bool, parse: impl FnOnce() -> T) -> (T, usize) {
+    let previous = LENIENT.with(|l| l.replace(lenient));
+    SKIPPED.with(|s| s.set(0));
+    let output = parse();
+    LENIENT.with(|l| l.set(previous));
+    (output, SKIPPED.with(|s| s.replace(0)))
+}
+
+fn skip<T>(error: serde_json::Error) {
+    warn!(
+        "Skipping malformed {}: {}",
+        std::any::type_name::<T>(),
+        error
+    );
+    SKIPPED.with(|s| s.set(s.get() + 1));
+}
+
+/// Deserialize an optional sub-object, in lenient mode a malformed value is
+/// logged and replaced by `None`. Must be used along `#[serde(default)]`
+pub fn deserialize_lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    if !LENIENT.with(Cell::get) {
+        return Option::<T>::deserialize(deserializer);
+    }
+
+    let Some(value) = Option::<serde_json::Value>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    match serde_json::from_value(value) {
+        Ok(object) => Ok(Some(object)),
+        Err(e) => {
+            skip::<T>(e);
+            Ok(None)
+        }
+    }
+}
+
+/// Deserialize a map of sub-objects, in lenient mode malformed entries are
+/// logged and left out. Must be used along `#[serde(default)]`
+pub fn deserialize_lenient_map<'de, D, K, V>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Eq + Hash,
+    V: DeserializeOwned,
+{
+    if !LENIENT.with(Cell::get) {
+        return HashMap::<K, V>::deserialize(deserializer);
+    }
+
+    let raw = HashMap::<K, serde_json::Value>::deserialize(deserializer)?;
+    Ok(raw
+        .into_iter()
+        .filter_map(|(key, value)| match serde_json::from_value(value) {
+            Ok(object) => Some((key, object)),
+            Err(e) => {
+                skip::<V>(e);
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::with_mode;
+
+    #[derive(Deserialize)]
+    struct Inner {
+        #[allow(dead_code)]
+        value: i64,
+    }
+
+    #[derive(Deserialize)]
+    struct Outer {
+        #[serde(default, deserialize_with = "super::deserialize_lenient")]
+        single: Option<Inner>,
+        #[serde(default, deserialize_with = "super::deserialize_lenient_map")]
+        many: HashMap<String, Inner>,
+    }
+
+    const MALFORMED: &str = r#"{
+        "single": {"value": "not a number"},
+        "many": {"good": {"value": 1}, "bad": {}}
+    }"#;
+
+    #[test]
+    fn strict_mode_fails() {
+        let (outer, skipped) = with_mode(false, || serde_json::from_str::<Outer>(MALFORMED));
+        assert!(outer.is_err());
+        assert_eq!(skipped, 0);
+    }
+
+    #[test]
+    fn lenient_mode_skips_malformed_objects() {
+        let (outer, skipped) = with_mode(true, || serde_json::from_str::<Outer>(MALFORMED));
+        let outer = outer.unwrap();
+        assert!(outer.single.is_none());
+        assert_eq!(outer.many.len(), 1);
+        assert!(outer.many.contains_key("good"));
+        assert_eq!(skipped, 2);
+    }
+}
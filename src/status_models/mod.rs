@@ -1,9 +1,12 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub mod address;
 pub mod client;
 pub mod cluster;
 pub mod cluster_backup;
+pub mod cluster_blob_granules;
+pub mod cluster_clients;
+pub mod cluster_configuration;
 pub mod cluster_data;
 pub mod cluster_machine;
 pub mod cluster_probe;
@@ -13,10 +16,49 @@ pub mod cluster_process_memory;
 pub mod cluster_process_network;
 pub mod cluster_process_role;
 pub mod cluster_qos;
+pub mod cluster_recovery;
 pub mod cluster_wiggle;
+pub mod cluster_workload;
+pub mod lenient;
+pub mod unparsed;
+pub mod unsigned;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Status {
     pub client: client::ClientStatus,
     pub cluster: Option<cluster::ClusterStatus>,
+    /// Number of malformed sub-objects skipped by a lenient parsing
+    #[serde(skip)]
+    pub skipped_objects: usize,
+}
+
+impl Status {
+    /// Largest difference between the clocks reported in the status, which
+    /// are only the ones of the client and of the cluster controller: the
+    /// status has no per-process nor per-machine clock. It includes the time
+    /// the cluster controller took to build the status, at a 1s resolution.
+    pub fn max_clock_skew_seconds(&self) -> Option<i64> {
+        let client = self.client.timestamp?;
+        let cluster_controller = self.cluster.as_ref()?.cluster_controller_timestamp?;
+        Some((client - cluster_controller).abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetcher::parse_cluster_status;
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn max_clock_skew() {
+        let mut status = parse_cluster_status(SIMPLE_STATUS).unwrap();
+        assert_eq!(status.max_clock_skew_seconds(), Some(0));
+
+        status.client.timestamp = status.client.timestamp.map(|t| t - 3);
+        assert_eq!(status.max_clock_skew_seconds(), Some(3));
+
+        status.client.timestamp = None;
+        assert_eq!(status.max_clock_skew_seconds(), None);
+    }
 }
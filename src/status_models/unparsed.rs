@@ -0,0 +1,268 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+use serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeSeed, Deserializer, MapAccess, SeqAccess,
+    Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+use serde_json::Value;
+
+use super::Status;
+
+/// Paths of the keys of a raw status which are not captured by the [Status]
+/// model, in jq syntax (e.g. `.cluster.processes[].new_field`). Entries of
+/// arrays and maps share the same `[]` path, so each key is reported once.
+///
+/// Fails if the raw status is not valid JSON or can't be parsed as a [Status]
+pub fn unparsed_paths(raw: &[u8]) -> Result<BTreeSet<String>, serde_json::Error> {
+    let value: Value = serde_json::from_slice(raw)?;
+    let ignored = RefCell::new(BTreeSet::new());
+    Status::deserialize(Tracked {
+        value: &value,
+        path: String::new(),
+        ignored: &ignored,
+    })?;
+    Ok(ignored.into_inner())
+}
+
+/// Deserializer of a JSON value which records the path of every value the
+/// model skips
+struct Tracked<'de, 'b> {
+    value: &'de Value,
+    path: String,
+    ignored: &'b RefCell<BTreeSet<String>>,
+}
+
+impl<'de, 'b> Tracked<'de, 'b> {
+    fn entries(self, field_names: bool) -> Entries<'de, 'b> {
+        let Value::Object(map) = self.value else {
+            unreachable!("entries of a non object value");
+        };
+        Entries {
+            iter: map.iter(),
+            value: None,
+            path: self.path,
+            field_names,
+            ignored: self.ignored,
+        }
+    }
+}
+
+impl<'de, 'b> Deserializer<'de> for Tracked<'de, 'b> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Array(values) => visitor.visit_seq(Elements {
+                iter: values.iter(),
+                path: format!("{}[]", self.path),
+                ignored: self.ignored,
+            }),
+            Value::Object(_) => visitor.visit_map(self.entries(true)),
+            scalar => scalar.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Object(_) => visitor.visit_map(self.entries(true)),
+            other => other.deserialize_struct(name, fields, visitor),
+        }
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Value::Object(_) => visitor.visit_map(self.entries(false)),
+            other => other.deserialize_map(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.value.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.ignored.borrow_mut().insert(self.path);
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier
+    }
+}
+
+/// Elements of a JSON array, all sharing the same path
+struct Elements<'de, 'b> {
+    iter: std::slice::Iter<'de, Value>,
+    path: String,
+    ignored: &'b RefCell<BTreeSet<String>>,
+}
+
+impl<'de, 'b> SeqAccess<'de> for Elements<'de, 'b> {
+    type Error = serde_json::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.iter
+            .next()
+            .map(|value| {
+                seed.deserialize(Tracked {
+                    value,
+                    path: self.path.clone(),
+                    ignored: self.ignored,
+                })
+            })
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Entries of a JSON object, deserialized either as the fields of a struct
+/// (`.field` paths) or as the entries of a map (`[]` paths)
+struct Entries<'de, 'b> {
+    iter: serde_json::map::Iter<'de>,
+    value: Option<(&'de String, &'de Value)>,
+    path: String,
+    field_names: bool,
+    ignored: &'b RefCell<BTreeSet<String>>,
+}
+
+impl<'de, 'b> MapAccess<'de> for Entries<'de, 'b> {
+    type Error = serde_json::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.iter.next() else {
+            return Ok(None);
+        };
+        self.value = Some((key, value));
+        seed.deserialize(Key(key)).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value requested before its key"))?;
+        let path = if self.field_names {
+            format!("{}.{}", self.path, key)
+        } else {
+            format!("{}[]", self.path)
+        };
+        seed.deserialize(Tracked {
+            value,
+            path,
+            ignored: self.ignored,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// Key of a JSON object, which may be deserialized as a newtype (e.g. a
+/// [ProcessId](super::cluster_process::ProcessId))
+struct Key<'de>(&'de str);
+
+impl<'de> Deserializer<'de> for Key<'de> {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        BorrowedStrDeserializer::new(self.0).deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::unparsed_paths;
+
+    const SIMPLE_STATUS: &[u8] = include_bytes!("../../tests/fixtures/simple_fdb.json");
+
+    #[test]
+    fn reports_unknown_keys_once() {
+        let mut status: Value = serde_json::from_slice(SIMPLE_STATUS).unwrap();
+        status["new_top_level_key"] = json!([]);
+        status["client"]["new_client_key"] = json!(1);
+        for (index, process) in status["cluster"]["processes"]
+            .as_object_mut()
+            .unwrap()
+            .values_mut()
+            .enumerate()
+        {
+            process["new_process_key"] = json!({"nested": index});
+        }
+
+        let paths = unparsed_paths(&serde_json::to_vec(&status).unwrap()).unwrap();
+        assert!(paths.contains(".new_top_level_key"));
+        assert!(paths.contains(".client.new_client_key"));
+        assert!(paths.contains(".cluster.processes[].new_process_key"));
+        // Captured by the model
+        assert!(!paths.contains(".cluster.processes[].roles"));
+        assert!(!paths
+            .iter()
+            .any(|path| path.starts_with(".cluster.processes[].new_process_key.")));
+    }
+}
@@ -0,0 +1,69 @@
+use serde::{Deserialize, Deserializer};
+
+/// Any JSON number FoundationDB may report for a size or a count
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawNumber {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+}
+
+impl From<RawNumber> for u64 {
+    fn from(value: RawNumber) -> Self {
+        match value {
+            RawNumber::Unsigned(v) => v,
+            // Only negative values end up here, they are nonsensical and clamped to 0
+            RawNumber::Signed(v) => v.max(0) as u64,
+            RawNumber::Float(v) => v.max(0.0) as u64,
+        }
+    }
+}
+
+/// Deserialize a size or a count which may exceed [i64::MAX], negative
+/// values occasionally reported by FoundationDB are clamped to 0
+pub fn deserialize_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    RawNumber::deserialize(deserializer).map(u64::from)
+}
+
+/// Same as [deserialize_u64] for optional fields, must be used along
+/// `#[serde(default)]`
+pub fn deserialize_option_u64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<RawNumber>::deserialize(deserializer).map(|v| v.map(u64::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Sizes {
+        #[serde(deserialize_with = "super::deserialize_u64")]
+        size: u64,
+        #[serde(default, deserialize_with = "super::deserialize_option_u64")]
+        maybe_size: Option<u64>,
+    }
+
+    #[test]
+    fn above_i64_max() {
+        let sizes: Sizes = serde_json::from_str(
+            r#"{"size": 18446744073709551615, "maybe_size": 9223372036854775808}"#,
+        )
+        .unwrap();
+        assert_eq!(sizes.size, u64::MAX);
+        assert_eq!(sizes.maybe_size, Some(i64::MAX as u64 + 1));
+    }
+
+    #[test]
+    fn negative_clamped() {
+        let sizes: Sizes = serde_json::from_str(r#"{"size": -42}"#).unwrap();
+        assert_eq!(sizes.size, 0);
+        assert_eq!(sizes.maybe_size, None);
+    }
+}
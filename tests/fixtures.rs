@@ -0,0 +1,155 @@
+//! Statuses captured from FoundationDB clusters are kept in `tests/fixtures`,
+//! every one of them must parse and a few gauges computed from each are
+//! checked, so new metrics are tested against every supported version.
+
+use std::fs;
+use std::path::PathBuf;
+
+use fdbexporter::{parse_cluster_status, Exporter, MetricsOptions};
+use prometheus::proto::MetricFamily;
+
+/// Value of a gauge whose labels include `labels`
+struct Expected {
+    name: &'static str,
+    labels: &'static [(&'static str, &'static str)],
+    value: f64,
+}
+
+const fn gauge(
+    name: &'static str,
+    labels: &'static [(&'static str, &'static str)],
+    value: f64,
+) -> Expected {
+    Expected {
+        name,
+        labels,
+        value,
+    }
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("Can't read {}: {}", path.display(), e))
+}
+
+fn gathered(raw: &[u8]) -> Vec<MetricFamily> {
+    let status = parse_cluster_status(raw).unwrap_or_else(|e| panic!("{}", e));
+    let exporter = Exporter::new();
+    exporter.process_metrics(&status, &MetricsOptions::default());
+    exporter.gather()
+}
+
+fn gauge_value(families: &[MetricFamily], expected: &Expected) -> Option<f64> {
+    families
+        .iter()
+        .find(|family| family.get_name() == expected.name)?
+        .get_metric()
+        .iter()
+        .find(|metric| {
+            expected.labels.iter().all(|(name, value)| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == *name && label.get_value() == *value)
+            })
+        })
+        .map(|metric| metric.get_gauge().get_value())
+}
+
+fn check(name: &str, expected: &[Expected]) {
+    let families = gathered(&fixture(name));
+    for expected in expected {
+        assert_eq!(
+            gauge_value(&families, expected),
+            Some(expected.value),
+            "{} {:?} of {}",
+            expected.name,
+            expected.labels,
+            name
+        );
+    }
+}
+
+#[test]
+fn every_fixture_parses() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut count = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let families = gathered(&fs::read(&path).unwrap());
+            assert!(!families.is_empty(), "{}", path.display());
+            count += 1;
+        }
+    }
+    assert!(count >= 2);
+}
+
+#[test]
+fn fdb_7_1() {
+    check(
+        "simple_fdb.json",
+        &[
+            gauge(
+                "fdb_cluster_protocol_version_info",
+                &[("version", "7.1")],
+                1.0,
+            ),
+            gauge("fdb_cluster_machines_count", &[("cluster", "default")], 3.0),
+            gauge("fdb_cluster_coordinators_reporting", &[], 1.0),
+            gauge("fdb_cluster_full_replication", &[], 1.0),
+            gauge("fdb_cluster_moving_data_total_written_bytes", &[], 0.0),
+            gauge(
+                "fdb_cluster_clients_by_version",
+                &[("version", "7.1.37")],
+                117.0,
+            ),
+            gauge(
+                "fdb_cluster_process_role_storage_engine_info",
+                &[("engine", "memory-2")],
+                1.0,
+            ),
+        ],
+    );
+}
+
+/// Coordinators given by DNS name, one of them unreachable, must parse
+/// like IP ones
+#[test]
+fn fdb_7_3_with_dns_coordinators() {
+    check(
+        "dns_coordinators_fdb_7_3.json",
+        &[
+            gauge(
+                "fdb_cluster_protocol_version_info",
+                &[("version", "7.3")],
+                1.0,
+            ),
+            gauge(
+                "fdb_cluster_connection_string_info",
+                &[(
+                    "connection_string",
+                    "docker:docker@fdb-coordinator-0.fdb.svc.cluster.local:4500:tls,fdb-coordinator-1.fdb.svc.cluster.local:4500:tls,fdb-coordinator-2.fdb.svc.cluster.local:4500:tls",
+                )],
+                1.0,
+            ),
+            gauge("fdb_cluster_coordinators_configured", &[], 3.0),
+            gauge("fdb_cluster_coordinators_reporting", &[], 2.0),
+            gauge(
+                "fdb_cluster_clients_by_version",
+                &[("version", "7.3.43")],
+                117.0,
+            ),
+            gauge(
+                "fdb_cluster_process_role_storage_engine_info",
+                &[("engine", "ssd-redwood-1")],
+                1.0,
+            ),
+        ],
+    );
+}